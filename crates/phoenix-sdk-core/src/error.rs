@@ -0,0 +1,63 @@
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use thiserror::Error;
+
+pub type Result<T> = std::result::Result<T, PhoenixSdkError>;
+
+/// Errors returned by the Phoenix SDK.
+#[derive(Error, Debug)]
+pub enum PhoenixSdkError {
+    /// The market's metadata has not been loaded into the client.
+    #[error("Market {0} is not loaded, call add_market first")]
+    MarketNotLoaded(Pubkey),
+
+    /// The market account exists but does not describe a usable market.
+    #[error("Invalid market: {0}")]
+    InvalidMarket(String),
+
+    /// The market has no free seat and every seated trader has locked funds.
+    #[error("Market {0} is full and has no trader without locked funds to evict")]
+    MarketFull(Pubkey),
+
+    /// An RPC request failed. The underlying error is kept so callers can tell transient
+    /// failures (e.g. a `ClientError` from a timeout) from permanent ones.
+    #[error("RPC request failed: {0}")]
+    RpcError(#[source] anyhow::Error),
+
+    /// Account data, instruction data or an event log could not be decoded.
+    #[error("Failed to decode {0}")]
+    DecodeError(String),
+
+    /// The transaction was rejected, in simulation or on chain.
+    #[error("Transaction failed: {0}")]
+    TransactionFailed(String),
+
+    /// The transaction landed, but its events could not be fetched or decoded afterwards.
+    #[error("Transaction {signature} landed but its events could not be read: {source}")]
+    EventsUnavailable {
+        signature: Signature,
+        #[source]
+        source: Box<PhoenixSdkError>,
+    },
+
+    /// The transaction was rejected because an account could not cover it.
+    #[error("Insufficient funds: {0}")]
+    InsufficientFunds(String),
+
+    /// The list of known markets could not be fetched.
+    #[error("Failed to load the market list: {0}")]
+    MarketConfigError(String),
+
+    /// A `_sync` helper could not start its Tokio runtime.
+    #[error("Failed to start a Tokio runtime: {0}")]
+    RuntimeError(#[from] std::io::Error),
+}
+
+impl PhoenixSdkError {
+    pub fn rpc(err: impl Into<anyhow::Error>) -> Self {
+        PhoenixSdkError::RpcError(err.into())
+    }
+
+    pub fn decode(what: impl std::fmt::Display) -> Self {
+        PhoenixSdkError::DecodeError(what.to_string())
+    }
+}
@@ -0,0 +1,1010 @@
+//! Decodes Phoenix events from transaction data the caller already holds, without any RPC
+//! requests. Market metadata is read from the markets loaded into [`SDKClientCore`].
+
+use base64::Engine;
+use borsh::BorshSerialize;
+use phoenix::program::{
+    EvictEvent, ExpiredOrderEvent, FeeEvent, FillEvent, FillSummaryEvent, PhoenixInstruction,
+    PhoenixMarketEvent, PlaceEvent, ReduceEvent, TimeInForceEvent,
+};
+use phoenix::state::enums::Side;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, UiCompiledInstruction,
+    UiInnerInstructions, UiInstruction, UiLoadedAddresses, UiMessage, UiParsedInstruction,
+};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::error::{PhoenixSdkError, Result};
+use crate::market_event::{
+    Evict, Fill, FillSummary, MarketEventDetails, PhoenixEvent, Place, Reduce, TimeInForce,
+};
+use crate::sdk_client_core::{
+    phoenix_instruction_trader, MarketMetadata, RawPhoenixEvent, RawPhoenixHeader, SDKClientCore,
+};
+
+/// How instruction data passed to
+/// [`SDKClientCore::parse_phoenix_events_from_instruction_data`] is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionDataEncoding {
+    /// As in `UiCompiledInstruction::data` and JSON-RPC responses.
+    Base58,
+    Base64,
+}
+
+/// Something that was skipped or only partially decoded while parsing a transaction's events.
+/// The rest of the transaction's events are still returned.
+#[derive(Clone, Debug)]
+pub enum ParseWarning {
+    /// The events of a market whose metadata is not loaded were skipped.
+    MarketNotLoaded {
+        signature: Signature,
+        market: Pubkey,
+    },
+    /// The metadata of a market could not be fetched, so its events were skipped.
+    MarketMetadataUnavailable {
+        signature: Signature,
+        market: Pubkey,
+        error: String,
+    },
+    /// An event was not recognized and is returned as [`MarketEventDetails::Unknown`].
+    UnknownEvent {
+        signature: Signature,
+        market: Pubkey,
+        event_index: u64,
+    },
+}
+
+impl ParseWarning {
+    /// A short label for the kind of warning, e.g. for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseWarning::MarketNotLoaded { .. } => "market_not_loaded",
+            ParseWarning::MarketMetadataUnavailable { .. } => "market_metadata_unavailable",
+            ParseWarning::UnknownEvent { .. } => "unknown_event",
+        }
+    }
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseWarning::MarketNotLoaded { signature, market } => write!(
+                f,
+                "skipped the events of {} in {}: market is not loaded",
+                market, signature
+            ),
+            ParseWarning::MarketMetadataUnavailable {
+                signature,
+                market,
+                error,
+            } => write!(
+                f,
+                "skipped the events of {} in {}: failed to fetch market metadata: {}",
+                market, signature, error
+            ),
+            ParseWarning::UnknownEvent {
+                signature,
+                market,
+                event_index,
+            } => write!(
+                f,
+                "unknown event {} of {} in {}",
+                event_index, market, signature
+            ),
+        }
+    }
+}
+
+/// The events decoded from a transaction, with warnings about what had to be skipped.
+#[derive(Clone, Debug, Default)]
+pub struct DecodedEvents {
+    pub events: Vec<PhoenixEvent>,
+    pub warnings: Vec<ParseWarning>,
+    /// Headers of the batches that logged no events, e.g. of deposits, withdrawals and seat
+    /// requests. They still take a sequence number of their market, see
+    /// [`OrderbookReplayer::advance`](crate::orderbook_replay::OrderbookReplayer::advance).
+    pub empty_batches: Vec<RawPhoenixHeader>,
+}
+
+/// Converts raw events into [`PhoenixEvent`]s, using the metadata `markets` returns to convert
+/// lots to atoms. The events of markets it has no metadata for are skipped with a warning.
+pub fn phoenix_events_from_raw(
+    sig: &Signature,
+    raw_phoenix_events: Vec<RawPhoenixEvent>,
+    markets: impl Fn(&Pubkey) -> Option<MarketMetadata>,
+) -> DecodedEvents {
+    let mut trade_direction = None;
+    let mut market_events = vec![];
+    let mut warnings = vec![];
+    let mut empty_batches = vec![];
+    for raw_phoenix_event in raw_phoenix_events {
+        let header = raw_phoenix_event.header;
+        let Some(meta) = markets(&header.market) else {
+            warnings.push(ParseWarning::MarketNotLoaded {
+                signature: *sig,
+                market: header.market,
+            });
+            continue;
+        };
+        if raw_phoenix_event.batch.is_empty() && raw_phoenix_event.undecoded.is_empty() {
+            empty_batches.push(header);
+            continue;
+        }
+        let unknown_event = |event_index: u64, bytes: Vec<u8>| PhoenixEvent {
+            market: header.market,
+            sequence_number: header.sequence_number,
+            slot: header.slot,
+            timestamp: header.timestamp,
+            signature: *sig,
+            signer: header.signer,
+            trader: header.trader,
+            event_index,
+            details: MarketEventDetails::Unknown(bytes),
+        };
+        let num_events = raw_phoenix_event.batch.len() as u64;
+
+        for (position, phoenix_event) in raw_phoenix_event.batch.into_iter().enumerate() {
+            match phoenix_event {
+                PhoenixMarketEvent::Fill(FillEvent {
+                    index,
+                    maker_id,
+                    order_sequence_number,
+                    price_in_ticks,
+                    base_lots_filled,
+                    base_lots_remaining,
+                }) => {
+                    let side_filled = Side::from_order_sequence_number(order_sequence_number);
+                    market_events.push(PhoenixEvent {
+                        market: header.market,
+                        sequence_number: header.sequence_number,
+                        slot: header.slot,
+                        timestamp: header.timestamp,
+                        signature: *sig,
+                        signer: header.signer,
+                        trader: header.trader,
+                        event_index: index as u64,
+                        details: MarketEventDetails::Fill(Fill {
+                            order_sequence_number,
+                            maker: maker_id,
+                            taker: header.trader,
+                            price_in_ticks,
+                            base_lots_filled,
+                            base_lots_remaining,
+                            side_filled: Side::from_order_sequence_number(order_sequence_number),
+                            is_full_fill: base_lots_remaining == 0,
+                        }),
+                    });
+                    if trade_direction.is_none() {
+                        trade_direction = match side_filled {
+                            Side::Bid => Some(-1),
+                            Side::Ask => Some(1),
+                        }
+                    }
+                }
+                PhoenixMarketEvent::Reduce(ReduceEvent {
+                    index,
+                    order_sequence_number,
+                    price_in_ticks,
+                    base_lots_removed,
+                    base_lots_remaining,
+                }) => market_events.push(PhoenixEvent {
+                    market: header.market,
+                    sequence_number: header.sequence_number,
+                    slot: header.slot,
+                    timestamp: header.timestamp,
+                    signature: *sig,
+                    signer: header.signer,
+                    trader: header.trader,
+                    event_index: index as u64,
+                    details: MarketEventDetails::Reduce(Reduce {
+                        order_sequence_number,
+                        maker: header.trader,
+                        price_in_ticks,
+                        base_lots_removed,
+                        base_lots_remaining,
+                        is_full_cancel: base_lots_remaining == 0,
+                    }),
+                }),
+
+                PhoenixMarketEvent::Place(PlaceEvent {
+                    index,
+                    order_sequence_number,
+                    client_order_id,
+                    price_in_ticks,
+                    base_lots_placed,
+                }) => market_events.push(PhoenixEvent {
+                    market: header.market,
+                    sequence_number: header.sequence_number,
+                    slot: header.slot,
+                    timestamp: header.timestamp,
+                    signature: *sig,
+                    signer: header.signer,
+                    trader: header.trader,
+                    event_index: index as u64,
+                    details: MarketEventDetails::Place(Place {
+                        order_sequence_number,
+                        client_order_id,
+                        maker: header.trader,
+                        price_in_ticks,
+                        base_lots_placed,
+                    }),
+                }),
+                PhoenixMarketEvent::Evict(EvictEvent {
+                    index,
+                    maker_id,
+                    order_sequence_number,
+                    price_in_ticks,
+                    base_lots_evicted,
+                }) => market_events.push(PhoenixEvent {
+                    market: header.market,
+                    sequence_number: header.sequence_number,
+                    slot: header.slot,
+                    timestamp: header.timestamp,
+                    signature: *sig,
+                    signer: header.signer,
+                    trader: header.trader,
+                    event_index: index as u64,
+                    details: MarketEventDetails::Evict(Evict {
+                        order_sequence_number,
+                        maker: maker_id,
+                        price_in_ticks,
+                        base_lots_evicted,
+                    }),
+                }),
+                PhoenixMarketEvent::FillSummary(FillSummaryEvent {
+                    index,
+                    client_order_id,
+                    total_base_lots_filled,
+                    total_quote_lots_filled,
+                    total_fee_in_quote_lots,
+                }) => market_events.push(PhoenixEvent {
+                    market: header.market,
+                    sequence_number: header.sequence_number,
+                    slot: header.slot,
+                    timestamp: header.timestamp,
+                    signature: *sig,
+                    signer: header.signer,
+                    trader: header.trader,
+                    event_index: index as u64,
+                    details: MarketEventDetails::FillSummary(FillSummary {
+                        client_order_id,
+                        total_base_filled: total_base_lots_filled * meta.base_atoms_per_base_lot,
+                        total_quote_filled_including_fees: total_quote_lots_filled
+                            * meta.quote_atoms_per_quote_lot,
+                        total_quote_fees: total_fee_in_quote_lots * meta.quote_atoms_per_quote_lot,
+                        trade_direction: trade_direction.unwrap_or(0),
+                    }),
+                }),
+                PhoenixMarketEvent::Fee(FeeEvent {
+                    index,
+                    fees_collected_in_quote_lots,
+                }) => market_events.push(PhoenixEvent {
+                    market: header.market,
+                    sequence_number: header.sequence_number,
+                    slot: header.slot,
+                    timestamp: header.timestamp,
+                    signature: *sig,
+                    signer: header.signer,
+                    trader: header.trader,
+                    event_index: index as u64,
+                    details: MarketEventDetails::Fee(
+                        fees_collected_in_quote_lots * meta.quote_atoms_per_quote_lot,
+                    ),
+                }),
+                PhoenixMarketEvent::TimeInForce(TimeInForceEvent {
+                    index,
+                    order_sequence_number,
+                    last_valid_slot,
+                    last_valid_unix_timestamp_in_seconds,
+                }) => market_events.push(PhoenixEvent {
+                    market: header.market,
+                    sequence_number: header.sequence_number,
+                    slot: header.slot,
+                    timestamp: header.timestamp,
+                    signature: *sig,
+                    signer: header.signer,
+                    trader: header.trader,
+                    event_index: index as u64,
+                    details: MarketEventDetails::TimeInForce(TimeInForce {
+                        order_sequence_number,
+                        last_valid_slot,
+                        last_valid_unix_timestamp_in_seconds,
+                    }),
+                }),
+                PhoenixMarketEvent::ExpiredOrder(ExpiredOrderEvent {
+                    index,
+                    maker_id,
+                    order_sequence_number,
+                    price_in_ticks,
+                    base_lots_removed,
+                }) => market_events.push(PhoenixEvent {
+                    market: header.market,
+                    sequence_number: header.sequence_number,
+                    slot: header.slot,
+                    timestamp: header.timestamp,
+                    signature: *sig,
+                    signer: header.signer,
+                    trader: header.trader,
+                    event_index: index as u64,
+                    details: MarketEventDetails::Reduce(Reduce {
+                        order_sequence_number,
+                        maker: maker_id,
+                        price_in_ticks,
+                        base_lots_removed,
+                        base_lots_remaining: 0,
+                        is_full_cancel: true,
+                    }),
+                }),
+                // Events without an index of their own are identified by their position
+                _ => {
+                    warnings.push(ParseWarning::UnknownEvent {
+                        signature: *sig,
+                        market: header.market,
+                        event_index: position as u64,
+                    });
+                    market_events.push(unknown_event(
+                        position as u64,
+                        phoenix_event.try_to_vec().unwrap_or_default(),
+                    ));
+                }
+            }
+        }
+        if !raw_phoenix_event.undecoded.is_empty() {
+            warnings.push(ParseWarning::UnknownEvent {
+                signature: *sig,
+                market: header.market,
+                event_index: num_events,
+            });
+            market_events.push(unknown_event(num_events, raw_phoenix_event.undecoded));
+        }
+    }
+    DecodedEvents {
+        events: market_events,
+        warnings,
+        empty_batches,
+    }
+}
+
+impl SDKClientCore {
+    /// Parses the Phoenix events out of a transaction as returned by `getTransaction` or a
+    /// Geyser plugin. Both JSON and binary transaction encodings are supported, but not
+    /// `accounts`, which carries no instructions.
+    ///
+    /// Failed transactions emit no events.
+    pub fn parse_phoenix_events_from_encoded_transaction(
+        &self,
+        tx: &EncodedTransactionWithStatusMeta,
+    ) -> Result<DecodedEvents> {
+        let meta = tx
+            .meta
+            .as_ref()
+            .ok_or_else(|| PhoenixSdkError::decode("transaction: status meta is missing"))?;
+        if meta.err.is_some() {
+            return Ok(DecodedEvents::default());
+        }
+        let message = decode_message(tx)?;
+        let inner_instructions: Option<&Vec<UiInnerInstructions>> =
+            meta.inner_instructions.as_ref().into();
+        self.parse_phoenix_events_from_inner_instructions(
+            &message.signature,
+            &message.instructions,
+            inner_instructions.map(Vec::as_slice).unwrap_or_default(),
+            &message.account_keys,
+        )
+    }
+
+    /// Parses the Phoenix events out of a transaction's inner instructions. `account_keys` are
+    /// the transaction's static account keys followed by any addresses loaded from lookup
+    /// tables, which compiled instructions index into.
+    ///
+    /// Events are attributed to the trader of the Phoenix instruction that emitted them, read
+    /// from the top-level `instructions` when Phoenix is invoked directly, or from the inner
+    /// instructions when another program invokes it.
+    pub fn parse_phoenix_events_from_inner_instructions(
+        &self,
+        signature: &Signature,
+        instructions: &[UiInstruction],
+        inner_instructions: &[UiInnerInstructions],
+        account_keys: &[Pubkey],
+    ) -> Result<DecodedEvents> {
+        let mut event_list = vec![];
+        for inner_ixs in inner_instructions {
+            let mut trader = match instructions.get(inner_ixs.index as usize) {
+                Some(ix) => match phoenix_instruction(signature, ix, account_keys)? {
+                    Some((data, accounts)) => phoenix_instruction_trader(&data, &accounts),
+                    None => None,
+                },
+                None => None,
+            };
+            for inner_ix in inner_ixs.instructions.iter() {
+                let Some((data, accounts)) =
+                    phoenix_instruction(signature, inner_ix, account_keys)?
+                else {
+                    continue;
+                };
+                if let Some(log) = log_data(&data) {
+                    event_list.push((log.to_vec(), trader));
+                } else if let Some(ix_trader) = phoenix_instruction_trader(&data, &accounts) {
+                    trader = Some(ix_trader);
+                }
+            }
+        }
+        let raw_phoenix_events =
+            self.parse_raw_phoenix_events_with_traders(signature, event_list)?;
+        Ok(phoenix_events_from_raw(
+            signature,
+            raw_phoenix_events,
+            |market| self.markets.get(market).copied(),
+        ))
+    }
+
+    /// Parses the Phoenix events out of the data of the Phoenix instructions of the transaction
+    /// with `signature`, in order. Instructions other than the `Log` instructions Phoenix emits
+    /// events through are skipped.
+    ///
+    /// Instruction data alone does not say which trader an instruction acted for, so events
+    /// are attributed to their signer.
+    pub fn parse_phoenix_events_from_instruction_data(
+        &self,
+        signature: &Signature,
+        instruction_data: &[&str],
+        encoding: InstructionDataEncoding,
+    ) -> Result<DecodedEvents> {
+        let mut event_list = vec![];
+        for data in instruction_data {
+            let data = match encoding {
+                InstructionDataEncoding::Base58 => decode_base58(data)?,
+                InstructionDataEncoding::Base64 => base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map_err(|e| PhoenixSdkError::decode(format!("instruction data: {}", e)))?,
+            };
+            if let Some(log) = log_data(&data) {
+                event_list.push(log.to_vec());
+            }
+        }
+        let raw_phoenix_events = self.parse_raw_phoenix_events(signature, event_list)?;
+        Ok(phoenix_events_from_raw(
+            signature,
+            raw_phoenix_events,
+            |market| self.markets.get(market).copied(),
+        ))
+    }
+}
+
+/// The parts of an encoded transaction's message needed to decode it.
+pub(crate) struct DecodedMessage {
+    pub signature: Signature,
+    /// The static account keys followed by any addresses loaded from lookup tables, which
+    /// compiled instructions index into.
+    pub account_keys: Vec<Pubkey>,
+    pub signers: Vec<Pubkey>,
+    pub instructions: Vec<UiInstruction>,
+}
+
+/// Reads the message of a transaction in any JSON or binary encoding. The `accounts` encoding
+/// carries no instructions and is rejected.
+pub(crate) fn decode_message(tx: &EncodedTransactionWithStatusMeta) -> Result<DecodedMessage> {
+    let (signature, mut account_keys, num_signers, instructions, loads_addresses) =
+        match &tx.transaction {
+            EncodedTransaction::Json(ui_transaction) => {
+                let signature = ui_transaction
+                    .signatures
+                    .first()
+                    .and_then(|signature| Signature::from_str(signature).ok())
+                    .ok_or_else(|| PhoenixSdkError::decode("transaction: invalid signature"))?;
+                match &ui_transaction.message {
+                    // Parsed messages already list the addresses loaded from lookup tables
+                    UiMessage::Parsed(message) => (
+                        signature,
+                        parse_pubkeys(message.account_keys.iter().map(|key| &key.pubkey))?,
+                        message.account_keys.iter().filter(|key| key.signer).count(),
+                        message.instructions.clone(),
+                        false,
+                    ),
+                    UiMessage::Raw(message) => (
+                        signature,
+                        parse_pubkeys(message.account_keys.iter())?,
+                        message.header.num_required_signatures as usize,
+                        message
+                            .instructions
+                            .iter()
+                            .cloned()
+                            .map(UiInstruction::Compiled)
+                            .collect(),
+                        true,
+                    ),
+                }
+            }
+            EncodedTransaction::Accounts(_) => {
+                return Err(PhoenixSdkError::decode(
+                    "transaction: `accounts` encoding has no instructions",
+                ))
+            }
+            encoded => {
+                let transaction = encoded
+                    .decode()
+                    .ok_or_else(|| PhoenixSdkError::decode("transaction: invalid encoding"))?;
+                let signature = transaction
+                    .signatures
+                    .first()
+                    .ok_or_else(|| PhoenixSdkError::decode("transaction: no signature"))?;
+                let instructions = transaction
+                    .message
+                    .instructions()
+                    .iter()
+                    .map(|ix| {
+                        UiInstruction::Compiled(UiCompiledInstruction {
+                            program_id_index: ix.program_id_index,
+                            accounts: ix.accounts.clone(),
+                            data: bs58::encode(&ix.data).into_string(),
+                            stack_height: None,
+                        })
+                    })
+                    .collect();
+                (
+                    *signature,
+                    transaction.message.static_account_keys().to_vec(),
+                    transaction.message.header().num_required_signatures as usize,
+                    instructions,
+                    true,
+                )
+            }
+        };
+    // Signers always come first
+    let signers = account_keys.iter().take(num_signers).copied().collect();
+    let loaded_addresses: Option<&UiLoadedAddresses> = tx
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.loaded_addresses.as_ref().into());
+    if let (true, Some(loaded_addresses)) = (loads_addresses, loaded_addresses) {
+        account_keys.extend(parse_pubkeys(loaded_addresses.writable.iter())?);
+        account_keys.extend(parse_pubkeys(loaded_addresses.readonly.iter())?);
+    }
+    Ok(DecodedMessage {
+        signature,
+        account_keys,
+        signers,
+        instructions,
+    })
+}
+
+/// Returns the data and accounts of `ix` if it is a Phoenix instruction.
+fn phoenix_instruction(
+    signature: &Signature,
+    ix: &UiInstruction,
+    account_keys: &[Pubkey],
+) -> Result<Option<(Vec<u8>, Vec<Pubkey>)>> {
+    let account = |index: u8| {
+        account_keys.get(index as usize).copied().ok_or_else(|| {
+            PhoenixSdkError::decode(format!(
+                "instruction of {}: account index {} is out of bounds",
+                signature, index
+            ))
+        })
+    };
+    match ix {
+        UiInstruction::Compiled(ix) => {
+            if account(ix.program_id_index)? != phoenix::id() {
+                return Ok(None);
+            }
+            let accounts = ix
+                .accounts
+                .iter()
+                .map(|index| account(*index))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Some((decode_base58(&ix.data)?, accounts)))
+        }
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => {
+            if parse_pubkey(&ix.program_id)? != phoenix::id() {
+                return Ok(None);
+            }
+            Ok(Some((
+                decode_base58(&ix.data)?,
+                parse_pubkeys(ix.accounts.iter())?,
+            )))
+        }
+        // Only programs with a JSON parser are fully parsed, which Phoenix is not
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => Ok(None),
+    }
+}
+
+/// Returns the event log carried by the data of a Phoenix `Log` instruction.
+fn log_data(data: &[u8]) -> Option<&[u8]> {
+    let (tag, log) = data.split_first()?;
+    matches!(
+        PhoenixInstruction::try_from(*tag),
+        Ok(PhoenixInstruction::Log)
+    )
+    .then_some(log)
+}
+
+fn decode_base58(data: &str) -> Result<Vec<u8>> {
+    bs58::decode(data)
+        .into_vec()
+        .map_err(|e| PhoenixSdkError::decode(format!("instruction data: {}", e)))
+}
+
+fn parse_pubkey(key: &str) -> Result<Pubkey> {
+    Pubkey::from_str(key).map_err(|e| PhoenixSdkError::decode(format!("pubkey {}: {}", key, e)))
+}
+
+fn parse_pubkeys<'a>(keys: impl Iterator<Item = &'a String>) -> Result<Vec<Pubkey>> {
+    keys.map(|key| parse_pubkey(key)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use phoenix::program::events::AuditLogHeader;
+    use phoenix::program::MarketSizeParams;
+    use phoenix::quantities::{BaseLots, Ticks, WrapperU64};
+    use solana_sdk::instruction::{
+        AccountMeta, CompiledInstruction, Instruction, InstructionError,
+    };
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::{Transaction, TransactionError, VersionedTransaction};
+    use solana_transaction_status::{
+        InnerInstruction, InnerInstructions, TransactionStatusMeta, TransactionWithStatusMeta,
+        UiTransactionEncoding, VersionedTransactionWithStatusMeta,
+    };
+    use std::collections::BTreeMap;
+
+    fn client(market: Pubkey) -> SDKClientCore {
+        let metadata = MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_atoms_per_raw_base_unit: 1_000_000_000,
+            quote_atoms_per_quote_unit: 1_000_000,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1_000,
+            num_base_lots_per_base_unit: 1_000,
+            raw_base_units_per_base_unit: 1,
+            market_size_params: MarketSizeParams {
+                bids_size: 4096,
+                asks_size: 4096,
+                num_seats: 8193,
+            },
+        };
+        SDKClientCore {
+            markets: BTreeMap::from([(market, metadata)]),
+            trader: Pubkey::new_unique(),
+        }
+    }
+
+    /// The data of a Phoenix `Log` instruction carrying one fill.
+    fn fill_log_data(market: Pubkey, signer: Pubkey, maker: Pubkey) -> Vec<u8> {
+        let mut data = vec![PhoenixInstruction::Log as u8];
+        data.extend(
+            PhoenixMarketEvent::Header(AuditLogHeader {
+                instruction: PhoenixInstruction::Swap as u8,
+                sequence_number: 11,
+                timestamp: 1_700_000_000,
+                slot: 5,
+                market,
+                signer,
+                total_events: 1,
+            })
+            .try_to_vec()
+            .unwrap(),
+        );
+        data.extend(
+            PhoenixMarketEvent::Fill(FillEvent {
+                index: 0,
+                maker_id: maker,
+                order_sequence_number: 42,
+                price_in_ticks: Ticks::new(25_000),
+                base_lots_filled: BaseLots::new(3),
+                base_lots_remaining: BaseLots::new(0),
+            })
+            .try_to_vec()
+            .unwrap(),
+        );
+        data
+    }
+
+    fn fill_transaction(
+        market: Pubkey,
+        maker: Pubkey,
+        err: Option<TransactionError>,
+    ) -> (TransactionWithStatusMeta, Keypair) {
+        let taker = Keypair::new();
+        let instruction = Instruction::new_with_bytes(phoenix::id(), &[], vec![]);
+        let message = Message::new(&[instruction], Some(&taker.pubkey()));
+        let transaction =
+            VersionedTransaction::from(Transaction::new(&[&taker], message, Default::default()));
+        let meta = TransactionStatusMeta {
+            status: err.map_or(Ok(()), Err),
+            inner_instructions: Some(vec![InnerInstructions {
+                index: 0,
+                instructions: vec![InnerInstruction {
+                    // The Phoenix program is the second account key, after the fee payer
+                    instruction: CompiledInstruction::new_from_raw_parts(
+                        1,
+                        fill_log_data(market, taker.pubkey(), maker),
+                        vec![],
+                    ),
+                    stack_height: Some(2),
+                }],
+            }]),
+            ..TransactionStatusMeta::default()
+        };
+        (
+            TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+                transaction,
+                meta,
+            }),
+            taker,
+        )
+    }
+
+    #[test]
+    fn test_parse_events_from_encoded_transaction() {
+        let market = Pubkey::new_unique();
+        let maker = Pubkey::new_unique();
+        let (tx, taker) = fill_transaction(market, maker, None);
+        let client = client(market);
+
+        for encoding in [
+            UiTransactionEncoding::Json,
+            UiTransactionEncoding::Base58,
+            UiTransactionEncoding::Base64,
+        ] {
+            let encoded = tx.clone().encode(encoding, Some(0), false).unwrap();
+            let events = client
+                .parse_phoenix_events_from_encoded_transaction(&encoded)
+                .unwrap()
+                .events;
+            assert_eq!(events.len(), 1, "{:?}", encoding);
+            let event = &events[0];
+            assert_eq!(event.market, market);
+            assert_eq!(event.sequence_number, 11);
+            assert_eq!(event.signer, taker.pubkey());
+            // Without trader accounts in the instruction, the signer is the trader
+            assert_eq!(event.trader, taker.pubkey());
+            let MarketEventDetails::Fill(fill) = event.details else {
+                panic!("expected a fill, got {:?}", event.details);
+            };
+            assert_eq!(fill.maker, maker);
+            assert_eq!(fill.price_in_ticks, 25_000);
+            assert_eq!(fill.base_lots_filled, 3);
+            assert!(fill.is_full_fill);
+        }
+    }
+
+    #[test]
+    fn test_routed_events_are_attributed_to_the_trader() {
+        let market = Pubkey::new_unique();
+        let maker = Pubkey::new_unique();
+        let wallet = Keypair::new();
+        let trader = Pubkey::new_unique();
+        let aggregator = Pubkey::new_unique();
+        // The aggregator invokes Phoenix for `trader`, so the signing wallet only routes
+        let accounts = [phoenix::id(), Pubkey::new_unique(), market, trader];
+        let instruction = Instruction::new_with_bytes(
+            aggregator,
+            &[],
+            accounts
+                .iter()
+                .map(|key| AccountMeta::new_readonly(*key, false))
+                .collect(),
+        );
+        let message = Message::new(&[instruction], Some(&wallet.pubkey()));
+        let index = |key: &Pubkey| {
+            message
+                .account_keys
+                .iter()
+                .position(|account| account == key)
+                .unwrap() as u8
+        };
+        let phoenix_index = index(&phoenix::id());
+        let swap = CompiledInstruction::new_from_raw_parts(
+            phoenix_index,
+            vec![PhoenixInstruction::Swap as u8],
+            accounts.iter().map(index).collect(),
+        );
+        let log = CompiledInstruction::new_from_raw_parts(
+            phoenix_index,
+            fill_log_data(market, wallet.pubkey(), maker),
+            vec![],
+        );
+        let meta = TransactionStatusMeta {
+            inner_instructions: Some(vec![InnerInstructions {
+                index: 0,
+                instructions: [swap, log]
+                    .into_iter()
+                    .map(|instruction| InnerInstruction {
+                        instruction,
+                        stack_height: Some(2),
+                    })
+                    .collect(),
+            }]),
+            ..TransactionStatusMeta::default()
+        };
+        let transaction =
+            VersionedTransaction::from(Transaction::new(&[&wallet], message, Default::default()));
+        let tx = TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+            transaction,
+            meta,
+        });
+
+        let encoded = tx
+            .encode(UiTransactionEncoding::Json, Some(0), false)
+            .unwrap();
+        let events = client(market)
+            .parse_phoenix_events_from_encoded_transaction(&encoded)
+            .unwrap()
+            .events;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].signer, wallet.pubkey());
+        assert_eq!(events[0].trader, trader);
+        let MarketEventDetails::Fill(fill) = events[0].details else {
+            panic!("expected a fill, got {:?}", events[0].details);
+        };
+        assert_eq!(fill.taker, trader);
+        assert_eq!(fill.maker, maker);
+    }
+
+    #[test]
+    fn test_failed_transactions_have_no_events() {
+        let market = Pubkey::new_unique();
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        let (tx, _) = fill_transaction(market, Pubkey::new_unique(), Some(err));
+        let encoded = tx
+            .encode(UiTransactionEncoding::Json, Some(0), false)
+            .unwrap();
+
+        let events = client(market)
+            .parse_phoenix_events_from_encoded_transaction(&encoded)
+            .unwrap()
+            .events;
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_parse_events_from_instruction_data() {
+        let market = Pubkey::new_unique();
+        let signer = Pubkey::new_unique();
+        let data = fill_log_data(market, signer, Pubkey::new_unique());
+        let signature = Signature::new_unique();
+        let client = client(market);
+
+        let base58_data = bs58::encode(&data).into_string();
+        let base64_data = base64::engine::general_purpose::STANDARD.encode(&data);
+        // Phoenix instructions other than `Log` are skipped
+        let swap = bs58::encode([PhoenixInstruction::Swap as u8]).into_string();
+        let from_base58 = client
+            .parse_phoenix_events_from_instruction_data(
+                &signature,
+                &[&swap, &base58_data],
+                InstructionDataEncoding::Base58,
+            )
+            .unwrap()
+            .events;
+        let from_base64 = client
+            .parse_phoenix_events_from_instruction_data(
+                &signature,
+                &[&base64_data],
+                InstructionDataEncoding::Base64,
+            )
+            .unwrap()
+            .events;
+
+        for events in [from_base58, from_base64] {
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].signature, signature);
+            assert_eq!(events[0].signer, signer);
+        }
+    }
+
+    #[test]
+    fn test_header_only_logs_are_returned_as_empty_batches() {
+        let market = Pubkey::new_unique();
+        let mut data = vec![PhoenixInstruction::Log as u8];
+        data.extend(
+            PhoenixMarketEvent::Header(AuditLogHeader {
+                instruction: PhoenixInstruction::DepositFunds as u8,
+                sequence_number: 12,
+                timestamp: 1_700_000_000,
+                slot: 5,
+                market,
+                signer: Pubkey::new_unique(),
+                total_events: 0,
+            })
+            .try_to_vec()
+            .unwrap(),
+        );
+        let decoded = client(market)
+            .parse_phoenix_events_from_instruction_data(
+                &Signature::new_unique(),
+                &[&bs58::encode(&data).into_string()],
+                InstructionDataEncoding::Base58,
+            )
+            .unwrap();
+
+        assert!(decoded.events.is_empty());
+        assert!(decoded.warnings.is_empty());
+        assert_eq!(decoded.empty_batches.len(), 1);
+        assert_eq!(decoded.empty_batches[0].market, market);
+        assert_eq!(decoded.empty_batches[0].sequence_number, 12);
+        assert_eq!(
+            decoded.empty_batches[0].instruction,
+            PhoenixInstruction::DepositFunds as u8
+        );
+    }
+
+    #[test]
+    fn test_unknown_markets_are_skipped_with_a_warning() {
+        let market = Pubkey::new_unique();
+        let unknown_market = Pubkey::new_unique();
+        let known = bs58::encode(fill_log_data(
+            market,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ))
+        .into_string();
+        let unknown = bs58::encode(fill_log_data(
+            unknown_market,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ))
+        .into_string();
+        let decoded = client(market)
+            .parse_phoenix_events_from_instruction_data(
+                &Signature::new_unique(),
+                &[&unknown, &known],
+                InstructionDataEncoding::Base58,
+            )
+            .unwrap();
+
+        assert_eq!(decoded.events.len(), 1);
+        assert_eq!(decoded.events[0].market, market);
+        assert_eq!(decoded.warnings.len(), 1);
+        assert!(matches!(
+            decoded.warnings[0],
+            ParseWarning::MarketNotLoaded { market, .. } if market == unknown_market
+        ));
+    }
+
+    #[test]
+    fn test_undecodable_events_are_returned_as_unknown() {
+        let market = Pubkey::new_unique();
+        let mut data = fill_log_data(market, Pubkey::new_unique(), Pubkey::new_unique());
+        // Claim a second event in the header's trailing `total_events` (a u16 after the
+        // instruction tag), with a variant this SDK does not know
+        let total_events = 1 + 90;
+        data[total_events..total_events + 2].copy_from_slice(&2u16.to_le_bytes());
+        data.extend([u8::MAX, 1, 2, 3]);
+        let decoded = client(market)
+            .parse_phoenix_events_from_instruction_data(
+                &Signature::new_unique(),
+                &[&bs58::encode(data).into_string()],
+                InstructionDataEncoding::Base58,
+            )
+            .unwrap();
+
+        assert_eq!(decoded.events.len(), 2);
+        assert!(matches!(
+            decoded.events[0].details,
+            MarketEventDetails::Fill(..)
+        ));
+        let MarketEventDetails::Unknown(bytes) = &decoded.events[1].details else {
+            panic!(
+                "expected an unknown event, got {:?}",
+                decoded.events[1].details
+            );
+        };
+        assert_eq!(bytes, &vec![u8::MAX, 1, 2, 3]);
+        assert_eq!(decoded.events[1].event_index, 1);
+        assert!(matches!(
+            decoded.warnings[..],
+            [ParseWarning::UnknownEvent { event_index: 1, .. }]
+        ));
+    }
+}
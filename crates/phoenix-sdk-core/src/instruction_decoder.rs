@@ -0,0 +1,422 @@
+//! Decodes the Phoenix instructions of a transaction into what was asked of the market, as
+//! opposed to the events, which describe what happened.
+
+use phoenix::program::new_order::{CondensedOrder, MultipleOrderPacket};
+use phoenix::program::PhoenixInstruction;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::{SelfTradeBehavior, Side};
+use phoenix::state::OrderPacket;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::error::Result;
+use crate::packet_decoder::{decode_multiple_order_packet, decode_order_packet};
+use crate::sdk_client_core::phoenix_instruction_trader;
+
+/// Position of the market in the accounts of every Phoenix instruction but `Log`.
+const MARKET_ACCOUNT_INDEX: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum OrderType {
+    PostOnly,
+    Limit,
+    ImmediateOrCancel,
+}
+
+/// An order an instruction asked Phoenix to place, decoded from its `OrderPacket`, or from one
+/// of the orders of its `MultipleOrderPacket`.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OrderIntent {
+    pub order_type: OrderType,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side"))]
+    pub side: Side,
+    /// The limit price in quote ticks per base unit. `None` for immediate-or-cancel orders
+    /// without a limit, i.e. market orders.
+    pub price_in_ticks: Option<u64>,
+    /// The size of the order in base lots. Zero for immediate-or-cancel orders sized in quote
+    /// lots.
+    pub num_base_lots: u64,
+    /// The size of an immediate-or-cancel order in quote lots, zero for other orders.
+    pub num_quote_lots: u64,
+    /// The base lots an immediate-or-cancel order must fill for the instruction to succeed.
+    pub min_base_lots_to_fill: u64,
+    /// The quote lots an immediate-or-cancel order must fill for the instruction to succeed.
+    pub min_quote_lots_to_fill: u64,
+    /// What happens when the order would match the trader's own orders. `None` for post-only
+    /// orders, which never match.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_utils::self_trade_behavior")
+    )]
+    pub self_trade_behavior: Option<SelfTradeBehavior>,
+    /// The most orders the order may match against.
+    pub match_limit: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
+    pub client_order_id: u128,
+    /// Whether the order may only use funds already deposited in the market.
+    pub use_only_deposited_funds: bool,
+    /// The order expires after this slot.
+    pub last_valid_slot: Option<u64>,
+    /// The order expires after this unix timestamp.
+    pub last_valid_unix_timestamp_in_seconds: Option<u64>,
+}
+
+/// A Phoenix instruction of a transaction, other than the `Log` instructions events are
+/// emitted through.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DecodedInstruction {
+    /// Index of the top-level instruction: the Phoenix instruction itself, or the instruction
+    /// of the program that invoked Phoenix.
+    pub instruction_index: usize,
+    /// Position among the inner instructions of `instruction_index`, when Phoenix was invoked
+    /// by another program.
+    pub inner_instruction_index: Option<usize>,
+    /// The instruction tag, as in `PhoenixInstruction`.
+    pub instruction: u8,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::optional_string"))]
+    pub market: Option<Pubkey>,
+    /// The trader the instruction acted for. `None` for market authority instructions.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::optional_string"))]
+    pub trader: Option<Pubkey>,
+    /// The orders placed: the order of swap and limit order instructions, and every bid, then
+    /// every ask, of multiple post-only order instructions. Empty for other instructions.
+    pub orders: Vec<OrderIntent>,
+}
+
+impl OrderIntent {
+    pub fn from_order_packet(order_packet: &OrderPacket) -> Self {
+        match *order_packet {
+            OrderPacket::PostOnly {
+                side,
+                price_in_ticks,
+                num_base_lots,
+                client_order_id,
+                use_only_deposited_funds,
+                last_valid_slot,
+                last_valid_unix_timestamp_in_seconds,
+                ..
+            } => OrderIntent {
+                order_type: OrderType::PostOnly,
+                side,
+                price_in_ticks: Some(price_in_ticks.as_u64()),
+                num_base_lots: num_base_lots.as_u64(),
+                num_quote_lots: 0,
+                min_base_lots_to_fill: 0,
+                min_quote_lots_to_fill: 0,
+                self_trade_behavior: None,
+                match_limit: None,
+                client_order_id,
+                use_only_deposited_funds,
+                last_valid_slot,
+                last_valid_unix_timestamp_in_seconds,
+            },
+            OrderPacket::Limit {
+                side,
+                price_in_ticks,
+                num_base_lots,
+                self_trade_behavior,
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+                last_valid_slot,
+                last_valid_unix_timestamp_in_seconds,
+                ..
+            } => OrderIntent {
+                order_type: OrderType::Limit,
+                side,
+                price_in_ticks: Some(price_in_ticks.as_u64()),
+                num_base_lots: num_base_lots.as_u64(),
+                num_quote_lots: 0,
+                min_base_lots_to_fill: 0,
+                min_quote_lots_to_fill: 0,
+                self_trade_behavior: Some(self_trade_behavior),
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+                last_valid_slot,
+                last_valid_unix_timestamp_in_seconds,
+            },
+            OrderPacket::ImmediateOrCancel {
+                side,
+                price_in_ticks,
+                num_base_lots,
+                num_quote_lots,
+                min_base_lots_to_fill,
+                min_quote_lots_to_fill,
+                self_trade_behavior,
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+                last_valid_slot,
+                last_valid_unix_timestamp_in_seconds,
+                ..
+            } => OrderIntent {
+                order_type: OrderType::ImmediateOrCancel,
+                side,
+                price_in_ticks: price_in_ticks.map(|price| price.as_u64()),
+                num_base_lots: num_base_lots.as_u64(),
+                num_quote_lots: num_quote_lots.as_u64(),
+                min_base_lots_to_fill: min_base_lots_to_fill.as_u64(),
+                min_quote_lots_to_fill: min_quote_lots_to_fill.as_u64(),
+                self_trade_behavior: Some(self_trade_behavior),
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+                last_valid_slot,
+                last_valid_unix_timestamp_in_seconds,
+            },
+        }
+    }
+}
+
+impl OrderIntent {
+    /// The post-only orders of a `MultipleOrderPacket`, bids first. They share the packet's
+    /// client order ID.
+    pub fn from_multiple_order_packet(
+        packet: &MultipleOrderPacket,
+        use_only_deposited_funds: bool,
+    ) -> Vec<Self> {
+        let intent = |side, order: &CondensedOrder| OrderIntent {
+            order_type: OrderType::PostOnly,
+            side,
+            price_in_ticks: Some(order.price_in_ticks),
+            num_base_lots: order.size_in_base_lots,
+            num_quote_lots: 0,
+            min_base_lots_to_fill: 0,
+            min_quote_lots_to_fill: 0,
+            self_trade_behavior: None,
+            match_limit: None,
+            client_order_id: packet.client_order_id.unwrap_or_default(),
+            use_only_deposited_funds,
+            last_valid_slot: order.last_valid_slot,
+            last_valid_unix_timestamp_in_seconds: order.last_valid_unix_timestamp_in_seconds,
+        };
+        packet
+            .bids
+            .iter()
+            .map(|order| intent(Side::Bid, order))
+            .chain(packet.asks.iter().map(|order| intent(Side::Ask, order)))
+            .collect()
+    }
+}
+
+/// Decodes a Phoenix instruction from its data and accounts. `None` for `Log` instructions and
+/// data that is not a Phoenix instruction.
+pub fn decode_phoenix_instruction(
+    data: &[u8],
+    accounts: &[Pubkey],
+    instruction_index: usize,
+    inner_instruction_index: Option<usize>,
+) -> Result<Option<DecodedInstruction>> {
+    let Some((tag, payload)) = data.split_first() else {
+        return Ok(None);
+    };
+    let Ok(ix) = PhoenixInstruction::try_from(*tag) else {
+        return Ok(None);
+    };
+    let orders = match ix {
+        PhoenixInstruction::Log => return Ok(None),
+        PhoenixInstruction::Swap
+        | PhoenixInstruction::SwapWithFreeFunds
+        | PhoenixInstruction::PlaceLimitOrder
+        | PhoenixInstruction::PlaceLimitOrderWithFreeFunds => {
+            vec![OrderIntent::from_order_packet(&decode_order_packet(
+                payload,
+            )?)]
+        }
+        PhoenixInstruction::PlaceMultiplePostOnlyOrders => {
+            OrderIntent::from_multiple_order_packet(&decode_multiple_order_packet(payload)?, false)
+        }
+        PhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds => {
+            OrderIntent::from_multiple_order_packet(&decode_multiple_order_packet(payload)?, true)
+        }
+        _ => vec![],
+    };
+    Ok(Some(DecodedInstruction {
+        instruction_index,
+        inner_instruction_index,
+        instruction: *tag,
+        market: accounts.get(MARKET_ACCOUNT_INDEX).copied(),
+        trader: phoenix_instruction_trader(data, accounts),
+        orders,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+
+    fn instruction_data(ix: PhoenixInstruction, order_packet: &OrderPacket) -> Vec<u8> {
+        let mut data = vec![ix as u8];
+        data.extend(order_packet.try_to_vec().unwrap());
+        data
+    }
+
+    #[test]
+    fn test_decode_order_intents() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let accounts = [phoenix::id(), Pubkey::new_unique(), market, trader];
+
+        let ioc = OrderPacket::new_ioc(
+            Side::Bid,
+            None,
+            0,
+            500,
+            0,
+            400,
+            SelfTradeBehavior::CancelProvide,
+            Some(4),
+            7,
+            false,
+            Some(1_000),
+            None,
+        );
+        let swap = decode_phoenix_instruction(
+            &instruction_data(PhoenixInstruction::Swap, &ioc),
+            &accounts,
+            1,
+            Some(0),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(swap.instruction, PhoenixInstruction::Swap as u8);
+        assert_eq!(swap.market, Some(market));
+        assert_eq!(swap.trader, Some(trader));
+        assert_eq!(swap.orders.len(), 1);
+        let order = swap.orders[0];
+        assert_eq!(order.order_type, OrderType::ImmediateOrCancel);
+        assert_eq!(order.side, Side::Bid);
+        assert_eq!(order.price_in_ticks, None);
+        assert_eq!(order.num_quote_lots, 500);
+        assert_eq!(order.min_quote_lots_to_fill, 400);
+        assert_eq!(order.match_limit, Some(4));
+        assert_eq!(order.client_order_id, 7);
+        assert_eq!(order.last_valid_slot, Some(1_000));
+
+        let post_only = OrderPacket::new_post_only_default(Side::Ask, 10_000, 10);
+        let place = decode_phoenix_instruction(
+            &instruction_data(PhoenixInstruction::PlaceLimitOrder, &post_only),
+            &accounts,
+            0,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        let order = place.orders[0];
+        assert_eq!(order.order_type, OrderType::PostOnly);
+        assert_eq!(order.price_in_ticks, Some(10_000));
+        assert_eq!(order.num_base_lots, 10);
+        assert!(order.self_trade_behavior.is_none());
+    }
+
+    #[test]
+    fn test_decode_multiple_post_only_orders() {
+        let market = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let accounts = [phoenix::id(), Pubkey::new_unique(), market, trader];
+        let packet = MultipleOrderPacket::new(
+            vec![
+                CondensedOrder::new_default(9_990, 5),
+                CondensedOrder::new_default(9_980, 10),
+            ],
+            vec![CondensedOrder {
+                price_in_ticks: 10_010,
+                size_in_base_lots: 7,
+                last_valid_slot: Some(1_000),
+                last_valid_unix_timestamp_in_seconds: None,
+            }],
+            Some(42),
+            false,
+        );
+
+        for (ix, use_only_deposited_funds) in [
+            (PhoenixInstruction::PlaceMultiplePostOnlyOrders, false),
+            (
+                PhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds,
+                true,
+            ),
+        ] {
+            let mut data = vec![ix as u8];
+            data.extend(packet.try_to_vec().unwrap());
+            let quote = decode_phoenix_instruction(&data, &accounts, 0, None)
+                .unwrap()
+                .unwrap();
+            assert_eq!(quote.market, Some(market));
+            assert_eq!(quote.trader, Some(trader));
+
+            let orders = quote.orders;
+            assert_eq!(orders.len(), 3);
+            assert!(orders
+                .iter()
+                .all(|order| order.order_type == OrderType::PostOnly
+                    && order.client_order_id == 42
+                    && order.use_only_deposited_funds == use_only_deposited_funds));
+            assert_eq!(
+                orders
+                    .iter()
+                    .map(|order| (order.side, order.price_in_ticks, order.num_base_lots))
+                    .collect::<Vec<_>>(),
+                vec![
+                    (Side::Bid, Some(9_990), 5),
+                    (Side::Bid, Some(9_980), 10),
+                    (Side::Ask, Some(10_010), 7),
+                ]
+            );
+            assert_eq!(orders[2].last_valid_slot, Some(1_000));
+        }
+    }
+
+    #[test]
+    fn test_log_and_cancel_instructions() {
+        let accounts = [phoenix::id(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let log = decode_phoenix_instruction(&[PhoenixInstruction::Log as u8], &accounts, 0, None);
+        assert!(log.unwrap().is_none());
+
+        let cancel = decode_phoenix_instruction(
+            &[PhoenixInstruction::CancelAllOrders as u8],
+            &accounts,
+            0,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        assert!(cancel.orders.is_empty());
+        assert_eq!(cancel.market, Some(accounts[2]));
+        // The trader account is missing
+        assert_eq!(cancel.trader, None);
+    }
+
+    #[test]
+    fn test_authority_instructions_act_for_their_target_trader() {
+        // Phoenix program, log authority, market, market authority, trader, seat
+        let accounts = (0..6).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        for ix in [
+            PhoenixInstruction::ForceCancelOrders,
+            PhoenixInstruction::EvictSeat,
+        ] {
+            let decoded = decode_phoenix_instruction(&[ix as u8], &accounts, 0, None)
+                .unwrap()
+                .unwrap();
+            assert_eq!(decoded.market, Some(accounts[2]));
+            assert_eq!(decoded.trader, Some(accounts[4]));
+        }
+
+        // Other market authority instructions have no trader
+        let change_fee_recipient = decode_phoenix_instruction(
+            &[PhoenixInstruction::ChangeFeeRecipient as u8],
+            &accounts,
+            0,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(change_fee_recipient.trader, None);
+    }
+}
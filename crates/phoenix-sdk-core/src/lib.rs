@@ -1,7 +1,14 @@
 pub mod ata_utils;
+pub mod error;
+pub mod event_decoder;
+pub mod instruction_decoder;
 pub mod market_event;
 pub mod orderbook;
+pub mod orderbook_replay;
 pub mod packet_decoder;
 pub mod sdk_client_core;
+#[cfg(feature = "serde")]
+mod serde_utils;
 #[cfg(test)]
 pub mod test_unit_conversion;
+pub mod transaction_context;
@@ -1,14 +1,19 @@
 use phoenix::state::enums::Side;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Fill {
     /// The sequence number of the order that was filled.
     pub order_sequence_number: u64,
     /// The pubkey of the maker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub maker: Pubkey,
     /// The pubkey of the taker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub taker: Pubkey,
     /// The quote ticks per base unit of the order.
     pub price_in_ticks: u64,
@@ -17,14 +22,17 @@ pub struct Fill {
     /// The number of lots that remain in the order.
     pub base_lots_remaining: u64,
     /// The side of the order that was filled.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side"))]
     pub side_filled: Side,
     /// Whether the order was fully filled.
     pub is_full_fill: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PhoenixEvent {
     /// The pubkey of the market the trade occurred in
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub market: Pubkey,
     /// The sequence number of the trade event.
     pub sequence_number: u64,
@@ -33,9 +41,15 @@ pub struct PhoenixEvent {
     /// The timestamp of the trade event.
     pub timestamp: i64,
     /// The signature of the transaction that contains this event.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub signature: Signature,
     /// The signer of the transaction that contains this event.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub signer: Pubkey,
+    /// The trader the Phoenix instruction acted for, which fills and cancels are attributed
+    /// to. Differs from `signer` when an aggregator or crank program signs.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
+    pub trader: Pubkey,
     /// The index of the trade in the list of trade_events.
     pub event_index: u64,
     /// Details of the event that are specific to the event type.
@@ -43,10 +57,12 @@ pub struct PhoenixEvent {
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Reduce {
     /// The sequence number of the order that was reduced.
     pub order_sequence_number: u64,
     /// The pubkey of the maker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub maker: Pubkey,
     /// The quote ticks per base unit of the order.
     pub price_in_ticks: u64,
@@ -59,10 +75,12 @@ pub struct Reduce {
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Evict {
     /// The sequence number of the order that was evicted.
     pub order_sequence_number: u64,
     /// The pubkey of the maker whose order was evicted.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub maker: Pubkey,
     /// The price of the order, in quote ticks per base unit
     pub price_in_ticks: u64,
@@ -71,12 +89,15 @@ pub struct Evict {
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Place {
     /// The sequence number of the order that was placed.
     pub order_sequence_number: u64,
     /// The client_order_id of the order that was placed.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub client_order_id: u128,
     /// The pubkey of the maker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub maker: Pubkey,
     /// The quote ticks per base unit of the order.
     pub price_in_ticks: u64,
@@ -85,8 +106,10 @@ pub struct Place {
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FillSummary {
     /// The client_order_id of the order that was filled.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::string"))]
     pub client_order_id: u128,
     /// The total base quantity that was filled.
     pub total_base_filled: u64,
@@ -99,13 +122,19 @@ pub struct FillSummary {
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TimeInForce {
     pub order_sequence_number: u64,
     pub last_valid_slot: u64,
     pub last_valid_unix_timestamp_in_seconds: u64,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// With the `serde` feature, serialized tagged by event type, e.g. `{"fill": {...}}` or
+/// `{"fee": 10}`. The tag is the variant itself rather than a field, which formats without
+/// self-description such as bincode cannot read.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum MarketEventDetails {
     Fill(Fill),
     Place(Place),
@@ -114,4 +143,79 @@ pub enum MarketEventDetails {
     FillSummary(FillSummary),
     Fee(u64),
     TimeInForce(TimeInForce),
+    /// An event this SDK does not decode, as the Borsh bytes it was logged as. Bytes that
+    /// could not be read as events at all are returned whole, as one `Unknown` event.
+    Unknown(#[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::base64"))] Vec<u8>),
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    fn place_event() -> PhoenixEvent {
+        PhoenixEvent {
+            market: Pubkey::new_unique(),
+            sequence_number: 7,
+            slot: 100,
+            timestamp: 1_700_000_000,
+            signature: Signature::new_unique(),
+            signer: Pubkey::new_unique(),
+            trader: Pubkey::new_unique(),
+            event_index: 2,
+            details: MarketEventDetails::Place(Place {
+                order_sequence_number: 42,
+                client_order_id: u128::MAX,
+                maker: Pubkey::new_unique(),
+                price_in_ticks: 25_000,
+                base_lots_placed: 3,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_json_uses_base58_string_ids_and_tagged_details() {
+        let event = place_event();
+        let json = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(json["market"], event.market.to_string());
+        assert_eq!(json["signature"], event.signature.to_string());
+        assert_eq!(
+            json["details"]["place"]["client_order_id"],
+            u128::MAX.to_string()
+        );
+
+        let decoded: PhoenixEvent = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.signature, event.signature);
+        let MarketEventDetails::Place(place) = decoded.details else {
+            panic!("expected a place, got {:?}", decoded.details);
+        };
+        assert_eq!(place.client_order_id, u128::MAX);
+    }
+
+    #[test]
+    fn test_bincode_round_trip() {
+        let fill = MarketEventDetails::Fill(Fill {
+            order_sequence_number: 42,
+            maker: Pubkey::new_unique(),
+            taker: Pubkey::new_unique(),
+            price_in_ticks: 25_000,
+            base_lots_filled: 3,
+            base_lots_remaining: 0,
+            side_filled: Side::Ask,
+            is_full_fill: true,
+        });
+        for details in [
+            fill,
+            MarketEventDetails::Fee(10),
+            MarketEventDetails::Unknown(vec![0, 1, 2]),
+        ] {
+            let event = PhoenixEvent {
+                details,
+                ..place_event()
+            };
+            let bytes = bincode::serialize(&event).unwrap();
+            let decoded: PhoenixEvent = bincode::deserialize(&bytes).unwrap();
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", event));
+        }
+    }
 }
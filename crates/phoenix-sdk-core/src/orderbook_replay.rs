@@ -0,0 +1,571 @@
+//! Rebuilds the L3 orderbook of a market over time by replaying its events onto a snapshot.
+
+use std::collections::BTreeMap;
+
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+use crate::event_decoder::DecodedEvents;
+use crate::market_event::{Evict, Fill, MarketEventDetails, PhoenixEvent, Place, Reduce};
+use crate::orderbook::Orderbook;
+use crate::sdk_client_core::PhoenixOrder;
+
+pub type L3Orderbook = Orderbook<FIFOOrderId, PhoenixOrder>;
+
+/// The L3 orderbook of a market as read from its account.
+#[derive(Debug, Clone)]
+pub struct OrderbookSnapshot {
+    pub market: Pubkey,
+    pub orderbook: L3Orderbook,
+    /// Slot the market account was read at.
+    pub slot: u64,
+    /// `MarketHeader::market_sequence_number` when it was read: the sequence number of the next
+    /// batch of events.
+    pub sequence_number: u64,
+}
+
+/// Why an event could not be applied. The book is left as it was before the event.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The event belongs to another market.
+    #[error("Event of market {found} replayed onto the book of {expected}")]
+    WrongMarket { expected: Pubkey, found: Pubkey },
+
+    /// Events are missing between the last one applied and this one, so the book is no longer
+    /// exact. Take a new snapshot to resume.
+    #[error("Sequence gap in market {market} at slot {slot}: expected {expected}, found {found}")]
+    SequenceGap {
+        market: Pubkey,
+        slot: u64,
+        expected: u64,
+        found: u64,
+    },
+
+    /// The event modifies an order that is not on the book, e.g. because the snapshot is newer
+    /// than it claims.
+    #[error(
+        "Order {order_sequence_number} at {price_in_ticks} ticks is not on the book of {market}"
+    )]
+    UnknownOrder {
+        market: Pubkey,
+        order_sequence_number: u64,
+        price_in_ticks: u64,
+    },
+}
+
+/// A change to one resting order.
+#[derive(Debug, Clone, Copy)]
+struct BookUpdate {
+    side: Side,
+    order_id: FIFOOrderId,
+    order: PhoenixOrder,
+    /// Whether the order was filled, rather than placed, reduced or removed.
+    is_trade: bool,
+}
+
+/// Past slots of books kept by default for [`OrderbookReplayer::orderbook_at`], about a minute
+/// of slots.
+pub const DEFAULT_HISTORY_LIMIT: usize = 150;
+
+/// Keeps the exact L3 orderbook of one market up to date from its `Place`, `Fill`, `Reduce`,
+/// `Evict` and expired-order events, starting from a snapshot of the book.
+///
+/// Events must be applied in `(slot, sequence_number, event_index)` order. Every Phoenix
+/// instruction on a market increments the market's sequence number, so a batch of events
+/// whose sequence number skips ahead means instructions were missed. Instructions that log no
+/// events, such as deposits, still take a sequence number and must be fed with
+/// [`Self::advance`], or through [`Self::apply_decoded`]. Events already reflected in the
+/// snapshot or applied before are ignored, so overlapping event streams are harmless.
+#[derive(Debug, Clone)]
+pub struct OrderbookReplayer {
+    market: Pubkey,
+    orderbook: L3Orderbook,
+    /// Slot of the last event applied, or of the snapshot.
+    slot: u64,
+    /// Sequence number the next batch of events carries.
+    next_sequence_number: u64,
+    /// Sequence number and index of the last event applied, if any.
+    last_event: Option<(u64, u64)>,
+    /// The book as of the end of earlier slots, by slot.
+    history: BTreeMap<u64, L3Orderbook>,
+    /// How many slots of history to keep, [`DEFAULT_HISTORY_LIMIT`] unless set, unbounded if
+    /// `None`.
+    history_limit: Option<usize>,
+}
+
+impl OrderbookReplayer {
+    /// Starts from `orderbook`, read at `slot` when the market's sequence number was
+    /// `sequence_number` (`MarketHeader::market_sequence_number`).
+    pub fn new(market: Pubkey, orderbook: L3Orderbook, slot: u64, sequence_number: u64) -> Self {
+        OrderbookReplayer {
+            market,
+            orderbook,
+            slot,
+            next_sequence_number: sequence_number,
+            last_event: None,
+            history: BTreeMap::new(),
+            history_limit: Some(DEFAULT_HISTORY_LIMIT),
+        }
+    }
+
+    pub fn from_snapshot(snapshot: OrderbookSnapshot) -> Self {
+        Self::new(
+            snapshot.market,
+            snapshot.orderbook,
+            snapshot.slot,
+            snapshot.sequence_number,
+        )
+    }
+
+    /// Keeps the book of at most `slots` past slots for [`Self::orderbook_at`], instead of
+    /// [`DEFAULT_HISTORY_LIMIT`]. Each one is a full copy of the book.
+    pub fn with_history_limit(mut self, slots: usize) -> Self {
+        self.history_limit = Some(slots);
+        self.prune_history();
+        self
+    }
+
+    /// Keeps the book of every slot since the snapshot. Memory grows with every slot replayed,
+    /// so this is only meant for bounded replays.
+    pub fn with_unlimited_history(mut self) -> Self {
+        self.history_limit = None;
+        self
+    }
+
+    pub fn market(&self) -> &Pubkey {
+        &self.market
+    }
+
+    /// Slot of the last event applied, or of the snapshot.
+    pub fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    /// Sequence number the next batch of events is expected to carry.
+    pub fn next_sequence_number(&self) -> u64 {
+        self.next_sequence_number
+    }
+
+    /// The current book.
+    pub fn orderbook(&self) -> &L3Orderbook {
+        &self.orderbook
+    }
+
+    /// The book as of the end of `slot`. For the current slot, this is the book after the
+    /// events applied so far. `None` before the snapshot or the retained history.
+    pub fn orderbook_at(&self, slot: u64) -> Option<&L3Orderbook> {
+        if slot >= self.slot {
+            return Some(&self.orderbook);
+        }
+        self.history
+            .range(..=slot)
+            .next_back()
+            .map(|(_, orderbook)| orderbook)
+    }
+
+    /// Applies `events` in `(slot, sequence_number, event_index)` order, stopping at the first
+    /// error.
+    pub fn apply_events<'a>(
+        &mut self,
+        events: impl IntoIterator<Item = &'a PhoenixEvent>,
+    ) -> Result<(), ReplayError> {
+        let mut events = events.into_iter().collect::<Vec<_>>();
+        events.sort_by_key(|event| (event.slot, event.sequence_number, event.event_index));
+        events.into_iter().try_for_each(|event| self.apply(event))
+    }
+
+    /// Applies the events of this market in `decoded`, together with its batches that logged
+    /// no events, in `(slot, sequence_number, event_index)` order, stopping at the first error.
+    /// Events of other markets are skipped.
+    pub fn apply_decoded(&mut self, decoded: &DecodedEvents) -> Result<(), ReplayError> {
+        let events = decoded
+            .events
+            .iter()
+            .filter(|event| event.market == self.market)
+            .map(|event| {
+                (
+                    (event.slot, event.sequence_number, event.event_index),
+                    Some(event),
+                )
+            });
+        let empty_batches = decoded
+            .empty_batches
+            .iter()
+            .filter(|header| header.market == self.market)
+            .map(|header| ((header.slot, header.sequence_number, 0), None));
+        let mut batches = events.chain(empty_batches).collect::<Vec<_>>();
+        batches.sort_by_key(|(key, _)| *key);
+        batches
+            .into_iter()
+            .try_for_each(|((slot, sequence_number, _), event)| match event {
+                Some(event) => self.apply(event),
+                None => self.advance(sequence_number, slot),
+            })
+    }
+
+    /// Accounts for the batch `sequence_number`, logged at `slot` by an instruction that
+    /// emitted no events besides its header, e.g. a deposit, withdrawal or seat request. Only
+    /// the sequence number and slot move; batches already applied are ignored.
+    pub fn advance(&mut self, sequence_number: u64, slot: u64) -> Result<(), ReplayError> {
+        if sequence_number < self.next_sequence_number {
+            return Ok(());
+        }
+        if sequence_number > self.next_sequence_number {
+            return Err(ReplayError::SequenceGap {
+                market: self.market,
+                slot,
+                expected: self.next_sequence_number,
+                found: sequence_number,
+            });
+        }
+        self.move_to_slot(slot);
+        self.next_sequence_number = sequence_number + 1;
+        // The batch has no events to continue
+        self.last_event = None;
+        Ok(())
+    }
+
+    /// Applies one event. Events that do not change resting orders, e.g. fees and fill
+    /// summaries, only advance the sequence number.
+    pub fn apply(&mut self, event: &PhoenixEvent) -> Result<(), ReplayError> {
+        if event.market != self.market {
+            return Err(ReplayError::WrongMarket {
+                expected: self.market,
+                found: event.market,
+            });
+        }
+        let in_last_batch = matches!(
+            self.last_event,
+            Some((sequence_number, event_index))
+                if sequence_number == event.sequence_number && event_index < event.event_index
+        );
+        if !in_last_batch {
+            if event.sequence_number < self.next_sequence_number {
+                // Already part of the book
+                return Ok(());
+            }
+            if event.sequence_number > self.next_sequence_number {
+                return Err(ReplayError::SequenceGap {
+                    market: self.market,
+                    slot: event.slot,
+                    expected: self.next_sequence_number,
+                    found: event.sequence_number,
+                });
+            }
+        }
+
+        // Look the order up before changing anything, so errors leave the book as it was
+        let update = match event.details {
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                maker,
+                price_in_ticks,
+                base_lots_placed,
+                ..
+            }) => Some(BookUpdate {
+                side: Side::from_order_sequence_number(order_sequence_number),
+                order_id: FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number),
+                order: PhoenixOrder {
+                    num_base_lots: base_lots_placed,
+                    maker_id: maker,
+                },
+                is_trade: false,
+            }),
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            }) => Some(self.resize(
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                true,
+            )?),
+            // Cancels and expired orders
+            MarketEventDetails::Reduce(Reduce {
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            }) => Some(self.resize(
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                false,
+            )?),
+            MarketEventDetails::Evict(Evict {
+                order_sequence_number,
+                price_in_ticks,
+                ..
+            }) => Some(self.resize(order_sequence_number, price_in_ticks, 0, false)?),
+            _ => None,
+        };
+
+        self.move_to_slot(event.slot);
+        if let Some(BookUpdate {
+            side,
+            order_id,
+            order,
+            is_trade,
+        }) = update
+        {
+            if is_trade {
+                self.orderbook.process_trade(side, order_id, order);
+            } else {
+                self.orderbook.process_book_update(side, order_id, order);
+            }
+        }
+        self.next_sequence_number = event.sequence_number + 1;
+        self.last_event = Some((event.sequence_number, event.event_index));
+        Ok(())
+    }
+
+    /// The update setting the resting order `order_sequence_number` to `num_base_lots`, which
+    /// removes it when zero.
+    fn resize(
+        &self,
+        order_sequence_number: u64,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        is_trade: bool,
+    ) -> Result<BookUpdate, ReplayError> {
+        let side = Side::from_order_sequence_number(order_sequence_number);
+        let order_id = FIFOOrderId::new_from_untyped(price_in_ticks, order_sequence_number);
+        let book = match side {
+            Side::Bid => &self.orderbook.bids,
+            Side::Ask => &self.orderbook.asks,
+        };
+        let order = book.get(&order_id).ok_or(ReplayError::UnknownOrder {
+            market: self.market,
+            order_sequence_number,
+            price_in_ticks,
+        })?;
+        Ok(BookUpdate {
+            side,
+            order_id,
+            order: PhoenixOrder {
+                num_base_lots,
+                ..*order
+            },
+            is_trade,
+        })
+    }
+
+    /// Keeps the book as of the end of the current slot when `slot` is later.
+    fn move_to_slot(&mut self, slot: u64) {
+        if slot > self.slot {
+            self.history.insert(self.slot, self.orderbook.clone());
+            self.prune_history();
+            self.slot = slot;
+        }
+    }
+
+    fn prune_history(&mut self) {
+        let Some(limit) = self.history_limit else {
+            return;
+        };
+        while self.history.len() > limit {
+            self.history.pop_first();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk_client_core::RawPhoenixHeader;
+    use solana_sdk::signature::Signature;
+
+    const BID: u64 = !0 - 10;
+    const ASK: u64 = 10;
+
+    fn event(
+        market: Pubkey,
+        slot: u64,
+        sequence_number: u64,
+        event_index: u64,
+        details: MarketEventDetails,
+    ) -> PhoenixEvent {
+        PhoenixEvent {
+            market,
+            sequence_number,
+            slot,
+            timestamp: 1_700_000_000,
+            signature: Signature::default(),
+            signer: Pubkey::default(),
+            trader: Pubkey::default(),
+            event_index,
+            details,
+        }
+    }
+
+    fn empty_book() -> L3Orderbook {
+        L3Orderbook {
+            raw_base_units_per_base_lot: 0.001,
+            quote_units_per_raw_base_unit_per_tick: 0.001,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+
+    fn place(order_sequence_number: u64, price_in_ticks: u64, lots: u64) -> MarketEventDetails {
+        MarketEventDetails::Place(Place {
+            order_sequence_number,
+            client_order_id: 0,
+            maker: Pubkey::default(),
+            price_in_ticks,
+            base_lots_placed: lots,
+        })
+    }
+
+    fn fill(order_sequence_number: u64, price_in_ticks: u64, remaining: u64) -> MarketEventDetails {
+        MarketEventDetails::Fill(Fill {
+            order_sequence_number,
+            maker: Pubkey::default(),
+            taker: Pubkey::default(),
+            price_in_ticks,
+            base_lots_filled: 1,
+            base_lots_remaining: remaining,
+            side_filled: Side::from_order_sequence_number(order_sequence_number),
+            is_full_fill: remaining == 0,
+        })
+    }
+
+    fn lots(orderbook: &L3Orderbook, side: Side, order_sequence_number: u64) -> Option<u64> {
+        let book = match side {
+            Side::Bid => &orderbook.bids,
+            Side::Ask => &orderbook.asks,
+        };
+        book.iter()
+            .find(|(order_id, _)| order_id.order_sequence_number == order_sequence_number)
+            .map(|(_, order)| order.num_base_lots)
+    }
+
+    #[test]
+    fn test_replay_keeps_history_per_slot() {
+        let market = Pubkey::new_unique();
+        let mut replayer = OrderbookReplayer::new(market, empty_book(), 100, 5);
+        let events = [
+            event(market, 102, 7, 0, fill(ASK, 1_010, 0)),
+            // Reflected in the snapshot already
+            event(market, 99, 4, 0, place(ASK + 1, 1_020, 1)),
+            event(market, 101, 5, 0, place(BID, 990, 4)),
+            event(market, 101, 5, 1, place(ASK, 1_010, 2)),
+            event(market, 101, 6, 0, fill(BID, 990, 3)),
+        ];
+        replayer.apply_events(events.iter()).unwrap();
+
+        assert_eq!(replayer.slot(), 102);
+        assert_eq!(replayer.next_sequence_number(), 8);
+        let book = replayer.orderbook();
+        assert_eq!(lots(book, Side::Bid, BID), Some(3));
+        assert_eq!(lots(book, Side::Ask, ASK), None);
+        assert_eq!(lots(book, Side::Ask, ASK + 1), None);
+
+        let book = replayer.orderbook_at(101).unwrap();
+        assert_eq!(lots(book, Side::Bid, BID), Some(3));
+        assert_eq!(lots(book, Side::Ask, ASK), Some(2));
+        assert!(replayer.orderbook_at(100).unwrap().bids.is_empty());
+        assert!(replayer.orderbook_at(99).is_none());
+
+        // Replaying the same events again changes nothing
+        replayer.apply_events(events.iter()).unwrap();
+        assert_eq!(replayer.next_sequence_number(), 8);
+    }
+
+    #[test]
+    fn test_history_is_bounded_by_default() {
+        let market = Pubkey::new_unique();
+        let slots = DEFAULT_HISTORY_LIMIT as u64 + 10;
+        let events = (0..slots)
+            .map(|i| event(market, 101 + i, 5 + i, 0, place(ASK + i, 1_010, 1)))
+            .collect::<Vec<_>>();
+
+        let mut replayer = OrderbookReplayer::new(market, empty_book(), 100, 5);
+        replayer.apply_events(events.iter()).unwrap();
+        assert_eq!(replayer.history.len(), DEFAULT_HISTORY_LIMIT);
+        assert!(replayer.orderbook_at(100).is_none());
+        assert!(replayer.orderbook_at(100 + slots - 1).is_some());
+
+        let mut replayer =
+            OrderbookReplayer::new(market, empty_book(), 100, 5).with_unlimited_history();
+        replayer.apply_events(events.iter()).unwrap();
+        assert_eq!(replayer.history.len() as u64, slots);
+        assert!(replayer.orderbook_at(100).unwrap().asks.is_empty());
+    }
+
+    #[test]
+    fn test_replay_errors() {
+        let market = Pubkey::new_unique();
+        let mut replayer = OrderbookReplayer::new(market, empty_book(), 100, 5);
+
+        let gap = replayer.apply(&event(market, 101, 6, 0, place(BID, 990, 4)));
+        assert_eq!(
+            gap,
+            Err(ReplayError::SequenceGap {
+                market,
+                slot: 101,
+                expected: 5,
+                found: 6
+            })
+        );
+        let unknown = replayer.apply(&event(market, 101, 5, 0, fill(ASK, 1_010, 0)));
+        assert!(matches!(unknown, Err(ReplayError::UnknownOrder { .. })));
+        let other_market = Pubkey::new_unique();
+        let wrong_market = replayer.apply(&event(other_market, 101, 5, 0, place(BID, 990, 4)));
+        assert!(matches!(wrong_market, Err(ReplayError::WrongMarket { .. })));
+
+        // Nothing was applied
+        assert_eq!(replayer.slot(), 100);
+        assert_eq!(replayer.next_sequence_number(), 5);
+    }
+
+    #[test]
+    fn test_header_only_batches_advance_the_sequence_number() {
+        let market = Pubkey::new_unique();
+        let place_bid = event(market, 101, 5, 0, place(BID, 990, 4));
+        let place_ask = event(market, 102, 7, 0, place(ASK, 1_010, 2));
+        // A deposit between the two places logs only its header
+        let deposit = RawPhoenixHeader {
+            market,
+            sequence_number: 6,
+            slot: 102,
+            ..RawPhoenixHeader::default()
+        };
+
+        let mut replayer = OrderbookReplayer::new(market, empty_book(), 100, 5);
+        replayer.apply(&place_bid).unwrap();
+        assert!(matches!(
+            replayer.apply(&place_ask),
+            Err(ReplayError::SequenceGap {
+                expected: 6,
+                found: 7,
+                ..
+            })
+        ));
+        replayer.advance(6, 102).unwrap();
+        replayer.apply(&place_ask).unwrap();
+        assert_eq!(replayer.next_sequence_number(), 8);
+        // Already applied
+        replayer.advance(6, 102).unwrap();
+
+        let decoded = DecodedEvents {
+            events: vec![
+                place_ask,
+                event(Pubkey::new_unique(), 102, 3, 0, place(BID, 990, 1)),
+                place_bid,
+            ],
+            warnings: vec![],
+            empty_batches: vec![deposit],
+        };
+        let mut replayer = OrderbookReplayer::new(market, empty_book(), 100, 5);
+        replayer.apply_decoded(&decoded).unwrap();
+        assert_eq!(replayer.slot(), 102);
+        assert_eq!(replayer.next_sequence_number(), 8);
+        assert_eq!(lots(replayer.orderbook(), Side::Bid, BID), Some(4));
+        assert_eq!(lots(replayer.orderbook(), Side::Ask, ASK), Some(2));
+    }
+}
@@ -1,18 +1,27 @@
 #[allow(unused_imports)]
 use borsh::{BorshDeserialize, BorshSerialize};
+use phoenix::program::new_order::MultipleOrderPacket;
 use phoenix::state::OrderPacket;
 
-pub fn decode_order_packet(bytes: &[u8]) -> anyhow::Result<OrderPacket> {
+use crate::error::{PhoenixSdkError, Result};
+
+pub fn decode_order_packet(bytes: &[u8]) -> Result<OrderPacket> {
     let order_packet = match OrderPacket::try_from_slice(bytes) {
         Ok(order_packet) => order_packet,
         Err(_) => {
             let padded_bytes = [bytes, &[0, 0]].concat();
-            OrderPacket::try_from_slice(&padded_bytes)?
+            OrderPacket::try_from_slice(&padded_bytes)
+                .map_err(|e| PhoenixSdkError::decode(format!("order packet: {}", e)))?
         }
     };
     Ok(order_packet)
 }
 
+pub fn decode_multiple_order_packet(bytes: &[u8]) -> Result<MultipleOrderPacket> {
+    MultipleOrderPacket::try_from_slice(bytes)
+        .map_err(|e| PhoenixSdkError::decode(format!("multiple order packet: {}", e)))
+}
+
 #[test]
 fn test_decode_order_packet() {
     let post_only_op = OrderPacket::new_post_only_default(phoenix::state::Side::Ask, 10000, 10);
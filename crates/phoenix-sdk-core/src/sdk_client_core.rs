@@ -1,8 +1,5 @@
-use anyhow::anyhow;
-
-use anyhow::Result;
 use borsh::BorshDeserialize;
-use ellipsis_transaction_utils::ParsedTransaction;
+use ellipsis_transaction_utils::{ParsedInstruction, ParsedTransaction};
 use itertools::Itertools;
 use phoenix::program::MarketHeader;
 use phoenix::program::MarketSizeParams;
@@ -33,10 +30,20 @@ use std::{
     ops::{Div, Rem},
 };
 
+use crate::error::{PhoenixSdkError, Result};
+use crate::instruction_decoder::{decode_phoenix_instruction, DecodedInstruction};
 use crate::{market_event::Fill, orderbook::Orderbook};
 
 const AUDIT_LOG_HEADER_LEN: usize = 92;
 
+/// Position of the trader in the accounts of the Phoenix instructions sent by traders, after
+/// the Phoenix program, the log authority and the market.
+const TRADER_ACCOUNT_INDEX: usize = 3;
+
+/// Position of the trader in the accounts of the market authority instructions that act on a
+/// trader (`ForceCancelOrders`, `EvictSeat`), after the market authority.
+const AUTHORITY_TRADER_ACCOUNT_INDEX: usize = 4;
+
 pub struct MarketState {
     /// State of the bids and offers in the market.
     pub orderbook: Orderbook<FIFOOrderId, PhoenixOrder>,
@@ -53,12 +60,19 @@ pub struct RawPhoenixHeader {
     pub slot: u64,
     pub market: Pubkey,
     pub signer: Pubkey,
+    /// The trader the instruction that emitted the events acted for. This differs from
+    /// `signer` when Phoenix is invoked by another program, e.g. an aggregator or a crank,
+    /// and falls back to `signer` when the instruction is not known.
+    pub trader: Pubkey,
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct RawPhoenixEvent {
     pub header: RawPhoenixHeader,
     pub batch: Vec<PhoenixMarketEvent>,
+    /// The bytes after the last event that could be decoded, e.g. an event added to Phoenix
+    /// after this SDK was built and everything logged after it. Empty when all were decoded.
+    pub undecoded: Vec<u8>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -67,6 +81,21 @@ pub struct PhoenixOrder {
     pub maker_id: Pubkey,
 }
 
+/// Returns the trader a Phoenix instruction acts for, given the instruction's data and
+/// accounts. For `ForceCancelOrders` and `EvictSeat` this is the trader the market authority
+/// acts on. `None` for `Log` and the other market authority instructions, which have no trader.
+pub fn phoenix_instruction_trader(data: &[u8], accounts: &[Pubkey]) -> Option<Pubkey> {
+    let ix = PhoenixInstruction::try_from(*data.first()?).ok()?;
+    match ix {
+        PhoenixInstruction::ForceCancelOrders | PhoenixInstruction::EvictSeat => {
+            accounts.get(AUTHORITY_TRADER_ACCOUNT_INDEX).copied()
+        }
+        PhoenixInstruction::Log => None,
+        _ if ix as u8 >= PhoenixInstruction::InitializeMarket as u8 => None,
+        _ => accounts.get(TRADER_ACCOUNT_INDEX).copied(),
+    }
+}
+
 pub fn get_decimal_string<N: Display + Div + Rem + Copy + TryFrom<u64>>(
     amount: N,
     decimals: u32,
@@ -125,8 +154,8 @@ impl MarketMetadata {
             % base_atoms_per_base_lot
             != 0
         {
-            return Err(anyhow!(
-                "Invalid base lot size (in base atoms per base lot)"
+            return Err(PhoenixSdkError::InvalidMarket(
+                "Invalid base lot size (in base atoms per base lot)".to_string(),
             ));
         }
 
@@ -269,7 +298,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.raw_base_units_to_base_lots_rounded_down(raw_base_units))
     }
 
@@ -281,7 +310,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.raw_base_units_to_base_lots_rounded_up(raw_base_units))
     }
 
@@ -293,7 +322,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.base_atoms_to_base_lots_rounded_down(base_atoms))
     }
 
@@ -305,7 +334,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.base_atoms_to_base_lots_rounded_up(base_atoms))
     }
 
@@ -313,7 +342,7 @@ impl SDKClientCore {
     pub fn base_lots_to_base_atoms(&self, market_key: &Pubkey, base_lots: u64) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.base_lots_to_base_atoms(base_lots))
     }
 
@@ -321,7 +350,7 @@ impl SDKClientCore {
     pub fn quote_units_to_quote_lots(&self, market_key: &Pubkey, quote_units: f64) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.quote_units_to_quote_lots(quote_units))
     }
 
@@ -333,7 +362,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.quote_atoms_to_quote_lots_rounded_down(quote_atoms))
     }
 
@@ -345,7 +374,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.quote_atoms_to_quote_lots_rounded_up(quote_atoms))
     }
 
@@ -353,7 +382,7 @@ impl SDKClientCore {
     pub fn quote_lots_to_quote_atoms(&self, market_key: &Pubkey, quote_lots: u64) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.quote_lots_to_quote_atoms(quote_lots))
     }
 
@@ -365,7 +394,7 @@ impl SDKClientCore {
     ) -> Result<f64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.base_atoms_to_raw_base_units_as_float(base_atoms))
     }
 
@@ -377,7 +406,7 @@ impl SDKClientCore {
     ) -> Result<f64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.quote_atoms_to_quote_units_as_float(quote_atoms))
     }
 
@@ -401,7 +430,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.base_lots_and_price_to_quote_atoms(base_lots, price_in_ticks))
     }
 
@@ -414,7 +443,7 @@ impl SDKClientCore {
     ) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.float_price_to_ticks_rounded_down(price))
     }
 
@@ -423,7 +452,7 @@ impl SDKClientCore {
     pub fn float_price_to_ticks_rounded_up(&self, market_key: &Pubkey, price: f64) -> Result<u64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.float_price_to_ticks_rounded_up(price))
     }
 
@@ -431,7 +460,7 @@ impl SDKClientCore {
     pub fn ticks_to_float_price(&self, market_key: &Pubkey, ticks: u64) -> Result<f64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.ticks_to_float_price(ticks))
     }
 
@@ -439,7 +468,7 @@ impl SDKClientCore {
     pub fn raw_base_units_per_base_lot(&self, market_key: &Pubkey) -> Result<f64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.raw_base_units_per_base_lot())
     }
 
@@ -447,7 +476,7 @@ impl SDKClientCore {
     pub fn quote_units_per_raw_base_unit_per_tick(&self, market_key: &Pubkey) -> Result<f64> {
         self.markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first"))
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
             .map(|m| m.quote_units_per_raw_base_unit_per_tick())
     }
 }
@@ -458,40 +487,65 @@ impl SDKClientCore {
         rng.gen::<u128>()
     }
 
-    pub fn get_market_metadata(&self, market_key: &Pubkey) -> &MarketMetadata {
-        match self.markets.get(market_key) {
-            Some(market_metadata) => market_metadata,
-            None => panic!("Market not found! Please load in the market first."),
-        }
+    pub fn get_market_metadata(&self, market_key: &Pubkey) -> Result<&MarketMetadata> {
+        self.markets
+            .get(market_key)
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
     }
 
     pub fn parse_raw_phoenix_events(
         &self,
         sig: &Signature,
         events: Vec<Vec<u8>>,
-    ) -> Option<Vec<RawPhoenixEvent>> {
+    ) -> Result<Vec<RawPhoenixEvent>> {
+        self.parse_raw_phoenix_events_with_traders(
+            sig,
+            events.into_iter().map(|event| (event, None)).collect(),
+        )
+    }
+
+    /// Like [`Self::parse_raw_phoenix_events`], with each event log paired with the trader of
+    /// the instruction that emitted it. Logs without a trader are attributed to their signer.
+    pub fn parse_raw_phoenix_events_with_traders(
+        &self,
+        sig: &Signature,
+        events: Vec<(Vec<u8>, Option<Pubkey>)>,
+    ) -> Result<Vec<RawPhoenixEvent>> {
         let mut market_events: Vec<RawPhoenixEvent> = vec![];
 
-        for event in events.iter() {
-            let header_event =
-                PhoenixMarketEvent::try_from_slice(&event[..AUDIT_LOG_HEADER_LEN]).ok()?;
+        for (event, trader) in events.iter() {
+            if event.len() < AUDIT_LOG_HEADER_LEN {
+                return Err(PhoenixSdkError::decode(format!(
+                    "event log of {}: {} bytes is too short for a header",
+                    sig,
+                    event.len()
+                )));
+            }
+            let header_event = PhoenixMarketEvent::try_from_slice(&event[..AUDIT_LOG_HEADER_LEN])
+                .map_err(|e| {
+                PhoenixSdkError::decode(format!("event log header of {}: {}", sig, e))
+            })?;
             let header = match header_event {
-                PhoenixMarketEvent::Header(header) => Some(header),
-                _ => {
-                    panic!("Expected a header event");
+                PhoenixMarketEvent::Header(header) => header,
+                other => {
+                    return Err(PhoenixSdkError::decode(format!(
+                        "event log of {}: expected a header, found {:?}",
+                        sig, other
+                    )))
                 }
-            }?;
-            let offset = AUDIT_LOG_HEADER_LEN;
-            let mut phoenix_event_bytes = (header.total_events as u32).to_le_bytes().to_vec();
-            phoenix_event_bytes.extend_from_slice(&event[offset..]);
-            let phoenix_events =
-                match Vec::<PhoenixMarketEvent>::try_from_slice(&phoenix_event_bytes) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        println!("Error parsing events: {:?}", e);
-                        return None;
-                    }
-                };
+            };
+            // Events are decoded one at a time so an event this SDK does not know only loses
+            // the events after it, rather than the whole log
+            let mut remaining = &event[AUDIT_LOG_HEADER_LEN..];
+            let mut phoenix_events = vec![];
+            while !remaining.is_empty() && phoenix_events.len() < header.total_events as usize {
+                let mut cursor = remaining;
+                match PhoenixMarketEvent::deserialize(&mut cursor) {
+                    Ok(phoenix_event) => phoenix_events.push(phoenix_event),
+                    Err(_) => break,
+                }
+                remaining = cursor;
+            }
 
             market_events.push(RawPhoenixEvent {
                 header: RawPhoenixHeader {
@@ -502,8 +556,10 @@ impl SDKClientCore {
                     slot: header.slot,
                     market: header.market,
                     signer: header.signer,
+                    trader: trader.unwrap_or(header.signer),
                 },
                 batch: phoenix_events,
+                undecoded: remaining.to_vec(),
             });
         }
 
@@ -512,31 +568,44 @@ impl SDKClientCore {
             .iter()
             .group_by(|event| event.header)
             .into_iter()
-            .map(|(header, batches)| RawPhoenixEvent {
-                header,
-                batch: batches
-                    .cloned()
-                    .flat_map(|event| event.batch)
-                    .collect::<Vec<_>>(),
+            .map(|(header, batches)| {
+                let mut merged = RawPhoenixEvent {
+                    header,
+                    ..Default::default()
+                };
+                for event in batches {
+                    merged.batch.extend(event.batch.iter().cloned());
+                    merged.undecoded.extend_from_slice(&event.undecoded);
+                }
+                merged
             })
             .collect();
 
-        Some(market_events)
+        Ok(market_events)
     }
 
     pub fn parse_events_from_transaction(
         &self,
         tx: &ParsedTransaction,
-    ) -> Option<Vec<RawPhoenixEvent>> {
-        let sig = Signature::from_str(&tx.signature).ok()?;
+    ) -> Result<Vec<RawPhoenixEvent>> {
+        let sig = Signature::from_str(&tx.signature)
+            .map_err(|e| PhoenixSdkError::decode(format!("signature {}: {}", tx.signature, e)))?;
+        let phoenix_id = phoenix::id().to_string();
+        let trader_of = |ix: &ParsedInstruction| {
+            if ix.program_id != phoenix_id {
+                return None;
+            }
+            phoenix_instruction_trader(&ix.data, &parse_accounts(ix).ok()?)
+        };
         let mut event_list = vec![];
         for inner_ixs in tx.inner_instructions.iter() {
+            // When Phoenix is invoked directly, the trader is in the top-level instruction
+            let mut trader = inner_ixs
+                .first()
+                .and_then(|inner_ix| tx.instructions.get(inner_ix.parent_index))
+                .and_then(trader_of);
             for inner_ix in inner_ixs.iter() {
-                let current_program_id = inner_ix.instruction.program_id.clone();
-                if current_program_id != phoenix::id().to_string() {
-                    continue;
-                }
-                if inner_ix.instruction.data.is_empty() {
+                if inner_ix.instruction.program_id != phoenix_id {
                     continue;
                 }
                 let (tag, data) = match inner_ix.instruction.data.split_first() {
@@ -548,12 +617,62 @@ impl SDKClientCore {
                     None => continue,
                 };
                 if matches!(ix_enum, PhoenixInstruction::Log) {
-                    event_list.push(data.to_vec());
+                    event_list.push((data.to_vec(), trader));
+                } else if let Some(ix_trader) = trader_of(&inner_ix.instruction) {
+                    // Phoenix invoked by another program, whose logs follow
+                    trader = Some(ix_trader);
                 }
             }
         }
-        self.parse_raw_phoenix_events(&sig, event_list)
+        self.parse_raw_phoenix_events_with_traders(&sig, event_list)
     }
+
+    /// Decodes the Phoenix instructions of a transaction, top-level and inner, in execution
+    /// order. `Log` instructions are skipped; their events are parsed by
+    /// [`Self::parse_events_from_transaction`].
+    pub fn parse_instructions_from_transaction(
+        &self,
+        tx: &ParsedTransaction,
+    ) -> Result<Vec<DecodedInstruction>> {
+        let phoenix_id = phoenix::id().to_string();
+        let mut instructions = vec![];
+        for (index, ix) in tx.instructions.iter().enumerate() {
+            if ix.program_id == phoenix_id {
+                instructions.extend(decode_phoenix_instruction(
+                    &ix.data,
+                    &parse_accounts(ix)?,
+                    index,
+                    None,
+                )?);
+            }
+        }
+        for inner_ixs in tx.inner_instructions.iter() {
+            for (position, inner_ix) in inner_ixs.iter().enumerate() {
+                let ix = &inner_ix.instruction;
+                if ix.program_id == phoenix_id {
+                    instructions.extend(decode_phoenix_instruction(
+                        &ix.data,
+                        &parse_accounts(ix)?,
+                        inner_ix.parent_index,
+                        Some(position),
+                    )?);
+                }
+            }
+        }
+        // A top-level instruction runs before its inner instructions
+        instructions.sort_by_key(|ix| (ix.instruction_index, ix.inner_instruction_index));
+        Ok(instructions)
+    }
+}
+
+fn parse_accounts(ix: &ParsedInstruction) -> Result<Vec<Pubkey>> {
+    ix.accounts
+        .iter()
+        .map(|key| {
+            Pubkey::from_str(key)
+                .map_err(|e| PhoenixSdkError::decode(format!("account {}: {}", key, e)))
+        })
+        .collect()
 }
 
 /// SDKClientCore instruction builders
@@ -596,7 +715,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         let num_quote_ticks_per_base_unit = price / market.tick_size_in_quote_atoms_per_base_unit;
         let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::CancelProvide);
         let client_order_id = client_order_id.unwrap_or(0);
@@ -721,7 +840,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::CancelProvide);
         let client_order_id = client_order_id.unwrap_or(0);
         let target_price_in_ticks = price / market.tick_size_in_quote_atoms_per_base_unit;
@@ -774,7 +893,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         let order_type = match side {
             Side::Bid => OrderPacket::new_ioc_buy_with_slippage(lots_in, min_lots_out),
             Side::Ask => OrderPacket::new_ioc_sell_with_slippage(lots_in, min_lots_out),
@@ -799,7 +918,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         Ok(create_new_order_instruction(
             &market_key.clone(),
             &self.trader,
@@ -846,7 +965,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         let price_in_ticks = price / market.tick_size_in_quote_atoms_per_base_unit;
         let client_order_id = client_order_id.unwrap_or(0);
         let reject_post_only = reject_post_only.unwrap_or(false);
@@ -885,7 +1004,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         Ok(create_new_order_instruction(
             &market_key.clone(),
             &self.trader,
@@ -939,7 +1058,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         let num_quote_ticks_per_base_unit = price / market.tick_size_in_quote_atoms_per_base_unit;
         let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::DecrementTake);
         let client_order_id = client_order_id.unwrap_or(0);
@@ -978,7 +1097,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         Ok(create_new_order_instruction(
             &market_key.clone(),
             &self.trader,
@@ -1001,7 +1120,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         let mut cancel_orders = vec![];
         for &FIFOOrderId {
             price_in_ticks,
@@ -1037,7 +1156,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         let params = CancelUpToParams {
             side,
             tick_limit,
@@ -1058,7 +1177,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         Ok(create_cancel_all_orders_instruction(
             &market_key.clone(),
             &self.trader,
@@ -1071,7 +1190,7 @@ impl SDKClientCore {
         let market = self
             .markets
             .get(market_key)
-            .ok_or_else(|| anyhow!("Market not found! Please load in the market first."))?;
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))?;
         Ok(create_withdraw_funds_instruction(
             &market_key.clone(),
             &self.trader,
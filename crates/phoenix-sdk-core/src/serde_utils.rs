@@ -0,0 +1,130 @@
+//! `serde(with = ...)` helpers for the event and instruction types, so that they serialize the
+//! way Solana tooling displays them rather than as raw bytes.
+
+/// Serializes a value through its `Display` and `FromStr` impls: base58 for pubkeys and
+/// signatures, decimal strings for `u128`s (which JSON numbers cannot represent exactly).
+pub mod string {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Serializes a [`Side`](phoenix::state::enums::Side) as `"bid"` or `"ask"`.
+pub mod side {
+    use phoenix::state::enums::Side;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    const VARIANTS: &[&str] = &["bid", "ask"];
+
+    pub fn serialize<S: Serializer>(side: &Side, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match side {
+            Side::Bid => "bid",
+            Side::Ask => "ask",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Side, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        match value.as_str() {
+            "bid" => Ok(Side::Bid),
+            "ask" => Ok(Side::Ask),
+            other => Err(de::Error::unknown_variant(other, VARIANTS)),
+        }
+    }
+}
+
+/// Like [`string`], for optional values, which are `null` when missing.
+pub mod optional_string {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => serializer.collect_str(value),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| value.parse().map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Serializes an optional [`SelfTradeBehavior`](phoenix::state::enums::SelfTradeBehavior) as
+/// `"abort"`, `"cancel_provide"`, `"decrement_take"` or `null`.
+pub mod self_trade_behavior {
+    use phoenix::state::enums::SelfTradeBehavior;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    const VARIANTS: &[&str] = &["abort", "cancel_provide", "decrement_take"];
+
+    pub fn serialize<S: Serializer>(
+        behavior: &Option<SelfTradeBehavior>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match behavior {
+            Some(SelfTradeBehavior::Abort) => serializer.serialize_str("abort"),
+            Some(SelfTradeBehavior::CancelProvide) => serializer.serialize_str("cancel_provide"),
+            Some(SelfTradeBehavior::DecrementTake) => serializer.serialize_str("decrement_take"),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SelfTradeBehavior>, D::Error> {
+        let Some(value) = Option::<String>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        match value.as_str() {
+            "abort" => Ok(Some(SelfTradeBehavior::Abort)),
+            "cancel_provide" => Ok(Some(SelfTradeBehavior::CancelProvide)),
+            "decrement_take" => Ok(Some(SelfTradeBehavior::DecrementTake)),
+            other => Err(de::Error::unknown_variant(other, VARIANTS)),
+        }
+    }
+}
+
+/// Serializes bytes as a base64 string.
+pub mod base64 {
+    use ::base64::engine::general_purpose::STANDARD;
+    use ::base64::Engine;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        STANDARD.decode(value).map_err(de::Error::custom)
+    }
+}
@@ -341,7 +341,7 @@ fn test_base_lots_and_price_to_quote_atoms() {
     let quote_atoms = core
         .base_lots_and_price_to_quote_atoms(&market, base_lots, price_in_ticks)
         .unwrap();
-    let meta = core.get_market_metadata(&market);
+    let meta = core.get_market_metadata(&market).unwrap();
 
     assert_eq!(
         quote_atoms,
@@ -354,7 +354,7 @@ fn test_base_lots_and_price_to_quote_atoms() {
     let quote_atoms = core
         .base_lots_and_price_to_quote_atoms(&market, base_lots, price_in_ticks)
         .unwrap();
-    let meta = core.get_market_metadata(&market);
+    let meta = core.get_market_metadata(&market).unwrap();
 
     assert_eq!(
         quote_atoms,
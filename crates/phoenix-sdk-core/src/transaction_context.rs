@@ -0,0 +1,268 @@
+//! The transaction-level context of Phoenix events: what the transaction cost, and whether and
+//! why it failed.
+
+use num_traits::FromPrimitive;
+use phoenix::program::error::PhoenixError;
+use solana_sdk::{
+    instruction::InstructionError, pubkey::Pubkey, signature::Signature,
+    transaction::TransactionError,
+};
+use solana_transaction_status::{
+    EncodedTransactionWithStatusMeta, UiInstruction, UiParsedInstruction,
+};
+
+use crate::error::{PhoenixSdkError, Result};
+use crate::event_decoder::decode_message;
+use crate::instruction_decoder::DecodedInstruction;
+
+/// `ComputeBudgetInstruction::SetComputeUnitLimit`, followed by the limit as a `u32`.
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+/// `ComputeBudgetInstruction::SetComputeUnitPrice`, followed by the price as a `u64`.
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransactionContext {
+    pub signature: Signature,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// The fee paid in lamports, including the priority fee.
+    pub fee: u64,
+    /// `None` when the RPC node does not report it.
+    pub compute_units_consumed: Option<u64>,
+    /// The priority fee in micro-lamports per compute unit, `None` without a
+    /// `SetComputeUnitPrice` instruction.
+    pub compute_unit_price: Option<u64>,
+    /// The compute unit limit requested with `SetComputeUnitLimit`, if any.
+    pub compute_unit_limit: Option<u32>,
+    /// Why the transaction failed; `None` if it succeeded.
+    pub failure: Option<TransactionFailure>,
+    /// Every signer of the transaction, fee payer first.
+    pub signers: Vec<Pubkey>,
+}
+
+/// The error a failed transaction was rejected with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionFailure {
+    /// The error as reported by the runtime.
+    pub error: String,
+    /// Index of the top-level instruction that failed. `None` for errors outside any
+    /// instruction.
+    pub instruction_index: Option<usize>,
+    /// The custom error code the failing program returned, if any.
+    pub error_code: Option<u32>,
+    /// The program that raised the error, read from the logs. When Phoenix is invoked by
+    /// another program, this tells Phoenix errors apart from errors of the caller.
+    pub program: Option<Pubkey>,
+}
+
+impl TransactionContext {
+    /// Reads the context of a transaction as returned by `getTransaction` or a Geyser plugin,
+    /// in any JSON or binary encoding.
+    pub fn from_encoded_transaction(
+        tx: &EncodedTransactionWithStatusMeta,
+        slot: u64,
+        block_time: Option<i64>,
+    ) -> Result<Self> {
+        let meta = tx
+            .meta
+            .as_ref()
+            .ok_or_else(|| PhoenixSdkError::decode("transaction: status meta is missing"))?;
+        let message = decode_message(tx)?;
+
+        let mut context = TransactionContext {
+            signature: message.signature,
+            slot,
+            block_time,
+            fee: meta.fee,
+            compute_units_consumed: meta.compute_units_consumed.clone().into(),
+            failure: meta.err.as_ref().map(|err| {
+                let log_messages: Option<Vec<String>> = meta.log_messages.clone().into();
+                TransactionFailure::new(err, &log_messages.unwrap_or_default())
+            }),
+            signers: message.signers,
+            ..Default::default()
+        };
+        let compute_budget = solana_sdk::compute_budget::id();
+        for ix in message.instructions.iter() {
+            let (program_id, data) = match ix {
+                UiInstruction::Compiled(ix) => (
+                    message
+                        .account_keys
+                        .get(ix.program_id_index as usize)
+                        .copied(),
+                    &ix.data,
+                ),
+                UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => {
+                    (ix.program_id.parse().ok(), &ix.data)
+                }
+                UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => continue,
+            };
+            if program_id != Some(compute_budget) {
+                continue;
+            }
+            let data = bs58::decode(data)
+                .into_vec()
+                .map_err(|e| PhoenixSdkError::decode(format!("instruction data: {}", e)))?;
+            match data.split_first() {
+                Some((&SET_COMPUTE_UNIT_LIMIT, limit)) => {
+                    context.compute_unit_limit = limit.try_into().ok().map(u32::from_le_bytes);
+                }
+                Some((&SET_COMPUTE_UNIT_PRICE, price)) => {
+                    context.compute_unit_price = price.try_into().ok().map(u64::from_le_bytes);
+                }
+                _ => {}
+            }
+        }
+        Ok(context)
+    }
+
+    pub fn is_err(&self) -> bool {
+        self.failure.is_some()
+    }
+}
+
+impl TransactionFailure {
+    pub fn new(err: &TransactionError, log_messages: &[String]) -> Self {
+        let (instruction_index, error_code) = match err {
+            TransactionError::InstructionError(index, err) => {
+                let error_code = match err {
+                    InstructionError::Custom(code) => Some(*code),
+                    _ => None,
+                };
+                (Some(*index as usize), error_code)
+            }
+            _ => (None, None),
+        };
+        TransactionFailure {
+            error: err.to_string(),
+            instruction_index,
+            error_code,
+            program: failed_program(log_messages),
+        }
+    }
+
+    /// The Phoenix error the transaction failed with, `None` if another program raised it.
+    pub fn phoenix_error(&self) -> Option<PhoenixError> {
+        if self.program != Some(phoenix::id()) {
+            return None;
+        }
+        self.error_code.and_then(PhoenixError::from_u32)
+    }
+
+    /// Whether `instruction` is part of the top-level instruction that failed. The other
+    /// instructions of a failed transaction are rolled back without failing themselves.
+    pub fn caused_by(&self, instruction: &DecodedInstruction) -> bool {
+        self.instruction_index == Some(instruction.instruction_index)
+    }
+}
+
+/// The program of the first `Program <id> failed: <error>` log line. Programs that invoked it
+/// log the same failure after it.
+fn failed_program(log_messages: &[String]) -> Option<Pubkey> {
+    log_messages.iter().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("Program"), Some(program), Some("failed:")) => program.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction;
+    use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::{Transaction, TransactionError, VersionedTransaction};
+    use solana_transaction_status::{
+        TransactionStatusMeta, TransactionWithStatusMeta, UiTransactionEncoding,
+        VersionedTransactionWithStatusMeta,
+    };
+
+    #[test]
+    fn test_context_from_encoded_transaction() {
+        let payer = Keypair::new();
+        let cosigner = Keypair::new();
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+            ComputeBudgetInstruction::set_compute_unit_price(5_000),
+            Instruction::new_with_bytes(
+                phoenix::id(),
+                &[],
+                vec![AccountMeta::new_readonly(cosigner.pubkey(), true)],
+            ),
+        ];
+        let message = Message::new(&instructions, Some(&payer.pubkey()));
+        let transaction = VersionedTransaction::from(Transaction::new(
+            &[&payer, &cosigner],
+            message,
+            Default::default(),
+        ));
+        let err = TransactionError::InstructionError(2, InstructionError::Custom(15));
+        let tx = TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+            transaction,
+            meta: TransactionStatusMeta {
+                status: Err(err.clone()),
+                fee: 15_000,
+                compute_units_consumed: Some(31_000),
+                log_messages: Some(vec![
+                    format!("Program {} invoke [1]", phoenix::id()),
+                    "Program log: PhoenixInstruction::Swap".to_string(),
+                    format!(
+                        "Program {} failed: custom program error: 0xf",
+                        phoenix::id()
+                    ),
+                ]),
+                ..TransactionStatusMeta::default()
+            },
+        });
+
+        for encoding in [UiTransactionEncoding::Json, UiTransactionEncoding::Base64] {
+            let encoded = tx.clone().encode(encoding, Some(0), false).unwrap();
+            let context =
+                TransactionContext::from_encoded_transaction(&encoded, 7, Some(1_700_000_000))
+                    .unwrap();
+            assert_eq!(context.slot, 7);
+            assert_eq!(context.fee, 15_000);
+            assert_eq!(context.compute_units_consumed, Some(31_000));
+            assert_eq!(context.compute_unit_limit, Some(200_000), "{:?}", encoding);
+            assert_eq!(context.compute_unit_price, Some(5_000), "{:?}", encoding);
+            let failure = context.failure.unwrap();
+            assert_eq!(failure.error, err.to_string());
+            assert_eq!(failure.instruction_index, Some(2));
+            assert_eq!(failure.error_code, Some(15));
+            assert_eq!(failure.program, Some(phoenix::id()));
+            assert_eq!(failure.phoenix_error().map(|err| err as u32), Some(15));
+            assert_eq!(context.signers, vec![payer.pubkey(), cosigner.pubkey()]);
+        }
+    }
+
+    #[test]
+    fn test_failure_raised_by_an_invoked_program() {
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        let log_messages = [
+            format!("Program {} invoke [1]", phoenix::id()),
+            format!("Program {} invoke [2]", spl_token::id()),
+            "Program log: Error: insufficient funds".to_string(),
+            format!(
+                "Program {} failed: custom program error: 0x1",
+                spl_token::id()
+            ),
+            format!(
+                "Program {} failed: custom program error: 0x1",
+                phoenix::id()
+            ),
+        ];
+        let failure = TransactionFailure::new(&err, &log_messages);
+        assert_eq!(failure.program, Some(spl_token::id()));
+        assert_eq!(failure.error_code, Some(1));
+        // Not Phoenix's `InvalidMarketAuthority`, which shares the code
+        assert!(failure.phoenix_error().is_none());
+
+        let failure = TransactionFailure::new(&TransactionError::AccountInUse, &[]);
+        assert_eq!(failure.instruction_index, None);
+        assert_eq!(failure.program, None);
+    }
+}
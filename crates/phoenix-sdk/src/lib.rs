@@ -1,5 +1,8 @@
+pub use phoenix_sdk_core::error;
 pub use phoenix_sdk_core::orderbook;
+pub use phoenix_sdk_core::orderbook_replay;
 pub mod ladder_utils;
 pub mod order_packet_template;
+pub mod orderbook_watcher;
 pub mod sdk_client;
 pub mod utils;
@@ -0,0 +1,376 @@
+//! Keeps the orderbooks of markets up to date from `accountSubscribe` notifications, and
+//! broadcasts what changed at each price level.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use futures::StreamExt;
+use phoenix::program::dispatch_market::load_with_dispatch;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::Side;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk_core::error::{PhoenixSdkError, Result};
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, PhoenixOrder};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast;
+
+use crate::orderbook::Orderbook;
+use crate::orderbook_replay::{L3Orderbook, OrderbookSnapshot};
+use crate::sdk_client::{invalid_market, split_market_account};
+
+/// Diffs buffered per subscriber. A subscriber that falls further behind misses diffs, and
+/// should start over from [`OrderbookWatcher::snapshot`].
+const DIFF_BUFFER: usize = 1024;
+
+/// A change to one price level. Sizes are the total base lots resting at the price.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelChange {
+    Added {
+        side: Side,
+        price_in_ticks: u64,
+        base_lots: u64,
+    },
+    Changed {
+        side: Side,
+        price_in_ticks: u64,
+        base_lots: u64,
+    },
+    Removed {
+        side: Side,
+        price_in_ticks: u64,
+    },
+}
+
+/// The levels of a market's book that changed with one account update.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderbookDiff {
+    pub market: Pubkey,
+    /// Slot of the update.
+    pub slot: u64,
+    /// The market's sequence number after the update, as in [`OrderbookSnapshot`].
+    pub sequence_number: u64,
+    /// Bids first, then asks, each by price.
+    pub changes: Vec<LevelChange>,
+}
+
+impl OrderbookDiff {
+    /// The whole book of `snapshot` as added levels, to start a subscriber from.
+    pub fn from_snapshot(snapshot: &OrderbookSnapshot) -> Self {
+        OrderbookDiff {
+            market: snapshot.market,
+            slot: snapshot.slot,
+            sequence_number: snapshot.sequence_number,
+            changes: diff_levels(&empty_orderbook(&snapshot.orderbook), &snapshot.orderbook),
+        }
+    }
+}
+
+fn empty_orderbook(orderbook: &L3Orderbook) -> L3Orderbook {
+    Orderbook {
+        raw_base_units_per_base_lot: orderbook.raw_base_units_per_base_lot,
+        quote_units_per_raw_base_unit_per_tick: orderbook.quote_units_per_raw_base_unit_per_tick,
+        bids: BTreeMap::new(),
+        asks: BTreeMap::new(),
+    }
+}
+
+/// Total base lots resting at each price of one side of a book.
+fn levels(orders: &BTreeMap<FIFOOrderId, PhoenixOrder>) -> BTreeMap<u64, u64> {
+    let mut levels = BTreeMap::new();
+    for (order_id, order) in orders {
+        *levels.entry(order_id.price_in_ticks.as_u64()).or_default() += order.num_base_lots;
+    }
+    levels
+}
+
+/// The level changes that turn `old` into `new`, bids first, then asks, each by price.
+pub fn diff_levels(old: &L3Orderbook, new: &L3Orderbook) -> Vec<LevelChange> {
+    let mut changes = vec![];
+    for (side, old, new) in [
+        (Side::Bid, &old.bids, &new.bids),
+        (Side::Ask, &old.asks, &new.asks),
+    ] {
+        let (old, new) = (levels(old), levels(new));
+        let mut prices = old.keys().chain(new.keys()).copied().collect::<Vec<_>>();
+        prices.sort_unstable();
+        prices.dedup();
+        for price_in_ticks in prices {
+            let change = match (old.get(&price_in_ticks), new.get(&price_in_ticks)) {
+                (None, Some(&base_lots)) => LevelChange::Added {
+                    side,
+                    price_in_ticks,
+                    base_lots,
+                },
+                (Some(before), Some(&base_lots)) if *before != base_lots => LevelChange::Changed {
+                    side,
+                    price_in_ticks,
+                    base_lots,
+                },
+                (Some(_), None) => LevelChange::Removed {
+                    side,
+                    price_in_ticks,
+                },
+                _ => continue,
+            };
+            changes.push(change);
+        }
+    }
+    changes
+}
+
+/// Keeps an in-memory orderbook per market from `accountSubscribe` notifications, decoding
+/// every update of the market account with `load_with_dispatch`, and broadcasts the levels
+/// that changed to subscribers.
+///
+/// Every notification carries the whole account, so an update missed while disconnected is
+/// made up for by the next update of the market. Updates older than the book, by market
+/// sequence number, are ignored, so a book seeded with [`Self::apply_snapshot`] is never
+/// rolled back.
+pub struct OrderbookWatcher {
+    ws_url: String,
+    commitment: CommitmentConfig,
+    markets: BTreeMap<Pubkey, MarketMetadata>,
+    orderbooks: RwLock<HashMap<Pubkey, OrderbookSnapshot>>,
+    diffs: broadcast::Sender<OrderbookDiff>,
+}
+
+impl OrderbookWatcher {
+    /// Watches `markets` over the PubSub WebSocket at `ws_url`. A market has no book until it is
+    /// seeded with [`Self::apply_snapshot`] or its account is first updated.
+    pub fn new(
+        ws_url: impl Into<String>,
+        commitment: CommitmentConfig,
+        markets: BTreeMap<Pubkey, MarketMetadata>,
+    ) -> Self {
+        let (diffs, _) = broadcast::channel(DIFF_BUFFER);
+        OrderbookWatcher {
+            ws_url: ws_url.into(),
+            commitment,
+            markets,
+            orderbooks: RwLock::new(HashMap::new()),
+            diffs,
+        }
+    }
+
+    pub fn markets(&self) -> impl Iterator<Item = &Pubkey> {
+        self.markets.keys()
+    }
+
+    /// Receives the diff of every later update that changed a book. Subscribe before reading
+    /// the book with [`Self::snapshot`], and skip diffs whose sequence number is not above the
+    /// snapshot's.
+    pub fn subscribe(&self) -> broadcast::Receiver<OrderbookDiff> {
+        self.diffs.subscribe()
+    }
+
+    /// The current book of `market`, with the slot and sequence number it is as of.
+    pub fn snapshot(&self, market: &Pubkey) -> Option<OrderbookSnapshot> {
+        self.orderbooks.read().unwrap().get(market).cloned()
+    }
+
+    /// Replaces the book of a market with `snapshot`, unless the book is already as of the same
+    /// or a later sequence number. Returns the diff sent to subscribers, `None` if no level
+    /// changed.
+    pub fn apply_snapshot(&self, snapshot: OrderbookSnapshot) -> Option<OrderbookDiff> {
+        let mut orderbooks = self.orderbooks.write().unwrap();
+        let changes = match orderbooks.get(&snapshot.market) {
+            Some(current) if current.sequence_number >= snapshot.sequence_number => return None,
+            Some(current) => diff_levels(&current.orderbook, &snapshot.orderbook),
+            None => diff_levels(&empty_orderbook(&snapshot.orderbook), &snapshot.orderbook),
+        };
+        let diff = OrderbookDiff {
+            market: snapshot.market,
+            slot: snapshot.slot,
+            sequence_number: snapshot.sequence_number,
+            changes,
+        };
+        orderbooks.insert(snapshot.market, snapshot);
+        if diff.changes.is_empty() {
+            return None;
+        }
+        // Sent under the lock, so subscribers see diffs in the order the books changed
+        let _ = self.diffs.send(diff.clone());
+        Some(diff)
+    }
+
+    /// Decodes the data of a market account read at `slot` and applies it like
+    /// [`Self::apply_snapshot`].
+    pub fn apply_account_data(
+        &self,
+        market: &Pubkey,
+        slot: u64,
+        data: &[u8],
+    ) -> Result<Option<OrderbookDiff>> {
+        let meta = self
+            .markets
+            .get(market)
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market))?;
+        let (header, bytes) = split_market_account(market, data)?;
+        let orderbook = Orderbook::from_market(
+            load_with_dispatch(&header.market_size_params, bytes)
+                .map_err(|e| invalid_market(market, e))?
+                .inner,
+            meta.raw_base_units_per_base_lot(),
+            meta.quote_units_per_raw_base_unit_per_tick(),
+        );
+        Ok(self.apply_snapshot(OrderbookSnapshot {
+            market: *market,
+            orderbook,
+            slot,
+            sequence_number: header.market_sequence_number,
+        }))
+    }
+
+    /// Subscribes to every watched market account and applies its updates until the connection
+    /// drops. Call again to resubscribe.
+    pub async fn run(&self) -> Result<()> {
+        let pubsub_client = PubsubClient::new(&self.ws_url)
+            .await
+            .map_err(PhoenixSdkError::rpc)?;
+        let config = RpcAccountInfoConfig {
+            // Market accounts are too large for the default base58 encoding
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.commitment),
+            ..RpcAccountInfoConfig::default()
+        };
+        let mut streams = vec![];
+        let mut unsubscribes = vec![];
+        for market in self.markets.keys() {
+            let (stream, unsubscribe) = pubsub_client
+                .account_subscribe(market, Some(config.clone()))
+                .await
+                .map_err(PhoenixSdkError::rpc)?;
+            streams.push(stream.map(move |update| (*market, update)));
+            unsubscribes.push(unsubscribe);
+        }
+
+        let mut updates = futures::stream::select_all(streams);
+        let mut result = Ok(());
+        while let Some((market, update)) = updates.next().await {
+            let Some(account) = update.value.decode::<Account>() else {
+                result = Err(PhoenixSdkError::decode(format!(
+                    "account update of market {}",
+                    market
+                )));
+                break;
+            };
+            if let Err(err) = self.apply_account_data(&market, update.context.slot, &account.data) {
+                result = Err(err);
+                break;
+            }
+        }
+        drop(updates);
+        for unsubscribe in unsubscribes {
+            unsubscribe().await;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(bids: &[(u64, u64, u64)], asks: &[(u64, u64, u64)]) -> L3Orderbook {
+        let mut orderbook = Orderbook {
+            raw_base_units_per_base_lot: 0.001,
+            quote_units_per_raw_base_unit_per_tick: 0.01,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        for &(price_in_ticks, sequence_number, num_base_lots) in bids {
+            orderbook.bids.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, !sequence_number),
+                PhoenixOrder {
+                    num_base_lots,
+                    maker_id: Pubkey::default(),
+                },
+            );
+        }
+        for &(price_in_ticks, sequence_number, num_base_lots) in asks {
+            orderbook.asks.insert(
+                FIFOOrderId::new_from_untyped(price_in_ticks, sequence_number),
+                PhoenixOrder {
+                    num_base_lots,
+                    maker_id: Pubkey::default(),
+                },
+            );
+        }
+        orderbook
+    }
+
+    #[test]
+    fn test_diff_levels() {
+        let old = book(&[(99, 1, 5), (99, 2, 5), (98, 3, 1)], &[(101, 4, 2)]);
+        // One of the orders at 99 is filled, the bid at 98 is cancelled, a new ask joins at 102
+        // and the ask at 101 is replaced by another order of the same size
+        let new = book(&[(99, 1, 5)], &[(101, 6, 2), (102, 5, 7)]);
+
+        assert_eq!(
+            diff_levels(&old, &new),
+            vec![
+                LevelChange::Removed {
+                    side: Side::Bid,
+                    price_in_ticks: 98,
+                },
+                LevelChange::Changed {
+                    side: Side::Bid,
+                    price_in_ticks: 99,
+                    base_lots: 5,
+                },
+                LevelChange::Added {
+                    side: Side::Ask,
+                    price_in_ticks: 102,
+                    base_lots: 7,
+                },
+            ]
+        );
+        assert!(diff_levels(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_apply_snapshot_broadcasts_diffs_in_sequence() {
+        let market = Pubkey::new_unique();
+        let watcher = OrderbookWatcher::new(
+            "ws://localhost:8900",
+            CommitmentConfig::confirmed(),
+            BTreeMap::new(),
+        );
+        let mut diffs = watcher.subscribe();
+        let snapshot = |sequence_number, orderbook| OrderbookSnapshot {
+            market,
+            orderbook,
+            slot: sequence_number * 10,
+            sequence_number,
+        };
+
+        let first = watcher
+            .apply_snapshot(snapshot(5, book(&[(99, 1, 5)], &[])))
+            .unwrap();
+        assert_eq!(
+            first,
+            OrderbookDiff::from_snapshot(&watcher.snapshot(&market).unwrap())
+        );
+        assert_eq!(diffs.try_recv().unwrap(), first);
+
+        // An older update, e.g. a notification queued before the book was seeded
+        assert!(watcher
+            .apply_snapshot(snapshot(4, book(&[], &[])))
+            .is_none());
+        // A newer update that changed nothing on the book, e.g. a deposit
+        assert!(watcher
+            .apply_snapshot(snapshot(6, book(&[(99, 2, 5)], &[])))
+            .is_none());
+        assert!(diffs.try_recv().is_err());
+
+        let removed = watcher.apply_snapshot(snapshot(7, book(&[], &[]))).unwrap();
+        assert_eq!(removed.sequence_number, 7);
+        assert_eq!(diffs.try_recv().unwrap(), removed);
+        assert_eq!(watcher.snapshot(&market).unwrap().sequence_number, 7);
+    }
+}
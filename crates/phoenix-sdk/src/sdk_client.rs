@@ -1,50 +1,58 @@
-use anyhow::anyhow;
-use anyhow::Result;
 use ellipsis_client::EllipsisClient;
+use ellipsis_transaction_utils::ParsedTransaction;
 use phoenix::program::dispatch_market::*;
-use phoenix::program::EvictEvent;
-use phoenix::program::ExpiredOrderEvent;
-use phoenix::program::FeeEvent;
-use phoenix::program::FillEvent;
-use phoenix::program::FillSummaryEvent;
 use phoenix::program::MarketHeader;
-use phoenix::program::PhoenixMarketEvent;
-use phoenix::program::PlaceEvent;
-use phoenix::program::ReduceEvent;
-use phoenix::program::TimeInForceEvent;
 use phoenix::state::enums::*;
 use phoenix::state::markets::*;
 use phoenix::state::TraderState;
-use phoenix_sdk_core::market_event::TimeInForce;
+use phoenix_sdk_core::error::{PhoenixSdkError, Result};
+use phoenix_sdk_core::event_decoder::phoenix_events_from_raw;
 use phoenix_sdk_core::sdk_client_core::MarketState;
 pub use phoenix_sdk_core::{
+    event_decoder::{DecodedEvents, InstructionDataEncoding, ParseWarning},
+    instruction_decoder::{DecodedInstruction, OrderIntent, OrderType},
     market_event::{Evict, Fill, FillSummary, MarketEventDetails, PhoenixEvent, Place, Reduce},
     sdk_client_core::{get_decimal_string, MarketMetadata, PhoenixOrder, SDKClientCore},
+    transaction_context::{TransactionContext, TransactionFailure},
 };
 use serde::{Deserialize, Serialize};
-use solana_client::client_error::reqwest;
+use solana_client::client_error::{reqwest, ClientError};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
+    instruction::{Instruction, InstructionError},
     pubkey::Pubkey,
     signature::{Signature, Signer},
     signer::keypair::Keypair,
+    transaction::TransactionError,
 };
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::str::FromStr;
 use std::{collections::BTreeMap, mem::size_of, ops::DerefMut};
+use tokio::sync::mpsc::UnboundedSender;
 
 use crate::orderbook::Orderbook;
+use crate::orderbook_replay::{OrderbookReplayer, OrderbookSnapshot};
+use crate::orderbook_watcher::OrderbookWatcher;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JsonMarketConfig {
     pub markets: Vec<String>,
 }
 
+/// The Phoenix events of a transaction, with the instructions that asked for them.
+#[derive(Clone, Debug, Default)]
+pub struct PhoenixTransaction {
+    pub events: Vec<PhoenixEvent>,
+    pub instructions: Vec<DecodedInstruction>,
+}
+
 pub struct SDKClient {
     pub client: EllipsisClient,
     pub core: SDKClientCore,
+    /// Where warnings raised while parsing events are sent, see [`Self::set_warning_channel`].
+    warnings: Option<UnboundedSender<ParseWarning>>,
 }
 
 impl Deref for SDKClient {
@@ -72,7 +80,11 @@ impl SDKClient {
             markets,
             trader: client.payer.pubkey(),
         };
-        Ok(SDKClient { client, core })
+        Ok(SDKClient {
+            client,
+            core,
+            warnings: None,
+        })
     }
 
     /// Create a new SDKClient from an EllipsisClient.
@@ -93,7 +105,11 @@ impl SDKClient {
             trader: client.payer.pubkey(),
         };
         println!("Creating SDKClient with all markets");
-        let mut sdk = SDKClient { client, core };
+        let mut sdk = SDKClient {
+            client,
+            core,
+            warnings: None,
+        };
         sdk.add_all_markets().await?;
         Ok(sdk)
     }
@@ -115,7 +131,11 @@ impl SDKClient {
             markets: BTreeMap::new(),
             trader: client.payer.pubkey(),
         };
-        let mut sdk = SDKClient { client, core };
+        let mut sdk = SDKClient {
+            client,
+            core,
+            warnings: None,
+        };
         for market_key in market_keys {
             sdk.add_market(market_key).await?;
         }
@@ -140,7 +160,7 @@ impl SDKClient {
     /// add markets to the SDKClient.
     pub async fn new(payer: &Keypair, url: &str) -> Result<Self> {
         let rpc = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed());
-        let client = EllipsisClient::from_rpc(rpc, payer)?;
+        let client = EllipsisClient::from_rpc(rpc, payer).map_err(PhoenixSdkError::rpc)?;
 
         SDKClient::new_from_ellipsis_client(client).await
     }
@@ -157,7 +177,7 @@ impl SDKClient {
     /// This will use a list of markets from a pre-defined config file to add all known markets to the SDKClient.
     pub async fn new_with_all_markets(payer: &Keypair, url: &str) -> Result<Self> {
         let rpc = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed());
-        let client = EllipsisClient::from_rpc(rpc, payer)?;
+        let client = EllipsisClient::from_rpc(rpc, payer).map_err(PhoenixSdkError::rpc)?;
 
         SDKClient::new_from_ellipsis_client_with_all_markets(client).await
     }
@@ -177,7 +197,7 @@ impl SDKClient {
         url: &str,
     ) -> Result<Self> {
         let rpc = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed());
-        let client = EllipsisClient::from_rpc(rpc, payer)?;
+        let client = EllipsisClient::from_rpc(rpc, payer).map_err(PhoenixSdkError::rpc)?;
 
         SDKClient::new_from_ellipsis_client_with_market_keys(market_keys, client).await
     }
@@ -197,7 +217,11 @@ impl SDKClient {
     pub async fn add_all_markets(&mut self) -> Result<()> {
         let config_url = "https://raw.githubusercontent.com/Ellipsis-Labs/phoenix-sdk/master/typescript/phoenix-sdk/config.json";
 
-        let genesis = self.client.get_genesis_hash().await?;
+        let genesis = self
+            .client
+            .get_genesis_hash()
+            .await
+            .map_err(PhoenixSdkError::rpc)?;
 
         //hardcoded in the genesis hashes for mainnet and devnet
         let cluster = match genesis.to_string().as_str() {
@@ -208,21 +232,30 @@ impl SDKClient {
 
         let response = reqwest::get(config_url)
             .await
-            .map_err(|e| anyhow!("Failed to get config file: {}", e))?
+            .map_err(|e| {
+                PhoenixSdkError::MarketConfigError(format!("Failed to get config file: {}", e))
+            })?
             .json::<HashMap<String, JsonMarketConfig>>()
             .await
-            .map_err(|e| anyhow!("Failed to parse config file: {}", e))?;
+            .map_err(|e| {
+                PhoenixSdkError::MarketConfigError(format!("Failed to parse config file: {}", e))
+            })?;
 
-        let market_details = response
-            .get(cluster)
-            .ok_or_else(|| anyhow!("Failed to find cluster {} in config file", cluster))?;
+        let market_details = response.get(cluster).ok_or_else(|| {
+            PhoenixSdkError::MarketConfigError(format!(
+                "Failed to find cluster {} in config file",
+                cluster
+            ))
+        })?;
 
         for market_key in market_details.markets.iter() {
-            let market_key = Pubkey::from_str(market_key).map_err(|e| anyhow!(e))?;
+            let market_key = Pubkey::from_str(market_key).map_err(|e| {
+                PhoenixSdkError::MarketConfigError(format!("Invalid market {}: {}", market_key, e))
+            })?;
             if self.markets.get(&market_key).is_some() {
                 continue;
             }
-            self.add_market(&market_key).await.map_err(|e| anyhow!(e))?;
+            self.add_market(&market_key).await?;
         }
 
         Ok(())
@@ -233,7 +266,7 @@ impl SDKClient {
         rt.block_on(self.add_all_markets())
     }
 
-    pub async fn add_market(&mut self, market_key: &Pubkey) -> anyhow::Result<()> {
+    pub async fn add_market(&mut self, market_key: &Pubkey) -> Result<()> {
         let market_metadata = self.get_market_metadata(market_key).await?;
         self.markets.insert(*market_key, market_metadata);
 
@@ -256,12 +289,10 @@ impl SDKClient {
     pub async fn get_market_ladder(&self, market_key: &Pubkey, levels: u64) -> Result<Ladder> {
         let market_account_data = (self.client.get_account_data(market_key))
             .await
-            .map_err(|_| anyhow!("Failed to get market account data"))?;
-        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
-        let header: &MarketHeader =
-            bytemuck::try_from_bytes(header_bytes).expect("Failed to deserialize market header");
+            .map_err(PhoenixSdkError::rpc)?;
+        let (header, bytes) = split_market_account(market_key, &market_account_data)?;
         let market = load_with_dispatch(&header.market_size_params, bytes)
-            .map_err(|_| anyhow!("Market configuration not found"))?
+            .map_err(|e| invalid_market(market_key, e))?
             .inner;
 
         Ok(market.get_ladder(levels))
@@ -319,6 +350,74 @@ impl SDKClient {
         rt.block_on(self.get_market_orderbook(market_key))
     }
 
+    /// Reads the market's L3 orderbook, together with the slot it was read at and the market's
+    /// sequence number. Unlike `get_market_orderbook`, RPC errors and missing accounts are
+    /// returned rather than read as an empty book.
+    pub async fn get_market_orderbook_snapshot(
+        &self,
+        market_key: &Pubkey,
+    ) -> Result<OrderbookSnapshot> {
+        let response = self
+            .client
+            .get_account_with_commitment(market_key, self.client.commitment())
+            .await
+            .map_err(PhoenixSdkError::rpc)?;
+        let account = response.value.ok_or_else(|| {
+            PhoenixSdkError::InvalidMarket(format!("{} does not exist", market_key))
+        })?;
+        let (header, bytes) = split_market_account(market_key, &account.data)?;
+        let market = load_with_dispatch(&header.market_size_params, bytes)
+            .map_err(|e| invalid_market(market_key, e))?
+            .inner;
+
+        let meta = self.get_market_metadata_from_cache(market_key)?;
+        let raw_base_units_per_base_lot =
+            meta.base_atoms_per_base_lot as f64 / meta.base_atoms_per_raw_base_unit as f64;
+        let quote_units_per_raw_base_unit_per_tick = meta.tick_size_in_quote_atoms_per_base_unit
+            as f64
+            / (meta.quote_atoms_per_quote_unit as f64 * meta.raw_base_units_per_base_unit as f64);
+        let orderbook = Orderbook::from_market(
+            market,
+            raw_base_units_per_base_lot,
+            quote_units_per_raw_base_unit_per_tick,
+        );
+        Ok(OrderbookSnapshot {
+            market: *market_key,
+            orderbook,
+            slot: response.context.slot,
+            sequence_number: header.market_sequence_number,
+        })
+    }
+
+    /// Takes a snapshot of the market's L3 orderbook to replay the market's later events onto.
+    pub async fn get_market_orderbook_replayer(
+        &self,
+        market_key: &Pubkey,
+    ) -> Result<OrderbookReplayer> {
+        self.get_market_orderbook_snapshot(market_key)
+            .await
+            .map(OrderbookReplayer::from_snapshot)
+    }
+
+    /// Watches the orderbooks of `markets` over the PubSub WebSocket at `ws_url`, at the
+    /// client's commitment. Each book starts from a snapshot read with
+    /// `get_market_orderbook_snapshot`; call `OrderbookWatcher::run` to keep them up to date.
+    pub async fn get_orderbook_watcher(
+        &self,
+        ws_url: &str,
+        markets: &[Pubkey],
+    ) -> Result<OrderbookWatcher> {
+        let metadata = markets
+            .iter()
+            .map(|market| Ok((*market, *self.get_market_metadata_from_cache(market)?)))
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        let watcher = OrderbookWatcher::new(ws_url, self.client.commitment(), metadata);
+        for market in markets {
+            watcher.apply_snapshot(self.get_market_orderbook_snapshot(market).await?);
+        }
+        Ok(watcher)
+    }
+
     pub async fn get_traders_with_market_key(
         &self,
         market_key: &Pubkey,
@@ -327,11 +426,9 @@ impl SDKClient {
             Ok(data) => data,
             Err(_) => return Ok(BTreeMap::new()),
         };
-        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
-        let header = bytemuck::try_from_bytes::<MarketHeader>(header_bytes)
-            .map_err(|_| anyhow!("Failed to deserialize market header"))?;
+        let (header, bytes) = split_market_account(market_key, &market_account_data)?;
         let market = load_with_dispatch(&header.market_size_params, bytes)
-            .map_err(|_| anyhow!("Market configuration not found"))?
+            .map_err(|e| invalid_market(market_key, e))?
             .inner;
 
         Ok(market
@@ -364,11 +461,9 @@ impl SDKClient {
                 })
             }
         };
-        let (header_bytes, bytes) = market_account_data.split_at(size_of::<MarketHeader>());
-        let header = bytemuck::try_from_bytes::<MarketHeader>(header_bytes)
-            .expect("Failed to deserialize market header");
+        let (header, bytes) = split_market_account(market_key, &market_account_data)?;
         let market = load_with_dispatch(&header.market_size_params, bytes)
-            .expect("Market configuration not found")
+            .map_err(|e| invalid_market(market_key, e))?
             .inner;
 
         let meta = self.get_market_metadata_from_cache(market_key)?;
@@ -398,311 +493,196 @@ impl SDKClient {
             None => {
                 let market_account_data = (self.client.get_account_data(market_key))
                     .await
-                    .map_err(|_| anyhow!("Failed to find market account"))?;
+                    .map_err(PhoenixSdkError::rpc)?;
 
-                let (header_bytes, _) = market_account_data.split_at(size_of::<MarketHeader>());
-                let header = bytemuck::try_from_bytes::<MarketHeader>(header_bytes)
-                    .map_err(|_| anyhow!("Failed to deserialize market header"))?;
-                MarketMetadata::from_header(&header)
+                let (header, _) = split_market_account(market_key, &market_account_data)?;
+                MarketMetadata::from_header(header)
             }
         }
     }
 
-    pub fn get_market_metadata_from_cache(
-        &self,
-        market_key: &Pubkey,
-    ) -> anyhow::Result<&MarketMetadata> {
-        match self.markets.get(market_key) {
-            Some(metadata) => return Ok(metadata),
-            None => Err(anyhow!(
-                "Market metadata not found in cache. Try calling add_market first."
-            )),
-        }
+    pub fn get_market_metadata_from_cache(&self, market_key: &Pubkey) -> Result<&MarketMetadata> {
+        self.markets
+            .get(market_key)
+            .ok_or(PhoenixSdkError::MarketNotLoaded(*market_key))
     }
 
     pub async fn parse_events_from_transaction(
         &self,
         sig: &Signature,
-    ) -> Option<Vec<PhoenixEvent>> {
-        let tx = self.client.get_transaction(sig).await.ok()?;
+    ) -> Result<Vec<PhoenixEvent>> {
+        let tx = self
+            .client
+            .get_transaction(sig)
+            .await
+            .map_err(PhoenixSdkError::rpc)?;
         if tx.is_err {
-            return None;
+            return Err(PhoenixSdkError::TransactionFailed(sig.to_string()));
         }
-        let raw_phoenix_events = self.core.parse_events_from_transaction( &tx)?;
-        let mut trade_direction = None;
-        let mut market_events = vec![];
-        let mut cached_metadata = self.markets.clone();
-        for raw_phoenix_event in raw_phoenix_events {
-            let header = raw_phoenix_event.header;
-            if !cached_metadata.contains_key(&header.market) {
-                let metadata = self.get_market_metadata(&header.market).await.ok()?;
-                cached_metadata.insert(header.market.clone(), metadata);
+        self.parse_events_from_parsed_transaction(&tx).await
+    }
+
+    /// Parses the Phoenix events out of a transaction that has already been fetched, for callers
+    /// that fetch transactions themselves (e.g. to retry RPC failures).
+    ///
+    /// The events of markets whose metadata cannot be fetched are skipped rather than failing
+    /// the whole transaction. Skipped markets and unknown events are reported on the warning
+    /// channel.
+    pub async fn parse_events_from_parsed_transaction(
+        &self,
+        tx: &ParsedTransaction,
+    ) -> Result<Vec<PhoenixEvent>> {
+        let raw_phoenix_events = self.core.parse_events_from_transaction(tx)?;
+        let sig = &Signature::from_str(&tx.signature)
+            .map_err(|e| PhoenixSdkError::decode(format!("signature {}: {}", tx.signature, e)))?;
+        // Only the markets that are not loaded are fetched
+        let mut fetched_metadata = BTreeMap::new();
+        let mut unavailable = vec![];
+        for raw_phoenix_event in raw_phoenix_events.iter() {
+            let market = raw_phoenix_event.header.market;
+            if self.markets.contains_key(&market)
+                || fetched_metadata.contains_key(&market)
+                || unavailable.contains(&market)
+            {
+                continue;
             }
-            let meta = cached_metadata.get(&header.market)?;
-
-            for phoenix_event in raw_phoenix_event.batch {
-                match phoenix_event {
-                    PhoenixMarketEvent::Fill(FillEvent {
-                        index,
-                        maker_id,
-                        order_sequence_number,
-                        price_in_ticks,
-                        base_lots_filled,
-                        base_lots_remaining,
-                    }) => {
-                        let side_filled = Side::from_order_sequence_number(order_sequence_number);
-                        market_events.push(PhoenixEvent {
-                            market: header.market,
-                            sequence_number: header.sequence_number,
-                            slot: header.slot,
-                            timestamp: header.timestamp,
-                            signature: *sig,
-                            signer: header.signer,
-                            event_index: index as u64,
-                            details: MarketEventDetails::Fill(Fill {
-                                order_sequence_number,
-                                maker: maker_id,
-                                taker: header.signer,
-                                price_in_ticks,
-                                base_lots_filled,
-                                base_lots_remaining,
-                                side_filled: Side::from_order_sequence_number(
-                                    order_sequence_number,
-                                ),
-                                is_full_fill: base_lots_remaining == 0,
-                            }),
-                        });
-                        if trade_direction.is_none() {
-                            trade_direction = match side_filled {
-                                Side::Bid => Some(-1),
-                                Side::Ask => Some(1),
-                            }
-                        }
-                    }
-                    PhoenixMarketEvent::Reduce(ReduceEvent {
-                        index,
-                        order_sequence_number,
-                        price_in_ticks,
-                        base_lots_removed,
-                        base_lots_remaining,
-                    }) => market_events.push(PhoenixEvent {
-                        market: header.market,
-                        sequence_number: header.sequence_number,
-                        slot: header.slot,
-                        timestamp: header.timestamp,
-                        signature: *sig,
-                        signer: header.signer,
-                        event_index: index as u64,
-                        details: MarketEventDetails::Reduce(Reduce {
-                            order_sequence_number,
-                            maker: header.signer,
-                            price_in_ticks,
-                            base_lots_removed,
-                            base_lots_remaining,
-                            is_full_cancel: base_lots_remaining == 0,
-                        }),
-                    }),
-
-                    PhoenixMarketEvent::Place(PlaceEvent {
-                        index,
-                        order_sequence_number,
-                        client_order_id,
-                        price_in_ticks,
-                        base_lots_placed,
-                    }) => market_events.push(PhoenixEvent {
-                        market: header.market,
-                        sequence_number: header.sequence_number,
-                        slot: header.slot,
-                        timestamp: header.timestamp,
-                        signature: *sig,
-                        signer: header.signer,
-                        event_index: index as u64,
-                        details: MarketEventDetails::Place(Place {
-                            order_sequence_number,
-                            client_order_id,
-                            maker: header.signer,
-                            price_in_ticks,
-                            base_lots_placed,
-                        }),
-                    }),
-                    PhoenixMarketEvent::Evict(EvictEvent {
-                        index,
-                        maker_id,
-                        order_sequence_number,
-                        price_in_ticks,
-                        base_lots_evicted,
-                    }) => market_events.push(PhoenixEvent {
-                        market: header.market,
-                        sequence_number: header.sequence_number,
-                        slot: header.slot,
-                        timestamp: header.timestamp,
-                        signature: *sig,
-                        signer: header.signer,
-                        event_index: index as u64,
-                        details: MarketEventDetails::Evict(Evict {
-                            order_sequence_number,
-                            maker: maker_id,
-                            price_in_ticks,
-                            base_lots_evicted,
-                        }),
-                    }),
-                    PhoenixMarketEvent::FillSummary(FillSummaryEvent {
-                        index,
-                        client_order_id,
-                        total_base_lots_filled,
-                        total_quote_lots_filled,
-                        total_fee_in_quote_lots,
-                    }) => market_events.push(PhoenixEvent {
-                        market: header.market,
-                        sequence_number: header.sequence_number,
-                        slot: header.slot,
-                        timestamp: header.timestamp,
-                        signature: *sig,
-                        signer: header.signer,
-                        event_index: index as u64,
-                        details: MarketEventDetails::FillSummary(FillSummary {
-                            client_order_id,
-                            total_base_filled: total_base_lots_filled
-                                * meta.base_atoms_per_base_lot,
-                            total_quote_filled_including_fees: total_quote_lots_filled
-                                * meta.quote_atoms_per_quote_lot,
-                            total_quote_fees: total_fee_in_quote_lots
-                                * meta.quote_atoms_per_quote_lot,
-                            trade_direction: trade_direction.unwrap_or(0),
-                        }),
-                    }),
-                    PhoenixMarketEvent::Fee(FeeEvent {
-                        index,
-                        fees_collected_in_quote_lots,
-                    }) => market_events.push(PhoenixEvent {
-                        market: header.market,
-                        sequence_number: header.sequence_number,
-                        slot: header.slot,
-                        timestamp: header.timestamp,
-                        signature: *sig,
-                        signer: header.signer,
-                        event_index: index as u64,
-                        details: MarketEventDetails::Fee(
-                            fees_collected_in_quote_lots * meta.quote_atoms_per_quote_lot,
-                        ),
-                    }),
-                    PhoenixMarketEvent::TimeInForce(TimeInForceEvent {
-                        index,
-                        order_sequence_number,
-                        last_valid_slot,
-                        last_valid_unix_timestamp_in_seconds,
-                    }) => market_events.push(PhoenixEvent {
-                        market: header.market,
-                        sequence_number: header.sequence_number,
-                        slot: header.slot,
-                        timestamp: header.timestamp,
-                        signature: *sig,
-                        signer: header.signer,
-                        event_index: index as u64,
-                        details: MarketEventDetails::TimeInForce(TimeInForce {
-                            order_sequence_number,
-                            last_valid_slot,
-                            last_valid_unix_timestamp_in_seconds,
-                        }),
-                    }),
-                    PhoenixMarketEvent::ExpiredOrder(ExpiredOrderEvent {
-                        index,
-                        maker_id,
-                        order_sequence_number,
-                        price_in_ticks,
-                        base_lots_removed,
-                    }) => market_events.push(PhoenixEvent {
-                        market: header.market,
-                        sequence_number: header.sequence_number,
-                        slot: header.slot,
-                        timestamp: header.timestamp,
+            match self.get_market_metadata(&market).await {
+                Ok(metadata) => {
+                    fetched_metadata.insert(market, metadata);
+                }
+                Err(err) => {
+                    unavailable.push(market);
+                    self.warn(ParseWarning::MarketMetadataUnavailable {
                         signature: *sig,
-                        signer: header.signer,
-                        event_index: index as u64,
-                        details: MarketEventDetails::Reduce(Reduce {
-                            order_sequence_number,
-                            maker: maker_id,
-                            price_in_ticks,
-                            base_lots_removed,
-                            base_lots_remaining: 0,
-                            is_full_cancel: true,
-                        }),
-                    }),
-                    _ => {
-                        println!("Unknown event: {:?}", phoenix_event);
-                    }
+                        market,
+                        error: err.to_string(),
+                    });
                 }
             }
         }
-        Some(market_events)
+        let raw_phoenix_events = raw_phoenix_events
+            .into_iter()
+            .filter(|event| !unavailable.contains(&event.header.market))
+            .collect();
+        let DecodedEvents {
+            events, warnings, ..
+        } = phoenix_events_from_raw(sig, raw_phoenix_events, |market| {
+            self.markets
+                .get(market)
+                .or_else(|| fetched_metadata.get(market))
+                .copied()
+        });
+        for warning in warnings {
+            self.warn(warning);
+        }
+        Ok(events)
+    }
+
+    /// Sends the warnings raised while parsing events to `sender`, e.g. so an ingester can
+    /// count and alert on them. Without a channel, warnings are printed to stderr.
+    pub fn set_warning_channel(&mut self, sender: UnboundedSender<ParseWarning>) {
+        self.warnings = Some(sender);
+    }
+
+    fn warn(&self, warning: ParseWarning) {
+        match &self.warnings {
+            // The receiver going away only means nobody is listening anymore
+            Some(sender) => {
+                let _ = sender.send(warning);
+            }
+            None => eprintln!("Warning: {}", warning),
+        }
     }
 
-    pub async fn parse_places(&self, signature: &Signature) -> Vec<PhoenixEvent> {
-        let events = self
-            .parse_events_from_transaction(signature)
+    /// Like [`Self::parse_events_from_transaction`], also decoding the Phoenix instructions of
+    /// the transaction: their type, accounts and the orders they placed.
+    pub async fn parse_transaction(&self, sig: &Signature) -> Result<PhoenixTransaction> {
+        let tx = self
+            .client
+            .get_transaction(sig)
             .await
-            .unwrap_or_default();
-        events
+            .map_err(PhoenixSdkError::rpc)?;
+        if tx.is_err {
+            return Err(PhoenixSdkError::TransactionFailed(sig.to_string()));
+        }
+        self.parse_parsed_transaction(&tx).await
+    }
+
+    /// Like [`Self::parse_events_from_parsed_transaction`], also decoding the Phoenix
+    /// instructions of the transaction.
+    pub async fn parse_parsed_transaction(
+        &self,
+        tx: &ParsedTransaction,
+    ) -> Result<PhoenixTransaction> {
+        Ok(PhoenixTransaction {
+            events: self.parse_events_from_parsed_transaction(tx).await?,
+            instructions: self.core.parse_instructions_from_transaction(tx)?,
+        })
+    }
+
+    pub async fn parse_places(&self, signature: &Signature) -> Result<Vec<PhoenixEvent>> {
+        let events = self.parse_events_from_transaction(signature).await?;
+        Ok(events
             .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Place(..) => Some(event),
+            .filter_map(|event| match event.details {
+                MarketEventDetails::Place(..) => Some(event.clone()),
                 _ => None,
             })
-            .collect::<Vec<PhoenixEvent>>()
+            .collect::<Vec<PhoenixEvent>>())
     }
 
-    pub async fn parse_cancels(&self, signature: &Signature) -> Vec<PhoenixEvent> {
-        let events = self
-            .parse_events_from_transaction(signature)
-            .await
-            .unwrap_or_default();
-        events
+    pub async fn parse_cancels(&self, signature: &Signature) -> Result<Vec<PhoenixEvent>> {
+        let events = self.parse_events_from_transaction(signature).await?;
+        Ok(events
             .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Reduce(..) => Some(event),
+            .filter_map(|event| match event.details {
+                MarketEventDetails::Reduce(..) => Some(event.clone()),
                 _ => None,
             })
-            .collect::<Vec<PhoenixEvent>>()
+            .collect::<Vec<PhoenixEvent>>())
     }
 
-    pub async fn parse_fills(&self, signature: &Signature) -> Vec<PhoenixEvent> {
-        let events = self
-            .parse_events_from_transaction(signature)
-            .await
-            .unwrap_or_default();
-        events
+    pub async fn parse_fills(&self, signature: &Signature) -> Result<Vec<PhoenixEvent>> {
+        let events = self.parse_events_from_transaction(signature).await?;
+        Ok(events
             .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Fill(..) => Some(event),
+            .filter_map(|event| match event.details {
+                MarketEventDetails::Fill(..) => Some(event.clone()),
                 _ => None,
             })
-            .collect::<Vec<PhoenixEvent>>()
+            .collect::<Vec<PhoenixEvent>>())
     }
 
     pub async fn parse_fills_and_places(
         &self,
         signature: &Signature,
-    ) -> (Vec<PhoenixEvent>, Vec<PhoenixEvent>) {
-        let events = self
-            .parse_events_from_transaction(signature)
-            .await
-            .unwrap_or_default();
+    ) -> Result<(Vec<PhoenixEvent>, Vec<PhoenixEvent>)> {
+        let events = self.parse_events_from_transaction(signature).await?;
         let fills = events
             .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Fill(..) => Some(event),
+            .filter_map(|event| match event.details {
+                MarketEventDetails::Fill(..) => Some(event.clone()),
                 _ => None,
             })
             .collect::<Vec<PhoenixEvent>>();
         let places = events
             .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Place(..) => Some(event),
+            .filter_map(|event| match event.details {
+                MarketEventDetails::Place(..) => Some(event.clone()),
                 _ => None,
             })
             .collect::<Vec<PhoenixEvent>>();
 
-        (fills, places)
+        Ok((fills, places))
+    }
+
+    /// Signs and sends a single instruction. A rejected transaction is reported as
+    /// `InsufficientFunds` or `TransactionFailed` rather than as an RPC failure.
+    async fn send_instruction(&self, instruction: Instruction) -> Result<Signature> {
+        self.client
+            .sign_send_instructions(vec![instruction], vec![])
+            .await
+            .map_err(send_error)
     }
 
     pub async fn send_ioc(
@@ -711,15 +691,14 @@ impl SDKClient {
         price: u64,
         side: Side,
         size: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_ioc_ix(market_key, price, side, size).ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let new_order_ix = self.get_ioc_ix(market_key, price, side, size)?;
+        let signature = self.send_instruction(new_order_ix).await?;
+        let fills = self
+            .parse_fills(&signature)
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, fills))
     }
 
     pub async fn send_fok_buy(
@@ -727,18 +706,15 @@ impl SDKClient {
         market_key: &Pubkey,
         price: u64,
         size_in_quote_lots: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self
-            .get_fok_buy_ix(market_key, price, size_in_quote_lots)
-            .ok()?;
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let new_order_ix = self.get_fok_buy_ix(market_key, price, size_in_quote_lots)?;
 
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+        let signature = self.send_instruction(new_order_ix).await?;
+        let fills = self
+            .parse_fills(&signature)
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, fills))
     }
 
     pub async fn send_fok_sell(
@@ -746,18 +722,15 @@ impl SDKClient {
         market_key: &Pubkey,
         price: u64,
         size_in_base_lots: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self
-            .get_fok_sell_ix(market_key, price, size_in_base_lots)
-            .ok()?;
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let new_order_ix = self.get_fok_sell_ix(market_key, price, size_in_base_lots)?;
 
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+        let signature = self.send_instruction(new_order_ix).await?;
+        let fills = self
+            .parse_fills(&signature)
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, fills))
     }
 
     pub async fn send_ioc_with_slippage(
@@ -766,17 +739,15 @@ impl SDKClient {
         lots_in: u64,
         min_lots_out: u64,
         side: Side,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self
-            .get_ioc_with_slippage_ix(market_key, lots_in, min_lots_out, side)
-            .ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let new_order_ix =
+            self.get_ioc_with_slippage_ix(market_key, lots_in, min_lots_out, side)?;
+        let signature = self.send_instruction(new_order_ix).await?;
+        let fills = self
+            .parse_fills(&signature)
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, fills))
     }
 
     pub async fn send_post_only(
@@ -785,15 +756,14 @@ impl SDKClient {
         price: u64,
         side: Side,
         size: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_post_only_ix(market_key, price, side, size).ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let new_order_ix = self.get_post_only_ix(market_key, price, side, size)?;
+        let signature = self.send_instruction(new_order_ix).await?;
+        let fills = self
+            .parse_fills(&signature)
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, fills))
     }
 
     pub async fn send_limit_order(
@@ -802,33 +772,29 @@ impl SDKClient {
         price: u64,
         side: Side,
         size: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>, Vec<PhoenixEvent>)> {
-        let new_order_ix = self
-            .get_limit_order_ix(market_key, price, side, size)
-            .ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+    ) -> Result<(Signature, Vec<PhoenixEvent>, Vec<PhoenixEvent>)> {
+        let new_order_ix = self.get_limit_order_ix(market_key, price, side, size)?;
+        let signature = self.send_instruction(new_order_ix).await?;
+        let (fills, places) = self
+            .parse_fills_and_places(&signature)
             .await
-            .ok()?;
-        let (fills, places) = self.parse_fills_and_places(&signature).await;
-        Some((signature, places, fills))
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, places, fills))
     }
 
     pub async fn send_cancel_ids(
         &self,
         market_key: &Pubkey,
         ids: Vec<FIFOOrderId>,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let cancel_ix = self.get_cancel_ids_ix(market_key, ids).ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_ix], vec![])
-            .await
-            .ok()?;
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let cancel_ix = self.get_cancel_ids_ix(market_key, ids)?;
+        let signature = self.send_instruction(cancel_ix).await?;
 
-        let cancels = self.parse_cancels(&signature).await;
-        Some((signature, cancels))
+        let cancels = self
+            .parse_cancels(&signature)
+            .await
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, cancels))
     }
 
     pub async fn send_cancel_up_to(
@@ -836,32 +802,74 @@ impl SDKClient {
         market_key: &Pubkey,
         tick_limit: Option<u64>,
         side: Side,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let cancel_ix = self
-            .get_cancel_up_to_ix(market_key, tick_limit, side)
-            .ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_ix], vec![])
-            .await
-            .ok()?;
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let cancel_ix = self.get_cancel_up_to_ix(market_key, tick_limit, side)?;
+        let signature = self.send_instruction(cancel_ix).await?;
 
-        let cancels = self.parse_cancels(&signature).await;
-        Some((signature, cancels))
+        let cancels = self
+            .parse_cancels(&signature)
+            .await
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, cancels))
     }
 
     pub async fn send_cancel_all(
         &self,
         market_key: &Pubkey,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let cancel_all_ix = self.get_cancel_all_ix(market_key).ok()?;
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_all_ix], vec![])
+    ) -> Result<(Signature, Vec<PhoenixEvent>)> {
+        let cancel_all_ix = self.get_cancel_all_ix(market_key)?;
+        let signature = self.send_instruction(cancel_all_ix).await?;
+
+        let cancels = self
+            .parse_cancels(&signature)
             .await
-            .ok()?;
+            .map_err(|e| events_unavailable(signature, e))?;
+        Ok((signature, cancels))
+    }
+}
+
+/// Splits market account data into the market header and the market itself.
+pub(crate) fn split_market_account<'a>(
+    market_key: &Pubkey,
+    data: &'a [u8],
+) -> Result<(&'a MarketHeader, &'a [u8])> {
+    if data.len() < size_of::<MarketHeader>() {
+        return Err(PhoenixSdkError::InvalidMarket(format!(
+            "{} is too small to be a market account",
+            market_key
+        )));
+    }
+    let (header_bytes, bytes) = data.split_at(size_of::<MarketHeader>());
+    let header = bytemuck::try_from_bytes::<MarketHeader>(header_bytes).map_err(|e| {
+        PhoenixSdkError::decode(format!("market header of {}: {:?}", market_key, e))
+    })?;
+    Ok((header, bytes))
+}
 
-        let cancels = self.parse_cancels(&signature).await;
-        Some((signature, cancels))
+pub(crate) fn invalid_market(market_key: &Pubkey, err: impl std::fmt::Debug) -> PhoenixSdkError {
+    PhoenixSdkError::InvalidMarket(format!("{}: {:?}", market_key, err))
+}
+
+fn send_error(err: impl Into<anyhow::Error>) -> PhoenixSdkError {
+    let err = err.into();
+    let transaction_error = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ClientError>())
+        .and_then(ClientError::get_transaction_error);
+    match transaction_error {
+        Some(
+            tx_err @ (TransactionError::InsufficientFundsForFee
+            | TransactionError::InsufficientFundsForRent { .. }
+            | TransactionError::InstructionError(_, InstructionError::InsufficientFunds)),
+        ) => PhoenixSdkError::InsufficientFunds(tx_err.to_string()),
+        Some(tx_err) => PhoenixSdkError::TransactionFailed(tx_err.to_string()),
+        None => PhoenixSdkError::RpcError(err),
+    }
+}
+
+fn events_unavailable(signature: Signature, source: PhoenixSdkError) -> PhoenixSdkError {
+    PhoenixSdkError::EventsUnavailable {
+        signature,
+        source: Box::new(source),
     }
-}
\ No newline at end of file
+}
@@ -4,9 +4,8 @@ use ellipsis_client::EllipsisClient;
 use phoenix::program::{
     dispatch_market, get_seat_address, status::SeatApprovalStatus, MarketHeader, Seat,
 };
-use phoenix_sdk_core::ata_utils::{
-    get_associated_token_address, create_associated_token_account,
-};
+use phoenix_sdk_core::ata_utils::{create_associated_token_account, get_associated_token_address};
+use phoenix_sdk_core::error::{PhoenixSdkError, Result};
 use phoenix_seat_manager::{
     get_seat_manager_address,
     instruction_builders::{
@@ -44,14 +43,15 @@ pub async fn create_claim_seat_ix_if_needed(
     client: &EllipsisClient,
     market_pubkey: &Pubkey,
     trader: &Pubkey,
-) -> anyhow::Result<Vec<Instruction>> {
+) -> Result<Vec<Instruction>> {
     let seat_address = get_seat_address(market_pubkey, trader).0;
     let seat_account = client.get_account(&seat_address).await;
 
     // If the seat is found, is initialized, and is already Approved, return early.
     if let Ok(seat_account) = seat_account {
         if !seat_account.data.is_empty() {
-            let seat_struct = bytemuck::from_bytes::<Seat>(seat_account.data.as_slice());
+            let seat_struct = bytemuck::try_from_bytes::<Seat>(seat_account.data.as_slice())
+                .map_err(|e| PhoenixSdkError::decode(format!("seat {}: {:?}", seat_address, e)))?;
             // If the seat account is found and is already approved, return early.
             if SeatApprovalStatus::from(seat_struct.approval_status) == SeatApprovalStatus::Approved
             {
@@ -74,35 +74,47 @@ pub async fn create_claim_seat_ix_if_needed(
 pub async fn get_evictable_trader_ix(
     client: &EllipsisClient,
     market_pubkey: &Pubkey,
-) -> anyhow::Result<Option<Instruction>> {
-    let market_bytes = client.get_account_data(market_pubkey).await?;
+) -> Result<Option<Instruction>> {
+    let market_bytes = client
+        .get_account_data(market_pubkey)
+        .await
+        .map_err(PhoenixSdkError::rpc)?;
+    if market_bytes.len() < size_of::<MarketHeader>() {
+        return Err(PhoenixSdkError::InvalidMarket(format!(
+            "{} is too small to be a market account",
+            market_pubkey
+        )));
+    }
     let (header_bytes, market_bytes) = market_bytes.split_at(size_of::<MarketHeader>());
-    let market_header = bytemuck::try_from_bytes::<MarketHeader>(header_bytes)
-        .map_err(|e| anyhow::anyhow!("Error deserializing market header. Error: {:?}", e))?;
+    let market_header = bytemuck::try_from_bytes::<MarketHeader>(header_bytes).map_err(|e| {
+        PhoenixSdkError::decode(format!("market header of {}: {:?}", market_pubkey, e))
+    })?;
+
+    let market =
+        dispatch_market::load_with_dispatch(&market_header.market_size_params, market_bytes)
+            .map_err(|e| PhoenixSdkError::InvalidMarket(format!("{}: {:?}", market_pubkey, e)))?
+            .inner;
 
     let max_traders = market_header.market_size_params.num_seats;
-    let num_traders =
-        dispatch_market::load_with_dispatch(&market_header.market_size_params, market_bytes)?
-            .inner
-            .get_registered_traders()
-            .len() as u64;
+    let num_traders = market.get_registered_traders().len() as u64;
 
     // If the market's trader state is full, evict a trader to make room for a new trader.
     if num_traders == max_traders {
-        let trader_tree =
-            dispatch_market::load_with_dispatch(&market_header.market_size_params, market_bytes)?
-                .inner
-                .get_registered_traders()
-                .iter()
-                .map(|(k, v)| (*k, *v))
-                .collect::<BTreeMap<_, _>>();
+        let trader_tree = market
+            .get_registered_traders()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect::<BTreeMap<_, _>>();
 
         let seat_manager_address = get_seat_manager_address(market_pubkey).0;
-        let seat_manager_account = client.get_account_data(&seat_manager_address).await?;
-        let seat_manager_struct = bytemuck::try_from_bytes::<SeatManager>(
-            seat_manager_account.as_slice(),
-        )
-        .map_err(|e| anyhow::anyhow!("Error deserializing seat manager data. Error: {:?}", e))?;
+        let seat_manager_account = client
+            .get_account_data(&seat_manager_address)
+            .await
+            .map_err(PhoenixSdkError::rpc)?;
+        let seat_manager_struct =
+            bytemuck::try_from_bytes::<SeatManager>(seat_manager_account.as_slice()).map_err(
+                |e| PhoenixSdkError::decode(format!("seat manager of {}: {:?}", market_pubkey, e)),
+            )?;
 
         //Find a seat to evict (a trader with no locked base or quote lots) and evict trader.
         for (trader_pubkey, trader_state) in trader_tree.iter() {
@@ -125,9 +137,7 @@ pub async fn get_evictable_trader_ix(
                 )));
             }
         }
-        return Err(anyhow::anyhow!(
-            "Trader state is full but unable to find a trader with no locked lots to evict."
-        ));
+        return Err(PhoenixSdkError::MarketFull(*market_pubkey));
     };
     Ok(None)
 }
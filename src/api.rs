@@ -1,36 +1,65 @@
 // main.rs
-use crate::database::{check_and_update_credits, fetch_ohlc_data};
+use crate::config::Config;
+use crate::database::{
+    check_and_update_credits, fetch_ohlc_data, fetch_orderbook_snapshot_at,
+    fetch_orderbook_snapshots, OrderbookSnapshotRow,
+};
+use crate::error::{ApiError, ErrorBody};
+use crate::health::{healthz_handler, metrics_handler, readyz_handler, track_request_latency};
+use crate::metrics::metrics;
+use crate::openapi::ApiDoc;
+use crate::sink::{format_event, BroadcastSink};
+use crate::udf;
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::Json;
 use axum::{
     extract::{Query, State},
-    http::StatusCode,
+    middleware,
     routing::get,
     Router,
 };
 use clickhouse::Client;
+use futures::{Stream, StreamExt};
+use phoenix_sdk::orderbook_watcher::{LevelChange, OrderbookDiff, OrderbookWatcher};
+use solana_sdk::pubkey::Pubkey;
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
 use tokio;
 use tokio::sync::Mutex;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
 #[derive(Clone)]
 pub struct AppState {
+    pub config: Arc<Config>,
     pub clickhouse_client: Client,
     pub rate_limits: Arc<Mutex<HashMap<String, (u64, tokio::time::Instant)>>>,
+    /// Set once the live orderbooks are loaded, if enabled.
+    pub orderbook_watcher: Arc<OnceLock<OrderbookWatcher>>,
+    /// Events published by the `broadcast` sink, if configured.
+    pub events: BroadcastSink,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct OHLCQuery {
+    /// The user ID for rate limiting and credit checking.
     pub user_id: String,
+    /// The base token mint address.
     pub base_token_mint: String,
+    /// The quote token mint address.
     pub quote_token_mint: String,
+    /// The start timestamp for the query (unix seconds).
     pub start_time: i64,
+    /// The end timestamp for the query (unix seconds).
     pub end_time: i64,
+    /// The interval for OHLC data aggregation (`1m`, `1h`, `1d`).
     pub interval: String,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, serde::Serialize, ToSchema)]
 pub struct OHLCResponse {
     pub open: u64,
     pub high: u64,
@@ -38,22 +67,266 @@ pub struct OHLCResponse {
     pub close: u64,
 }
 
-pub async fn start_api_server() -> Result<(), Box<dyn std::error::Error>> {
-    let clickhouse_client = Client::default()
-        .with_url("http://localhost:8123")
-        .with_user("default")
-        .with_password("password");
+/// Most snapshots returned by `/orderbook/snapshots` per request.
+const MAX_ORDERBOOK_SNAPSHOTS: u64 = 1000;
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderbookSnapshotQuery {
+    /// The market address.
+    pub market: String,
+    /// Returns the latest snapshot taken at or before this time (unix seconds).
+    pub time: i64,
+    /// Include every resting order, if the snapshot was stored with them.
+    #[serde(default)]
+    pub include_orders: bool,
+}
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderbookSnapshotsQuery {
+    /// The market address.
+    pub market: String,
+    /// The start timestamp for the query (unix seconds).
+    pub start_time: i64,
+    /// The end timestamp for the query (unix seconds).
+    pub end_time: i64,
+    /// Include every resting order, if the snapshots were stored with them.
+    #[serde(default)]
+    pub include_orders: bool,
+}
+
+/// The orders resting at one price. `price` is in quote units per base unit and `size` in base
+/// units.
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct OrderbookLevel {
+    pub price_in_ticks: u64,
+    pub price: f64,
+    pub base_lots: u64,
+    pub size: f64,
+    pub orders: u32,
+}
+
+/// Base lots resting within `bps` basis points of the mid, on each side.
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct OrderbookDepth {
+    pub bps: u32,
+    pub bid_base_lots: u64,
+    pub ask_base_lots: u64,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct OrderbookOrder {
+    /// `Bid` or `Ask`.
+    pub side: String,
+    pub price_in_ticks: u64,
+    pub order_sequence_number: u64,
+    pub maker: String,
+    pub base_lots: u64,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct OrderbookSnapshotResponse {
+    pub market: String,
+    /// When the snapshot was taken (unix milliseconds).
+    pub timestamp_ms: i64,
+    /// The slot the market account was read at.
+    pub slot: u64,
+    pub sequence_number: u64,
+    pub best_bid_in_ticks: Option<u64>,
+    pub best_ask_in_ticks: Option<u64>,
+    pub mid_price_in_ticks: Option<f64>,
+    /// The mid in quote units per base unit.
+    pub mid_price: Option<f64>,
+    pub spread_in_ticks: Option<u64>,
+    pub spread_bps: Option<f64>,
+    /// The best levels of each side, best first.
+    pub bids: Vec<OrderbookLevel>,
+    pub asks: Vec<OrderbookLevel>,
+    pub depth: Vec<OrderbookDepth>,
+    /// Every resting order, when requested and stored.
+    pub orders: Vec<OrderbookOrder>,
+}
+
+impl From<OrderbookSnapshotRow> for OrderbookSnapshotResponse {
+    fn from(row: OrderbookSnapshotRow) -> Self {
+        let price = |ticks: f64| ticks * row.quote_units_per_raw_base_unit_per_tick;
+        let levels = |prices: &[u64], sizes: &[u64], orders: &[u32]| {
+            prices
+                .iter()
+                .zip(sizes)
+                .zip(orders)
+                .map(|((&price_in_ticks, &base_lots), &orders)| OrderbookLevel {
+                    price_in_ticks,
+                    price: price(price_in_ticks as f64),
+                    base_lots,
+                    size: base_lots as f64 * row.raw_base_units_per_base_lot,
+                    orders,
+                })
+                .collect::<Vec<_>>()
+        };
+        let bids = levels(
+            &row.bid_prices_in_ticks,
+            &row.bid_sizes_in_base_lots,
+            &row.bid_order_counts,
+        );
+        let asks = levels(
+            &row.ask_prices_in_ticks,
+            &row.ask_sizes_in_base_lots,
+            &row.ask_order_counts,
+        );
+        let depth = row
+            .depth_bps
+            .iter()
+            .zip(
+                row.bid_depth_in_base_lots
+                    .iter()
+                    .zip(&row.ask_depth_in_base_lots),
+            )
+            .map(|(&bps, (&bid_base_lots, &ask_base_lots))| OrderbookDepth {
+                bps,
+                bid_base_lots,
+                ask_base_lots,
+            })
+            .collect();
+        OrderbookSnapshotResponse {
+            mid_price: row.mid_price_in_ticks.map(price),
+            market: row.market,
+            timestamp_ms: row.timestamp_ms,
+            slot: row.slot,
+            sequence_number: row.sequence_number,
+            best_bid_in_ticks: row.best_bid_in_ticks,
+            best_ask_in_ticks: row.best_ask_in_ticks,
+            mid_price_in_ticks: row.mid_price_in_ticks,
+            spread_in_ticks: row.spread_in_ticks,
+            spread_bps: row.spread_bps,
+            bids,
+            asks,
+            depth,
+            orders: row
+                .orders
+                .into_iter()
+                .map(
+                    |(side, price_in_ticks, order_sequence_number, maker, base_lots)| {
+                        OrderbookOrder {
+                            side,
+                            price_in_ticks,
+                            order_sequence_number,
+                            maker,
+                            base_lots,
+                        }
+                    },
+                )
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderbookStreamQuery {
+    /// The market address.
+    pub market: String,
+}
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventStreamQuery {
+    /// Only stream events of this market address.
+    pub market: Option<String>,
+}
+
+/// A change to one price level. `base_lots` is the total size now resting at the price, zero
+/// once the level is removed.
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct LevelChangeResponse {
+    /// `added`, `changed` or `removed`.
+    pub change: &'static str,
+    /// `Bid` or `Ask`.
+    pub side: String,
+    pub price_in_ticks: u64,
+    pub base_lots: u64,
+}
 
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct OrderbookDiffResponse {
+    pub market: String,
+    /// The slot of the account update.
+    pub slot: u64,
+    /// The market's sequence number after the update.
+    pub sequence_number: u64,
+    /// Bids first, then asks, each by price.
+    pub changes: Vec<LevelChangeResponse>,
+}
+
+impl From<OrderbookDiff> for OrderbookDiffResponse {
+    fn from(diff: OrderbookDiff) -> Self {
+        let changes = diff
+            .changes
+            .into_iter()
+            .map(|change| {
+                let (change, side, price_in_ticks, base_lots) = match change {
+                    LevelChange::Added {
+                        side,
+                        price_in_ticks,
+                        base_lots,
+                    } => ("added", side, price_in_ticks, base_lots),
+                    LevelChange::Changed {
+                        side,
+                        price_in_ticks,
+                        base_lots,
+                    } => ("changed", side, price_in_ticks, base_lots),
+                    LevelChange::Removed {
+                        side,
+                        price_in_ticks,
+                    } => ("removed", side, price_in_ticks, 0),
+                };
+                LevelChangeResponse {
+                    change,
+                    side: format!("{:?}", side),
+                    price_in_ticks,
+                    base_lots,
+                }
+            })
+            .collect();
+        OrderbookDiffResponse {
+            market: diff.market.to_string(),
+            slot: diff.slot,
+            sequence_number: diff.sequence_number,
+            changes,
+        }
+    }
+}
+
+pub async fn start_api_server(
+    config: Arc<Config>,
+    clickhouse_client: Client,
+    orderbook_watcher: Arc<OnceLock<OrderbookWatcher>>,
+    events: BroadcastSink,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let addr: SocketAddr = config.server.bind_address;
     let state = AppState {
+        config,
         clickhouse_client,
         rate_limits: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        orderbook_watcher,
+        events,
     };
 
     let app = Router::new()
         .route("/ohlc", get(ohlc_handler))
-        .with_state(state);
+        .route("/orderbook/snapshot", get(orderbook_snapshot_handler))
+        .route("/orderbook/snapshots", get(orderbook_snapshots_handler))
+        .route("/orderbook/stream", get(orderbook_stream_handler))
+        .route("/events/stream", get(event_stream_handler))
+        .nest("/udf", udf::router())
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler))
+        .layer(middleware::from_fn(track_request_latency))
+        .with_state(state)
+        .merge(SwaggerUi::new("/swagger-ui").url("/openapi.json", ApiDoc::openapi()));
 
-    let addr: SocketAddr = "0.0.0.0:8080".parse()?;
     println!("Server is running at http://{}", addr);
 
     axum_server::bind(addr)
@@ -64,59 +337,225 @@ pub async fn start_api_server() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn ohlc_handler(
+/// Fetch OHLC data for a token pair.
+#[utoipa::path(
+    get,
+    path = "/ohlc",
+    params(OHLCQuery),
+    responses(
+        (status = 200, description = "OHLC data for the requested range", body = OHLCResponse),
+        (status = 400, description = "Invalid time range or interval", body = ErrorBody),
+        (status = 402, description = "The user has no credits left", body = ErrorBody),
+        (status = 404, description = "No trades in the requested range", body = ErrorBody),
+        (status = 429, description = "Rate limit exceeded", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+pub(crate) async fn ohlc_handler(
     Query(params): Query<OHLCQuery>,
     State(state): State<AppState>,
-) -> Result<Json<OHLCResponse>, (StatusCode, String)> {
+) -> Result<Json<OHLCResponse>, ApiError> {
     // Input validation
     if params.start_time >= params.end_time {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "start_time must be less than end_time".to_string(),
-        ));
+        return Err(ApiError::InvalidTimeRange {
+            start_time: params.start_time,
+            end_time: params.end_time,
+        });
     }
 
     if !["1m", "1h", "1d"].contains(&params.interval.as_str()) {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            "Invalid interval. Supported values: 1m, 1h, 1d".to_string(),
-        ));
+        return Err(ApiError::InvalidInterval(params.interval.clone()));
     }
 
     // Rate limit check
+    let plan = state.config.rate_limit.plan_for(&params.user_id);
+    let window = tokio::time::Duration::from_secs(plan.window_secs);
     let mut rate_limits = state.rate_limits.lock().await;
     let user_limit = rate_limits
         .entry(params.user_id.clone())
-        .or_insert((10, tokio::time::Instant::now()));
+        .or_insert((plan.requests, tokio::time::Instant::now()));
 
-    // Reset rate limit if more than a minute has passed
-    if user_limit.1.elapsed() >= tokio::time::Duration::from_secs(60) {
-        *user_limit = (10, tokio::time::Instant::now());
+    // Reset rate limit once the plan's window has passed
+    if user_limit.1.elapsed() >= window {
+        *user_limit = (plan.requests, tokio::time::Instant::now());
     }
 
     if user_limit.0 == 0 {
-        return Err((
-            StatusCode::TOO_MANY_REQUESTS,
-            "Rate limit exceeded. Please try again later.".to_string(),
-        ));
+        metrics().rate_limit_rejections_total.inc();
+        let retry_after = window.saturating_sub(user_limit.1.elapsed());
+        return Err(ApiError::RateLimited {
+            retry_after_secs: retry_after.as_secs().max(1),
+        });
     }
 
     user_limit.0 -= 1;
+    drop(rate_limits);
 
     // Check and update credits
-    if let Err(e) = check_and_update_credits(&state.clickhouse_client, &params.user_id).await {
-        return Err((
-            StatusCode::PAYMENT_REQUIRED,
-            format!("Failed to check or update credits: {}", e),
-        ));
-    }
+    check_and_update_credits(&state.clickhouse_client, &params.user_id).await?;
+    metrics().credits_consumed_total.inc();
 
     // Fetch OHLC data
-    match fetch_ohlc_data(&state.clickhouse_client, &params).await {
-        Ok(ohlc_data) => Ok(Json(ohlc_data)),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to fetch OHLC data: {}", e),
-        )),
+    let ohlc_data = fetch_ohlc_data(&state.clickhouse_client, &params).await?;
+    Ok(Json(ohlc_data))
+}
+
+fn parse_market(market: &str) -> Result<Pubkey, ApiError> {
+    Pubkey::from_str(market).map_err(|_| ApiError::InvalidMarket(market.to_string()))
+}
+
+/// Fetch the orderbook of a market as of a given time, from the latest snapshot taken at or
+/// before it.
+#[utoipa::path(
+    get,
+    path = "/orderbook/snapshot",
+    params(OrderbookSnapshotQuery),
+    responses(
+        (status = 200, description = "The latest snapshot at or before the requested time", body = OrderbookSnapshotResponse),
+        (status = 400, description = "Invalid market address", body = ErrorBody),
+        (status = 404, description = "No snapshot at or before the requested time", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+pub(crate) async fn orderbook_snapshot_handler(
+    Query(params): Query<OrderbookSnapshotQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<OrderbookSnapshotResponse>, ApiError> {
+    let market = parse_market(&params.market)?;
+    let row = fetch_orderbook_snapshot_at(
+        &state.clickhouse_client,
+        &market.to_string(),
+        params.time.saturating_mul(1000),
+        params.include_orders,
+    )
+    .await?
+    .ok_or(ApiError::NoData)?;
+    Ok(Json(row.into()))
+}
+
+/// Fetch the orderbook snapshots of a market taken in a time range, oldest first.
+#[utoipa::path(
+    get,
+    path = "/orderbook/snapshots",
+    params(OrderbookSnapshotsQuery),
+    responses(
+        (status = 200, description = "Up to 1000 snapshots in the requested range", body = [OrderbookSnapshotResponse]),
+        (status = 400, description = "Invalid market address or time range", body = ErrorBody),
+        (status = 404, description = "No snapshots in the requested range", body = ErrorBody),
+        (status = 500, description = "Internal server error", body = ErrorBody),
+    )
+)]
+pub(crate) async fn orderbook_snapshots_handler(
+    Query(params): Query<OrderbookSnapshotsQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OrderbookSnapshotResponse>>, ApiError> {
+    let market = parse_market(&params.market)?;
+    if params.start_time >= params.end_time {
+        return Err(ApiError::InvalidTimeRange {
+            start_time: params.start_time,
+            end_time: params.end_time,
+        });
+    }
+    let rows = fetch_orderbook_snapshots(
+        &state.clickhouse_client,
+        &market.to_string(),
+        params.start_time.saturating_mul(1000),
+        params.end_time.saturating_mul(1000),
+        params.include_orders,
+        MAX_ORDERBOOK_SNAPSHOTS,
+    )
+    .await?;
+    if rows.is_empty() {
+        return Err(ApiError::NoData);
+    }
+    Ok(Json(rows.into_iter().map(Into::into).collect()))
+}
+
+/// Stream the changes to the orderbook of a market as server-sent `diff` events. The first
+/// event adds every level of the current book, and each later one carries the levels an update
+/// of the market account changed. A client that falls too far behind is disconnected, and
+/// starts over from the current book when it reconnects.
+#[utoipa::path(
+    get,
+    path = "/orderbook/stream",
+    params(OrderbookStreamQuery),
+    responses(
+        (status = 200, description = "A stream of `diff` events, each with an OrderbookDiffResponse", content_type = "text/event-stream", body = OrderbookDiffResponse),
+        (status = 400, description = "Invalid market address", body = ErrorBody),
+        (status = 404, description = "The market is not watched, or live orderbooks are disabled or still loading", body = ErrorBody),
+    )
+)]
+pub(crate) async fn orderbook_stream_handler(
+    Query(params): Query<OrderbookStreamQuery>,
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let market = parse_market(&params.market)?;
+    let watcher = state.orderbook_watcher.get().ok_or(ApiError::NoData)?;
+    // Subscribe before reading the book, so that no update falls in between
+    let diffs = watcher.subscribe();
+    let snapshot = watcher.snapshot(&market).ok_or(ApiError::NoData)?;
+    let sequence_number = snapshot.sequence_number;
+    let later_diffs = futures::stream::unfold(diffs, move |mut diffs| async move {
+        loop {
+            match diffs.recv().await {
+                Ok(diff) if diff.market == market && diff.sequence_number > sequence_number => {
+                    return Some((diff, diffs));
+                }
+                Ok(_) => continue,
+                // Lagged behind and missed diffs, or the watcher is gone
+                Err(_) => return None,
+            }
+        }
+    });
+    let events = futures::stream::iter([OrderbookDiff::from_snapshot(&snapshot)])
+        .chain(later_diffs)
+        .map(|diff| {
+            Event::default()
+                .event("diff")
+                .json_data(OrderbookDiffResponse::from(diff))
+        });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Stream the Phoenix events decoded by ingestion as server-sent `event` events, in the JSON of
+/// the `stdout` and `file` sinks. Requires the `broadcast` sink. A client that falls too far
+/// behind is disconnected.
+#[utoipa::path(
+    get,
+    path = "/events/stream",
+    params(EventStreamQuery),
+    responses(
+        (status = 200, description = "A stream of `event` events, each with a decoded Phoenix event", content_type = "text/event-stream"),
+        (status = 400, description = "Invalid market address", body = ErrorBody),
+        (status = 404, description = "The broadcast sink is not configured", body = ErrorBody),
+    )
+)]
+pub(crate) async fn event_stream_handler(
+    Query(params): Query<EventStreamQuery>,
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, ApiError> {
+    let market = params.market.as_deref().map(parse_market).transpose()?;
+    if !state
+        .config
+        .ingestion
+        .sinks
+        .iter()
+        .any(|sink| sink == "broadcast")
+    {
+        return Err(ApiError::NoData);
     }
+    let events = futures::stream::unfold(state.events.subscribe(), move |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) if market.map_or(true, |market| event.event.market == market) => {
+                    return Some((event, events));
+                }
+                Ok(_) => continue,
+                // Lagged behind and missed events, or ingestion is gone
+                Err(_) => return None,
+            }
+        }
+    })
+    .map(|event| Ok(Event::default().event("event").data(format_event(&event))));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
 }
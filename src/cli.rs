@@ -0,0 +1,154 @@
+// cli.rs
+use clap::{Parser, Subcommand};
+use clickhouse::Client;
+use solana_sdk::signature::Signature;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::database::{fetch_failed_signature, fetch_failed_signatures};
+use crate::dead_letter::replay_signature;
+use crate::fixtures::{record_fixtures, replay_fixtures};
+use crate::rpc::RpcPool;
+use crate::solana::{replay_pipeline, FetchError};
+
+#[derive(Debug, Parser)]
+#[clap(about = "Phoenix transaction ingestion and OHLC API")]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run ingestion and the API server (the default)
+    Serve,
+    /// Inspect and replay signatures that failed to be ingested
+    #[clap(subcommand)]
+    FailedSignatures(FailedSignaturesCommand),
+    /// Record transactions to disk and replay them through the ingestion pipeline
+    #[clap(subcommand)]
+    Fixtures(FixturesCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FailedSignaturesCommand {
+    /// List failed signatures, oldest first
+    List {
+        #[clap(long, default_value = "50")]
+        limit: u64,
+        /// Include signatures that have since been replayed successfully
+        #[clap(long)]
+        all: bool,
+    },
+    /// Reprocess failed signatures now, regardless of their attempt count
+    Replay {
+        /// Signatures to replay; they do not have to be in the dead-letter store
+        signatures: Vec<String>,
+        /// Replay every unresolved signature
+        #[clap(long, conflicts_with = "signatures")]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FixturesCommand {
+    /// Fetch transactions, and the headers of the markets they touch, into a fixture directory
+    Record {
+        #[clap(long)]
+        dir: PathBuf,
+        signatures: Vec<String>,
+    },
+    /// Decode recorded transactions into the configured sinks
+    Replay {
+        /// Fixture files, or directories of them
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+    },
+}
+
+pub async fn run_failed_signatures_command(
+    command: FailedSignaturesCommand,
+    rpc_pool: &RpcPool,
+    clickhouse_client: &Client,
+) -> Result<(), FetchError> {
+    match command {
+        FailedSignaturesCommand::List { limit, all } => {
+            let entries = fetch_failed_signatures(clickhouse_client, all, None, limit).await?;
+            println!(
+                "{:<88} {:>10} {:<6} {:>8} {:<8} error",
+                "signature", "slot", "stage", "attempts", "resolved"
+            );
+            for entry in entries {
+                println!(
+                    "{:<88} {:>10} {:<6} {:>8} {:<8} {}",
+                    entry.signature,
+                    entry.slot,
+                    entry.stage,
+                    entry.attempts,
+                    entry.resolved,
+                    entry.error
+                );
+            }
+        }
+        FailedSignaturesCommand::Replay { signatures, all } => {
+            let targets = if all {
+                fetch_failed_signatures(clickhouse_client, false, None, u64::MAX)
+                    .await?
+                    .into_iter()
+                    .map(|entry| (entry.signature, entry.slot))
+                    .collect::<Vec<_>>()
+            } else {
+                let mut targets = vec![];
+                for signature in signatures {
+                    let slot = fetch_failed_signature(clickhouse_client, &signature)
+                        .await?
+                        .map_or(0, |entry| entry.slot);
+                    targets.push((signature, slot));
+                }
+                targets
+            };
+
+            let mut pipeline = replay_pipeline(rpc_pool, clickhouse_client).await?;
+            for (signature, slot) in targets {
+                match replay_signature(rpc_pool, clickhouse_client, &mut pipeline, &signature, slot)
+                    .await
+                {
+                    Ok(()) => println!("{}: replayed", signature),
+                    Err(err) => println!("{}: failed: {}", signature, err),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn run_fixtures_command(
+    command: FixturesCommand,
+    rpc_pool: &Arc<RpcPool>,
+    config: &Config,
+    clickhouse_client: &Client,
+) -> Result<(), FetchError> {
+    match command {
+        FixturesCommand::Record { dir, signatures } => {
+            let signatures = signatures
+                .iter()
+                .map(|signature| {
+                    Signature::from_str(signature)
+                        .map_err(|_| FetchError::InvalidSignature(signature.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            record_fixtures(rpc_pool, &signatures, &dir).await?;
+            println!(
+                "Recorded {} transactions in {}",
+                signatures.len(),
+                dir.display()
+            );
+        }
+        FixturesCommand::Replay { paths } => {
+            replay_fixtures(&paths, rpc_pool, config, clickhouse_client).await?;
+        }
+    }
+    Ok(())
+}
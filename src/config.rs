@@ -0,0 +1,690 @@
+// config.rs
+use clickhouse::Client;
+use serde::Deserialize;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::env;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Default location of the configuration file, overridable with `CONFIG_PATH`.
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(String, std::io::Error),
+
+    #[error("Failed to parse config file {0}: {1}")]
+    Parse(String, toml::de::Error),
+
+    #[error("Invalid value for environment variable {0}: {1}")]
+    InvalidEnv(&'static str, String),
+
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+}
+
+/// Application configuration, loaded once at startup and passed down to every subsystem.
+///
+/// Values are read from a TOML file (see `config.example.toml`) and then overridden by
+/// environment variables, so secrets never have to live in the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub rpc: RpcConfig,
+    pub clickhouse: ClickHouseConfig,
+    pub server: ServerConfig,
+    pub rate_limit: RateLimitConfig,
+    pub ingestion: IngestionConfig,
+    pub dead_letter: DeadLetterConfig,
+    pub geyser: GeyserConfig,
+    pub snapshots: SnapshotConfig,
+    pub live_orderbook: LiveOrderbookConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    /// Solana JSON-RPC endpoints, in order of preference.
+    pub urls: Vec<String>,
+    /// Commitment level used for RPC reads: `processed`, `confirmed` or `finalized`.
+    pub commitment: String,
+    /// Per-request timeout; a timed out request fails over to the next endpoint.
+    pub timeout_ms: u64,
+    /// Consecutive failures after which an endpoint is skipped for `cooldown_secs`.
+    pub failure_threshold: u32,
+    pub cooldown_secs: u64,
+    /// Rounds of retries, with exponential backoff, once every endpoint has failed.
+    pub max_retries: u32,
+    pub backoff_base_ms: u64,
+    pub backoff_max_ms: u64,
+    /// Client-side budget of RPC requests per second, shared by every ingestion worker.
+    pub max_requests_per_second: u32,
+    /// Solana PubSub WebSocket endpoint. Derived from the first RPC URL when unset.
+    pub ws_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClickHouseConfig {
+    pub url: String,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitPlan {
+    /// Number of requests allowed per window.
+    pub requests: u64,
+    /// Length of the window in seconds.
+    pub window_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Plan applied to users without an explicit assignment.
+    pub default_plan: String,
+    pub plans: BTreeMap<String, RateLimitPlan>,
+    /// Maps user ids to plan names.
+    pub users: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IngestionConfig {
+    /// Address of the Phoenix program whose transactions are ingested.
+    pub program_id: String,
+    /// Maximum number of signatures fetched per `getSignaturesForAddress` call (1-1000).
+    pub signature_batch_size: usize,
+    /// Readiness fails once ingestion falls this many slots behind the cluster tip.
+    pub max_lag_slots: i64,
+    /// How often fills ingested below `finalized` are promoted or rolled back.
+    pub finality_check_interval_secs: u64,
+    /// Maximum number of unfinalized transactions checked per pass.
+    pub finality_batch_size: u64,
+    /// Stream new transactions over the PubSub WebSocket instead of polling once at startup.
+    pub streaming: bool,
+    /// Where decoded events are written: `clickhouse`, `stdout`, `file` and/or `broadcast`.
+    pub sinks: Vec<String>,
+    /// File the `file` sink appends events to.
+    pub sink_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeadLetterConfig {
+    /// How often the background job replays failed signatures.
+    pub retry_interval_secs: u64,
+    /// Signatures that have failed this many times are left for an operator to replay.
+    pub max_attempts: u32,
+    /// Maximum number of signatures replayed per run.
+    pub batch_size: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GeyserConfig {
+    /// Yellowstone gRPC endpoint. When set, transactions are ingested from it instead of RPC.
+    pub endpoint: Option<String>,
+    /// Access token sent as `x-token`, if the endpoint requires one.
+    pub x_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotConfig {
+    /// Periodically store the orderbook of every tracked market in `orderbook_snapshots`. Off
+    /// by default, as it needs that table and one account read per market per interval.
+    pub enabled: bool,
+    /// How often each tracked market is snapshotted.
+    pub interval_secs: u64,
+    /// Markets to snapshot. Every market loaded by the SDK when empty.
+    pub markets: Vec<String>,
+    /// Aggregated price levels stored per side.
+    pub levels: usize,
+    /// Distances from the mid, in basis points, at which the resting depth is stored.
+    pub depth_bps: Vec<u32>,
+    /// Also store every resting order, not only the aggregated levels.
+    pub include_l3: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiveOrderbookConfig {
+    /// Keep the orderbooks of the watched markets in memory from `accountSubscribe`, and
+    /// stream their changes at `/orderbook/stream`.
+    pub enabled: bool,
+    /// Markets to watch. Every market loaded by the SDK when empty.
+    pub markets: Vec<String>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            urls: vec![],
+            commitment: "confirmed".to_string(),
+            timeout_ms: 10_000,
+            failure_threshold: 3,
+            cooldown_secs: 30,
+            max_retries: 3,
+            backoff_base_ms: 250,
+            backoff_max_ms: 10_000,
+            max_requests_per_second: 10,
+            ws_url: None,
+        }
+    }
+}
+
+impl Default for ClickHouseConfig {
+    fn default() -> Self {
+        ClickHouseConfig {
+            url: "http://localhost:8123".to_string(),
+            user: "default".to_string(),
+            password: "password".to_string(),
+            database: "default".to_string(),
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 8080)),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            default_plan: "free".to_string(),
+            plans: BTreeMap::from([(
+                "free".to_string(),
+                RateLimitPlan {
+                    requests: 10,
+                    window_secs: 60,
+                },
+            )]),
+            users: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for IngestionConfig {
+    fn default() -> Self {
+        IngestionConfig {
+            program_id: phoenix::id().to_string(),
+            signature_batch_size: 1000,
+            // Roughly ten minutes at 400ms slots
+            max_lag_slots: 1500,
+            finality_check_interval_secs: 30,
+            finality_batch_size: 1000,
+            streaming: true,
+            sinks: vec!["clickhouse".to_string()],
+            sink_file: None,
+        }
+    }
+}
+
+impl Default for DeadLetterConfig {
+    fn default() -> Self {
+        DeadLetterConfig {
+            retry_interval_secs: 300,
+            max_attempts: 5,
+            batch_size: 100,
+        }
+    }
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            enabled: false,
+            interval_secs: 60,
+            markets: vec![],
+            levels: 20,
+            depth_bps: vec![10, 50, 100],
+            include_l3: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from `CONFIG_PATH` (or `config.toml`), applies environment
+    /// overrides and validates the result. A missing file is not an error; defaults are used.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = env::var("CONFIG_PATH").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+        let mut config = if Path::new(&path).exists() {
+            let contents =
+                std::fs::read_to_string(&path).map_err(|e| ConfigError::Read(path.clone(), e))?;
+            Self::from_toml(&path, &contents)?
+        } else {
+            Config::default()
+        };
+        config.apply_env_overrides(|key| env::var(key).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(path: &str, contents: &str) -> Result<Self, ConfigError> {
+        toml::from_str(contents).map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+
+    /// Applies environment overrides. `lookup` is injected so tests don't touch the process env.
+    pub fn apply_env_overrides(
+        &mut self,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<(), ConfigError> {
+        if let Some(urls) = lookup("RPC_URLS") {
+            self.rpc.urls = urls
+                .split(',')
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty())
+                .collect();
+        }
+        // Kept for backwards compatibility with deployments that only set the Helius key
+        if self.rpc.urls.is_empty() {
+            if let Some(api_key) = lookup("HELIUS_API_KEY") {
+                self.rpc
+                    .urls
+                    .push(format!("https://rpc.helius.xyz/?api-key={}", api_key));
+            }
+        }
+        if let Some(ws_url) = lookup("RPC_WS_URL") {
+            self.rpc.ws_url = Some(ws_url);
+        }
+        if let Some(commitment) = lookup("RPC_COMMITMENT") {
+            self.rpc.commitment = commitment;
+        }
+        if let Some(url) = lookup("CLICKHOUSE_URL") {
+            self.clickhouse.url = url;
+        }
+        if let Some(user) = lookup("CLICKHOUSE_USER") {
+            self.clickhouse.user = user;
+        }
+        if let Some(password) = lookup("CLICKHOUSE_PASSWORD") {
+            self.clickhouse.password = password;
+        }
+        if let Some(database) = lookup("CLICKHOUSE_DATABASE") {
+            self.clickhouse.database = database;
+        }
+        if let Some(bind_address) = lookup("BIND_ADDRESS") {
+            self.server.bind_address = bind_address
+                .parse()
+                .map_err(|_| ConfigError::InvalidEnv("BIND_ADDRESS", bind_address))?;
+        }
+        if let Some(endpoint) = lookup("GEYSER_ENDPOINT") {
+            self.geyser.endpoint = Some(endpoint);
+        }
+        if let Some(x_token) = lookup("GEYSER_X_TOKEN") {
+            self.geyser.x_token = Some(x_token);
+        }
+        if let Some(program_id) = lookup("PHOENIX_PROGRAM_ID") {
+            self.ingestion.program_id = program_id;
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.rpc.urls.is_empty() {
+            return Err(ConfigError::Invalid(
+                "at least one RPC URL is required (rpc.urls, RPC_URLS or HELIUS_API_KEY)"
+                    .to_string(),
+            ));
+        }
+        for url in self.rpc.urls.iter() {
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(ConfigError::Invalid(format!(
+                    "RPC URL must start with http:// or https://: {}",
+                    url
+                )));
+            }
+        }
+        if let Some(ws_url) = &self.rpc.ws_url {
+            if !(ws_url.starts_with("ws://") || ws_url.starts_with("wss://")) {
+                return Err(ConfigError::Invalid(format!(
+                    "rpc.ws_url must start with ws:// or wss://: {}",
+                    ws_url
+                )));
+            }
+        }
+        if let Some(endpoint) = &self.geyser.endpoint {
+            if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+                return Err(ConfigError::Invalid(format!(
+                    "geyser.endpoint must start with http:// or https://: {}",
+                    endpoint
+                )));
+            }
+        }
+        if self.rpc.timeout_ms == 0 || self.rpc.failure_threshold == 0 {
+            return Err(ConfigError::Invalid(
+                "rpc.timeout_ms and rpc.failure_threshold must be greater than 0".to_string(),
+            ));
+        }
+        if self.rpc.max_requests_per_second == 0 {
+            return Err(ConfigError::Invalid(
+                "rpc.max_requests_per_second must be greater than 0".to_string(),
+            ));
+        }
+        if self.rpc.backoff_base_ms > self.rpc.backoff_max_ms {
+            return Err(ConfigError::Invalid(
+                "rpc.backoff_base_ms must not exceed rpc.backoff_max_ms".to_string(),
+            ));
+        }
+        if !["processed", "confirmed", "finalized"].contains(&self.rpc.commitment.as_str()) {
+            return Err(ConfigError::Invalid(format!(
+                "rpc.commitment must be processed, confirmed or finalized, got {}",
+                self.rpc.commitment
+            )));
+        }
+        if !(self.clickhouse.url.starts_with("http://")
+            || self.clickhouse.url.starts_with("https://"))
+        {
+            return Err(ConfigError::Invalid(format!(
+                "clickhouse.url must start with http:// or https://: {}",
+                self.clickhouse.url
+            )));
+        }
+        if !self
+            .rate_limit
+            .plans
+            .contains_key(&self.rate_limit.default_plan)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "rate_limit.default_plan {} is not defined in rate_limit.plans",
+                self.rate_limit.default_plan
+            )));
+        }
+        for (name, plan) in self.rate_limit.plans.iter() {
+            if plan.window_secs == 0 {
+                return Err(ConfigError::Invalid(format!(
+                    "rate_limit.plans.{}.window_secs must be greater than 0",
+                    name
+                )));
+            }
+        }
+        for (user, plan) in self.rate_limit.users.iter() {
+            if !self.rate_limit.plans.contains_key(plan) {
+                return Err(ConfigError::Invalid(format!(
+                    "user {} is assigned to unknown rate limit plan {}",
+                    user, plan
+                )));
+            }
+        }
+        Pubkey::from_str(&self.ingestion.program_id).map_err(|_| {
+            ConfigError::Invalid(format!(
+                "ingestion.program_id is not a valid pubkey: {}",
+                self.ingestion.program_id
+            ))
+        })?;
+        if !(1..=1000).contains(&self.ingestion.signature_batch_size) {
+            return Err(ConfigError::Invalid(
+                "ingestion.signature_batch_size must be between 1 and 1000".to_string(),
+            ));
+        }
+        if self.ingestion.max_lag_slots < 0 {
+            return Err(ConfigError::Invalid(
+                "ingestion.max_lag_slots must not be negative".to_string(),
+            ));
+        }
+        if self.ingestion.finality_check_interval_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "ingestion.finality_check_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+        for sink in self.ingestion.sinks.iter() {
+            if !["clickhouse", "stdout", "file", "broadcast"].contains(&sink.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "ingestion.sinks must contain clickhouse, stdout, file or broadcast, got {}",
+                    sink
+                )));
+            }
+        }
+        if self.ingestion.sinks.iter().any(|sink| sink == "file")
+            && self.ingestion.sink_file.is_none()
+        {
+            return Err(ConfigError::Invalid(
+                "ingestion.sink_file is required for the file sink".to_string(),
+            ));
+        }
+        if self.dead_letter.retry_interval_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "dead_letter.retry_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.snapshots.interval_secs == 0 || self.snapshots.levels == 0 {
+            return Err(ConfigError::Invalid(
+                "snapshots.interval_secs and snapshots.levels must be greater than 0".to_string(),
+            ));
+        }
+        for market in self.snapshots.markets.iter() {
+            Pubkey::from_str(market).map_err(|_| {
+                ConfigError::Invalid(format!(
+                    "snapshots.markets contains an invalid pubkey: {}",
+                    market
+                ))
+            })?;
+        }
+        for market in self.live_orderbook.markets.iter() {
+            Pubkey::from_str(market).map_err(|_| {
+                ConfigError::Invalid(format!(
+                    "live_orderbook.markets contains an invalid pubkey: {}",
+                    market
+                ))
+            })?;
+        }
+        if self
+            .snapshots
+            .depth_bps
+            .iter()
+            .any(|&bps| bps == 0 || bps > 10_000)
+        {
+            return Err(ConfigError::Invalid(
+                "snapshots.depth_bps must be between 1 and 10000".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl RpcConfig {
+    pub fn commitment_config(&self) -> CommitmentConfig {
+        CommitmentConfig::from_str(&self.commitment)
+            .unwrap_or_else(|_| CommitmentConfig::confirmed())
+    }
+
+    /// The PubSub WebSocket URL: `ws_url` if set, otherwise the first RPC URL with its scheme
+    /// swapped, and the default RPC port 8899 mapped to the PubSub port 8900.
+    pub fn pubsub_url(&self) -> Option<String> {
+        if let Some(ws_url) = &self.ws_url {
+            return Some(ws_url.clone());
+        }
+        let url = self.urls.first()?;
+        let url = if let Some(rest) = url.strip_prefix("https://") {
+            format!("wss://{}", rest)
+        } else {
+            format!("ws://{}", url.strip_prefix("http://")?)
+        };
+        Some(url.replacen(":8899", ":8900", 1))
+    }
+}
+
+impl ClickHouseConfig {
+    pub fn client(&self) -> Client {
+        Client::default()
+            .with_url(&self.url)
+            .with_user(&self.user)
+            .with_password(&self.password)
+            .with_database(&self.database)
+    }
+}
+
+impl RateLimitConfig {
+    /// Returns the plan that applies to `user_id`.
+    pub fn plan_for(&self, user_id: &str) -> RateLimitPlan {
+        let name = self.users.get(user_id).unwrap_or(&self.default_plan);
+        self.plans[name]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_parse_toml_and_validate() {
+        let config = Config::from_toml(
+            "test.toml",
+            r#"
+            [rpc]
+            urls = ["https://api.mainnet-beta.solana.com", "http://localhost:8899"]
+            commitment = "finalized"
+
+            [server]
+            bind_address = "127.0.0.1:9000"
+
+            [rate_limit]
+            default_plan = "free"
+            plans.free = { requests = 10, window_secs = 60 }
+            plans.pro = { requests = 1000, window_secs = 60 }
+            users.morgan = "pro"
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.rpc.urls[0], "https://api.mainnet-beta.solana.com");
+        assert_eq!(
+            config.rpc.commitment_config(),
+            CommitmentConfig::finalized()
+        );
+        assert_eq!(config.server.bind_address.port(), 9000);
+        assert_eq!(config.clickhouse.url, "http://localhost:8123");
+        assert_eq!(config.rate_limit.plan_for("morgan").requests, 1000);
+        assert_eq!(config.rate_limit.plan_for("someone_else").requests, 10);
+        // Jobs that need their own tables or subscriptions are opt-in
+        assert!(!config.snapshots.enabled);
+        assert!(!config.live_orderbook.enabled);
+    }
+
+    #[test]
+    fn test_env_overrides_take_precedence() {
+        let mut config = Config::default();
+        config
+            .apply_env_overrides(lookup(&[
+                ("RPC_URLS", "https://a.example.com, https://b.example.com"),
+                ("CLICKHOUSE_PASSWORD", "secret"),
+                ("BIND_ADDRESS", "127.0.0.1:3000"),
+            ]))
+            .unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.rpc.urls.len(), 2);
+        assert_eq!(config.clickhouse.password, "secret");
+        assert_eq!(config.server.bind_address.port(), 3000);
+    }
+
+    #[test]
+    fn test_helius_api_key_fallback() {
+        let mut config = Config::default();
+        config
+            .apply_env_overrides(lookup(&[("HELIUS_API_KEY", "abc")]))
+            .unwrap();
+        assert_eq!(config.rpc.urls, vec!["https://rpc.helius.xyz/?api-key=abc"]);
+    }
+
+    #[test]
+    fn test_invalid_configs_are_rejected() {
+        // No RPC endpoint at all
+        assert!(Config::default().validate().is_err());
+
+        let mut config = Config::default();
+        config.rpc.urls = vec!["https://api.mainnet-beta.solana.com".to_string()];
+        config.validate().unwrap();
+
+        let mut bad_commitment = config.clone();
+        bad_commitment.rpc.commitment = "max".to_string();
+        assert!(bad_commitment.validate().is_err());
+
+        let mut bad_plan = config.clone();
+        bad_plan
+            .rate_limit
+            .users
+            .insert("eoin".to_string(), "enterprise".to_string());
+        assert!(bad_plan.validate().is_err());
+
+        let mut bad_program = config.clone();
+        bad_program.ingestion.program_id = "not-a-pubkey".to_string();
+        assert!(bad_program.validate().is_err());
+
+        let mut bad_geyser = config.clone();
+        bad_geyser.geyser.endpoint = Some("grpc.example.com:10000".to_string());
+        assert!(bad_geyser.validate().is_err());
+
+        let mut bad_sink = config.clone();
+        bad_sink.ingestion.sinks = vec!["kafka".to_string()];
+        assert!(bad_sink.validate().is_err());
+        bad_sink.ingestion.sinks = vec!["file".to_string()];
+        assert!(bad_sink.validate().is_err());
+        bad_sink.ingestion.sink_file = Some("events.log".to_string());
+        bad_sink.validate().unwrap();
+        bad_sink.ingestion.sinks = vec!["broadcast".to_string()];
+        bad_sink.validate().unwrap();
+
+        let mut bad_snapshots = config.clone();
+        bad_snapshots.snapshots.markets = vec!["SOL/USDC".to_string()];
+        assert!(bad_snapshots.validate().is_err());
+        bad_snapshots.snapshots.markets = vec![];
+        bad_snapshots.snapshots.depth_bps = vec![0];
+        assert!(bad_snapshots.validate().is_err());
+
+        let mut bad_live_orderbook = config.clone();
+        bad_live_orderbook.live_orderbook.markets = vec!["SOL/USDC".to_string()];
+        assert!(bad_live_orderbook.validate().is_err());
+
+        assert!(Config::default()
+            .apply_env_overrides(lookup(&[("BIND_ADDRESS", "nope")]))
+            .is_err());
+        assert!(Config::from_toml("test.toml", "[unknown]\nkey = 1").is_err());
+    }
+
+    #[test]
+    fn test_pubsub_url_is_derived_from_rpc_url() {
+        let mut rpc = RpcConfig {
+            urls: vec!["https://mainnet.helius-rpc.com/?api-key=secret".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            rpc.pubsub_url().unwrap(),
+            "wss://mainnet.helius-rpc.com/?api-key=secret"
+        );
+
+        rpc.urls = vec!["http://localhost:8899".to_string()];
+        assert_eq!(rpc.pubsub_url().unwrap(), "ws://localhost:8900");
+
+        rpc.ws_url = Some("wss://stream.example.com".to_string());
+        assert_eq!(rpc.pubsub_url().unwrap(), "wss://stream.example.com");
+    }
+}
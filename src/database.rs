@@ -4,22 +4,86 @@ use clickhouse::{Client, Row};
 
 use crate::api::OHLCQuery;
 use crate::api::OHLCResponse;
+use crate::metrics::metrics;
+use crate::snapshot::BookSummary;
+use phoenix::program::MarketSizeParams;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::enums::Side;
 use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use thiserror::Error;
 
 pub use phoenix_sdk_core::{
+    instruction_decoder::DecodedInstruction,
     market_event::{Fill, MarketEventDetails, PhoenixEvent},
+    orderbook_replay::OrderbookSnapshot,
     sdk_client_core::MarketMetadata,
+    transaction_context::{TransactionContext, TransactionFailure},
 };
 
+#[derive(Error, Debug)]
+pub enum DatabaseError {
+    #[error("Invalid interval: {0}")]
+    InvalidInterval(String),
+
+    #[error("No data found")]
+    NoData,
+
+    #[error("Insufficient credits")]
+    InsufficientCredits,
+
+    #[error("ClickHouse error: {0}")]
+    Clickhouse(#[from] clickhouse::error::Error),
+}
+
+/// A single candle, bucketed by `toStartOfInterval`. Prices are in ticks and volume in base lots.
+#[derive(Debug, Clone, Copy, Row, Deserialize)]
+pub struct OHLCRow {
+    pub time: i64,
+    pub open: u64,
+    pub high: u64,
+    pub low: u64,
+    pub close: u64,
+    pub volume: u64,
+}
+
 #[derive(Debug, Row, Deserialize)]
-struct OHLCRow {
-    open: u64,
-    high: u64,
-    low: u64,
-    close: u64,
+struct MarketRow {
+    market: String,
+    base_mint: String,
+    quote_mint: String,
+    base_decimals: u32,
+    quote_decimals: u32,
+    base_atoms_per_raw_base_unit: u64,
+    quote_atoms_per_quote_unit: u64,
+    quote_atoms_per_quote_lot: u64,
+    base_atoms_per_base_lot: u64,
+    tick_size_in_quote_atoms_per_base_unit: u64,
+    num_base_lots_per_base_unit: u64,
+    raw_base_units_per_base_unit: u32,
+    bids_size: u64,
+    asks_size: u64,
+    num_seats: u64,
+}
+
+#[derive(Row, Deserialize)]
+struct LastTradeRow {
+    timestamp: i64,
 }
 
-pub async fn insert_fill_event(event: PhoenixEvent, metadata: MarketMetadata) -> Result<()> {
+/// Inserts a fill. Re-inserting the same event is harmless: `trade_fill_events` is a
+/// `ReplacingMergeTree` keyed on `(market, signature, sequence_number, event_index)`, so
+/// overlapping backfills, restarts and dead-letter replays collapse to a single row.
+///
+/// `commitment` is the level the transaction was read at; rows below `finalized` are later
+/// promoted or rolled back by the finality job.
+pub async fn insert_fill_event(
+    client: &Client,
+    event: PhoenixEvent,
+    metadata: MarketMetadata,
+    commitment: &str,
+) -> Result<()> {
     // Match on the details to ensure it's a Fill event
     if let MarketEventDetails::Fill(Fill {
         order_sequence_number,
@@ -32,11 +96,6 @@ pub async fn insert_fill_event(event: PhoenixEvent, metadata: MarketMetadata) ->
         is_full_fill,
     }) = event.details
     {
-        let client = Client::default()
-            .with_url("http://localhost:8123")
-            .with_user("default")
-            .with_password("password");
-
         let side_as_string = match side_filled {
             phoenix::state::enums::Side::Bid => "Bid",
             phoenix::state::enums::Side::Ask => "Ask",
@@ -49,14 +108,15 @@ pub async fn insert_fill_event(event: PhoenixEvent, metadata: MarketMetadata) ->
                 side_filled, is_full_fill, base_mint, quote_mint, base_decimals, quote_decimals, 
                 base_atoms_per_raw_base_unit, quote_atoms_per_quote_unit, quote_atoms_per_quote_lot, 
                 base_atoms_per_base_lot, tick_size_in_quote_atoms_per_base_unit, num_base_lots_per_base_unit, 
-                raw_base_units_per_base_unit, bids_size, asks_size, num_seats, real_data
+                raw_base_units_per_base_unit, bids_size, asks_size, num_seats, real_data, commitment
             ) VALUES (
                 '{market}', {sequence_number}, {slot}, {timestamp}, '{signature}', '{signer}', {event_index}, 
                 {order_sequence_number}, '{maker}', '{taker}', {price_in_ticks}, {base_lots_filled}, 
                 {base_lots_remaining}, '{side_filled}', {is_full_fill}, '{base_mint}', '{quote_mint}', 
                 {base_decimals}, {quote_decimals}, {base_atoms_per_raw_base_unit}, {quote_atoms_per_quote_unit}, 
                 {quote_atoms_per_quote_lot}, {base_atoms_per_base_lot}, {tick_size_in_quote_atoms_per_base_unit}, 
-                {num_base_lots_per_base_unit}, {raw_base_units_per_base_unit}, {bids_size}, {asks_size}, {num_seats}, true
+                {num_base_lots_per_base_unit}, {raw_base_units_per_base_unit}, {bids_size}, {asks_size}, {num_seats}, true,
+                '{commitment}'
             )",
             market = event.market,
             sequence_number = event.sequence_number,
@@ -86,11 +146,14 @@ pub async fn insert_fill_event(event: PhoenixEvent, metadata: MarketMetadata) ->
             raw_base_units_per_base_unit = metadata.raw_base_units_per_base_unit,
             bids_size = metadata.market_size_params.bids_size,
             asks_size = metadata.market_size_params.asks_size,
-            num_seats = metadata.market_size_params.num_seats
+            num_seats = metadata.market_size_params.num_seats,
+            commitment = commitment
         );
 
         // Execute the query
+        let timer = metrics().clickhouse_insert_duration_seconds.start_timer();
         client.query(&query).execute().await?;
+        timer.observe_duration();
     } else {
         return Err(anyhow::anyhow!("Event is not a Fill variant."));
     }
@@ -98,35 +161,401 @@ pub async fn insert_fill_event(event: PhoenixEvent, metadata: MarketMetadata) ->
     Ok(())
 }
 
-pub async fn fetch_ohlc_data(
+/// Inserts the context of a transaction into `transaction_context`, one row per market it
+/// touched. Like fills, rows are keyed on `(market, slot, signature)` in a `ReplacingMergeTree`,
+/// so re-ingesting a transaction is harmless.
+pub async fn insert_transaction_context(
     client: &Client,
-    query: &OHLCQuery,
-) -> Result<OHLCResponse, Box<dyn std::error::Error>> {
-    let interval_duration = match query.interval.as_str() {
-        "1m" => 1,
-        "1h" => 60,
-        "1d" => 1440,
-        _ => return Err("Invalid interval".into()),
+    context: &TransactionContext,
+    markets: &[Pubkey],
+    commitment: &str,
+) -> Result<()> {
+    if markets.is_empty() {
+        return Ok(());
+    }
+    let signers = context
+        .signers
+        .iter()
+        .map(|signer| format!("'{}'", signer))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let rows = markets
+        .iter()
+        .map(|market| {
+            format!(
+                "('{}', {}, {}, '{}', {}, {}, {}, {}, {}, '{}', [{}], '{}')",
+                context.signature,
+                context.slot,
+                nullable(context.block_time),
+                market,
+                context.fee,
+                nullable(context.compute_units_consumed),
+                nullable(context.compute_unit_price),
+                nullable(context.compute_unit_limit),
+                context.is_err(),
+                escape_string(
+                    context
+                        .failure
+                        .as_ref()
+                        .map_or("", |failure| failure.error.as_str())
+                ),
+                signers,
+                escape_string(commitment)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let query = format!(
+        "INSERT INTO transaction_context (
+            signature, slot, block_time, market, fee, compute_units_consumed, compute_unit_price,
+            compute_unit_limit, is_err, error, signers, commitment
+        ) VALUES {}",
+        rows
+    );
+
+    let timer = metrics().clickhouse_insert_duration_seconds.start_timer();
+    client.query(&query).execute().await?;
+    timer.observe_duration();
+    Ok(())
+}
+
+/// Inserts the orders of a failed transaction into `failed_orders`, with the error it failed
+/// with. `instructions` are the transaction's Phoenix instructions; those that place no order
+/// are skipped. Rows are keyed on `(market, slot, signature, instruction_index,
+/// inner_instruction_index, order_index)` in a `ReplacingMergeTree`, so re-ingesting is
+/// harmless.
+pub async fn insert_failed_orders(
+    client: &Client,
+    context: &TransactionContext,
+    failure: &TransactionFailure,
+    instructions: &[DecodedInstruction],
+    commitment: &str,
+) -> Result<()> {
+    let phoenix_error = failure.phoenix_error().map(|err| format!("'{:?}'", err));
+    let rows = instructions
+        .iter()
+        .filter_map(|instruction| Some((instruction, instruction.market?)))
+        .flat_map(|(instruction, market)| {
+            instruction
+                .orders
+                .iter()
+                .enumerate()
+                .map(move |(order_index, order)| (instruction, market, order_index, order))
+        })
+        .map(|(instruction, market, order_index, order)| {
+            format!(
+                "('{}', {}, {}, '{}', {}, {}, {}, {}, {}, '{:?}', '{:?}', {}, {}, {}, {}, {}, {}, \
+                {}, toUInt128('{}'), {}, {}, {}, {}, '{}', {}, {}, {}, '{}')",
+                context.signature,
+                context.slot,
+                nullable(context.block_time),
+                market,
+                nullable(instruction.trader.map(|trader| format!("'{}'", trader))),
+                instruction.instruction_index,
+                nullable(instruction.inner_instruction_index),
+                order_index,
+                instruction.instruction,
+                order.order_type,
+                order.side,
+                nullable(order.price_in_ticks),
+                order.num_base_lots,
+                order.num_quote_lots,
+                order.min_base_lots_to_fill,
+                order.min_quote_lots_to_fill,
+                nullable(
+                    order
+                        .self_trade_behavior
+                        .map(|behavior| format!("'{:?}'", behavior))
+                ),
+                nullable(order.match_limit),
+                order.client_order_id,
+                order.use_only_deposited_funds,
+                nullable(order.last_valid_slot),
+                nullable(order.last_valid_unix_timestamp_in_seconds),
+                failure.caused_by(instruction),
+                escape_string(&failure.error),
+                nullable(failure.error_code),
+                nullable(failure.program.map(|program| format!("'{}'", program))),
+                nullable(phoenix_error.as_ref()),
+                escape_string(commitment)
+            )
+        })
+        .collect::<Vec<_>>();
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let query = format!(
+        "INSERT INTO failed_orders (
+            signature, slot, block_time, market, trader, instruction_index,
+            inner_instruction_index, order_index, instruction, order_type, side, price_in_ticks, num_base_lots,
+            num_quote_lots, min_base_lots_to_fill, min_quote_lots_to_fill, self_trade_behavior,
+            match_limit, client_order_id, use_only_deposited_funds, last_valid_slot,
+            last_valid_unix_timestamp_in_seconds, caused_failure, error, error_code, error_program,
+            phoenix_error, commitment
+        ) VALUES {}",
+        rows.join(", ")
+    );
+
+    let timer = metrics().clickhouse_insert_duration_seconds.start_timer();
+    client.query(&query).execute().await?;
+    timer.observe_duration();
+    Ok(())
+}
+
+/// Inserts an orderbook snapshot read at `timestamp_ms` (unix milliseconds) into
+/// `orderbook_snapshots`, with its summary. `depth_bps` are the distances from the mid the
+/// summary's depth was measured at. Resting orders are only stored with `include_l3`.
+pub async fn insert_orderbook_snapshot(
+    client: &Client,
+    snapshot: &OrderbookSnapshot,
+    timestamp_ms: i64,
+    summary: &BookSummary,
+    depth_bps: &[u32],
+    include_l3: bool,
+) -> Result<()> {
+    let orders = if include_l3 {
+        let bids = snapshot
+            .orderbook
+            .bids
+            .iter()
+            .map(|order| (Side::Bid, order));
+        let asks = snapshot
+            .orderbook
+            .asks
+            .iter()
+            .map(|order| (Side::Ask, order));
+        bids.chain(asks)
+            .map(|(side, (order_id, order))| {
+                format!(
+                    "('{:?}', {}, {}, '{}', {})",
+                    side,
+                    order_id.price_in_ticks.as_u64(),
+                    order_id.order_sequence_number,
+                    order.maker_id,
+                    order.num_base_lots
+                )
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
     };
+    let query = format!(
+        "INSERT INTO orderbook_snapshots (
+            market, timestamp, slot, sequence_number, raw_base_units_per_base_lot,
+            quote_units_per_raw_base_unit_per_tick, best_bid_in_ticks, best_ask_in_ticks,
+            mid_price_in_ticks, spread_in_ticks, spread_bps, bid_prices_in_ticks,
+            bid_sizes_in_base_lots, bid_order_counts, ask_prices_in_ticks, ask_sizes_in_base_lots,
+            ask_order_counts, depth_bps, bid_depth_in_base_lots, ask_depth_in_base_lots, orders
+        ) VALUES ('{}', fromUnixTimestamp64Milli(toInt64({})), {}, {}, {}, {}, {}, {}, {}, {}, {}, \
+        {}, {}, {}, {}, {}, {}, {}, {}, {}, [{}])",
+        snapshot.market,
+        timestamp_ms,
+        snapshot.slot,
+        snapshot.sequence_number,
+        snapshot.orderbook.raw_base_units_per_base_lot,
+        snapshot.orderbook.quote_units_per_raw_base_unit_per_tick,
+        nullable(summary.bids.first().map(|level| level.price_in_ticks)),
+        nullable(summary.asks.first().map(|level| level.price_in_ticks)),
+        nullable(summary.mid_price_in_ticks),
+        nullable(summary.spread_in_ticks),
+        nullable(summary.spread_bps),
+        array(summary.bids.iter().map(|level| level.price_in_ticks)),
+        array(summary.bids.iter().map(|level| level.base_lots)),
+        array(summary.bids.iter().map(|level| level.orders)),
+        array(summary.asks.iter().map(|level| level.price_in_ticks)),
+        array(summary.asks.iter().map(|level| level.base_lots)),
+        array(summary.asks.iter().map(|level| level.orders)),
+        array(depth_bps),
+        array(&summary.bid_depth),
+        array(&summary.ask_depth),
+        orders.join(", ")
+    );
+
+    let timer = metrics().clickhouse_insert_duration_seconds.start_timer();
+    client.query(&query).execute().await?;
+    timer.observe_duration();
+    Ok(())
+}
+
+/// Formats values as a SQL array literal.
+fn array<T: std::fmt::Display>(values: impl IntoIterator<Item = T>) -> String {
+    let values = values
+        .into_iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>();
+    format!("[{}]", values.join(", "))
+}
 
-    let start_time = query.start_time;
-    let end_time = query.end_time;
+/// Formats an optional value as a SQL literal.
+fn nullable<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "NULL".to_string(), |value| value.to_string())
+}
+
+/// Maps an `/ohlc` interval to its length in seconds.
+pub fn interval_to_seconds(interval: &str) -> Option<u64> {
+    match interval {
+        "1m" => Some(60),
+        "1h" => Some(3600),
+        "1d" => Some(86400),
+        _ => None,
+    }
+}
+
+/// Which fills a candle query covers.
+#[derive(Debug, Clone, Copy)]
+pub enum TradeFilter<'a> {
+    /// Every market trading `base_mint` against `quote_mint`.
+    Pair {
+        base_mint: &'a str,
+        quote_mint: &'a str,
+    },
+    /// A single market. Prices and sizes are in that market's ticks and lots, so this is the
+    /// only filter whose candles can be converted with its `MarketMetadata`.
+    Market(&'a str),
+}
+
+impl TradeFilter<'_> {
+    fn to_sql(self) -> String {
+        match self {
+            TradeFilter::Pair {
+                base_mint,
+                quote_mint,
+            } => format!(
+                "base_mint = '{}' AND quote_mint = '{}'",
+                escape_string(base_mint),
+                escape_string(quote_mint)
+            ),
+            TradeFilter::Market(market) => format!("market = '{}'", escape_string(market)),
+        }
+    }
+}
 
+/// Candle engine shared by `/ohlc` and the UDF `/history` endpoint.
+/// Returns one candle per `interval_secs` bucket in `[start_time, end_time]`, ordered by time.
+pub async fn fetch_candles(
+    client: &Client,
+    filter: TradeFilter<'_>,
+    start_time: i64,
+    end_time: i64,
+    interval_secs: u64,
+) -> Result<Vec<OHLCRow>, DatabaseError> {
+    // Trades are ordered by (timestamp, sequence_number, event_index) so open and close are
+    // deterministic even when several fills share a timestamp. FINAL collapses events that were
+    // ingested more than once but not merged yet, which would otherwise inflate the volume.
     let sql = format!(
         r#"
         SELECT
-            MIN(price_in_ticks) AS low,
+            toInt64(toUnixTimestamp(toStartOfInterval(toDateTime(timestamp), INTERVAL {} SECOND))) AS time,
+            argMin(price_in_ticks, (timestamp, sequence_number, event_index)) AS open,
             MAX(price_in_ticks) AS high,
-            anyLast(price_in_ticks) AS close,
-            any(price_in_ticks) AS open
-        FROM trade_fill_events
-        WHERE base_mint = '{}' AND quote_mint = '{}'
+            MIN(price_in_ticks) AS low,
+            argMax(price_in_ticks, (timestamp, sequence_number, event_index)) AS close,
+            SUM(base_lots_filled) AS volume
+        FROM trade_fill_events FINAL
+        WHERE {}
         AND timestamp >= {} AND timestamp <= {}
-        GROUP BY toStartOfInterval(toDateTime(timestamp), INTERVAL {} MINUTE)
+        GROUP BY time
+        ORDER BY time
         "#,
-        query.base_token_mint, query.quote_token_mint, start_time, end_time, interval_duration
+        interval_secs,
+        filter.to_sql(),
+        start_time,
+        end_time
+    );
+    Ok(client.query(&sql).fetch_all::<OHLCRow>().await?)
+}
+
+/// Returns the timestamp of the most recent trade strictly before `before`, if any.
+/// Duplicate events do not change the result, so this skips the cost of `FINAL`.
+pub async fn fetch_last_trade_time_before(
+    client: &Client,
+    filter: TradeFilter<'_>,
+    before: i64,
+) -> Result<Option<i64>, DatabaseError> {
+    let sql = format!(
+        "SELECT timestamp FROM trade_fill_events
+        WHERE {} AND timestamp < {}
+        ORDER BY timestamp DESC LIMIT 1",
+        filter.to_sql(),
+        before
     );
-    let rows = client.query(&sql).fetch_all::<OHLCRow>().await?;
+    let row: Option<LastTradeRow> = client.query(&sql).fetch_optional().await?;
+    Ok(row.map(|row| row.timestamp))
+}
+
+/// Returns the metadata of every market that has at least one recorded fill.
+pub async fn fetch_markets(
+    client: &Client,
+) -> Result<Vec<(Pubkey, MarketMetadata)>, DatabaseError> {
+    let sql = r#"
+        SELECT
+            market,
+            any(base_mint) AS base_mint,
+            any(quote_mint) AS quote_mint,
+            any(base_decimals) AS base_decimals,
+            any(quote_decimals) AS quote_decimals,
+            any(base_atoms_per_raw_base_unit) AS base_atoms_per_raw_base_unit,
+            any(quote_atoms_per_quote_unit) AS quote_atoms_per_quote_unit,
+            any(quote_atoms_per_quote_lot) AS quote_atoms_per_quote_lot,
+            any(base_atoms_per_base_lot) AS base_atoms_per_base_lot,
+            any(tick_size_in_quote_atoms_per_base_unit) AS tick_size_in_quote_atoms_per_base_unit,
+            any(num_base_lots_per_base_unit) AS num_base_lots_per_base_unit,
+            any(raw_base_units_per_base_unit) AS raw_base_units_per_base_unit,
+            any(bids_size) AS bids_size,
+            any(asks_size) AS asks_size,
+            any(num_seats) AS num_seats
+        FROM trade_fill_events
+        GROUP BY market
+        ORDER BY market
+    "#;
+    let rows = client.query(sql).fetch_all::<MarketRow>().await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            // Rows written by older versions may contain malformed keys; skip them
+            let market = Pubkey::from_str(&row.market).ok()?;
+            let metadata = MarketMetadata {
+                base_mint: Pubkey::from_str(&row.base_mint).ok()?,
+                quote_mint: Pubkey::from_str(&row.quote_mint).ok()?,
+                base_decimals: row.base_decimals,
+                quote_decimals: row.quote_decimals,
+                base_atoms_per_raw_base_unit: row.base_atoms_per_raw_base_unit,
+                quote_atoms_per_quote_unit: row.quote_atoms_per_quote_unit,
+                quote_atoms_per_quote_lot: row.quote_atoms_per_quote_lot,
+                base_atoms_per_base_lot: row.base_atoms_per_base_lot,
+                tick_size_in_quote_atoms_per_base_unit: row.tick_size_in_quote_atoms_per_base_unit,
+                num_base_lots_per_base_unit: row.num_base_lots_per_base_unit,
+                raw_base_units_per_base_unit: row.raw_base_units_per_base_unit,
+                market_size_params: MarketSizeParams {
+                    bids_size: row.bids_size,
+                    asks_size: row.asks_size,
+                    num_seats: row.num_seats,
+                },
+            };
+            Some((market, metadata))
+        })
+        .collect())
+}
+
+pub async fn fetch_ohlc_data(
+    client: &Client,
+    query: &OHLCQuery,
+) -> Result<OHLCResponse, DatabaseError> {
+    let interval_secs = interval_to_seconds(&query.interval)
+        .ok_or_else(|| DatabaseError::InvalidInterval(query.interval.clone()))?;
+
+    let rows = fetch_candles(
+        client,
+        TradeFilter::Pair {
+            base_mint: &query.base_token_mint,
+            quote_mint: &query.quote_token_mint,
+        },
+        query.start_time,
+        query.end_time,
+        interval_secs,
+    )
+    .await?;
 
     if let Some(row) = rows.first() {
         Ok(OHLCResponse {
@@ -136,28 +565,112 @@ pub async fn fetch_ohlc_data(
             close: row.close,
         })
     } else {
-        Err("No data found".into())
+        Err(DatabaseError::NoData)
     }
 }
 
+/// A row of `orderbook_snapshots`. Prices are in ticks and sizes in base lots.
+#[derive(Debug, Clone, Row, Deserialize)]
+pub struct OrderbookSnapshotRow {
+    pub market: String,
+    /// Unix milliseconds.
+    pub timestamp_ms: i64,
+    pub slot: u64,
+    pub sequence_number: u64,
+    pub raw_base_units_per_base_lot: f64,
+    pub quote_units_per_raw_base_unit_per_tick: f64,
+    pub best_bid_in_ticks: Option<u64>,
+    pub best_ask_in_ticks: Option<u64>,
+    pub mid_price_in_ticks: Option<f64>,
+    pub spread_in_ticks: Option<u64>,
+    pub spread_bps: Option<f64>,
+    pub bid_prices_in_ticks: Vec<u64>,
+    pub bid_sizes_in_base_lots: Vec<u64>,
+    pub bid_order_counts: Vec<u32>,
+    pub ask_prices_in_ticks: Vec<u64>,
+    pub ask_sizes_in_base_lots: Vec<u64>,
+    pub ask_order_counts: Vec<u32>,
+    pub depth_bps: Vec<u32>,
+    pub bid_depth_in_base_lots: Vec<u64>,
+    pub ask_depth_in_base_lots: Vec<u64>,
+    /// `(side, price_in_ticks, order_sequence_number, maker, num_base_lots)`, empty unless the
+    /// snapshot was stored with its resting orders and they were requested.
+    pub orders: Vec<(String, u64, u64, String, u64)>,
+}
+
+/// Columns of `OrderbookSnapshotRow`, with the resting orders only if `include_orders`.
+fn orderbook_snapshot_columns(include_orders: bool) -> String {
+    format!(
+        "market, toUnixTimestamp64Milli(timestamp) AS timestamp_ms, slot, sequence_number,
+        raw_base_units_per_base_lot, quote_units_per_raw_base_unit_per_tick, best_bid_in_ticks,
+        best_ask_in_ticks, mid_price_in_ticks, spread_in_ticks, spread_bps, bid_prices_in_ticks,
+        bid_sizes_in_base_lots, bid_order_counts, ask_prices_in_ticks, ask_sizes_in_base_lots,
+        ask_order_counts, depth_bps, bid_depth_in_base_lots, ask_depth_in_base_lots,
+        if({}, orders, []) AS orders",
+        include_orders
+    )
+}
+
+/// Returns the latest snapshot of `market` taken at or before `time_ms` (unix milliseconds).
+pub async fn fetch_orderbook_snapshot_at(
+    client: &Client,
+    market: &str,
+    time_ms: i64,
+    include_orders: bool,
+) -> Result<Option<OrderbookSnapshotRow>, DatabaseError> {
+    let sql = format!(
+        "SELECT {} FROM orderbook_snapshots
+        WHERE market = '{}' AND timestamp <= fromUnixTimestamp64Milli(toInt64({}))
+        ORDER BY timestamp DESC LIMIT 1",
+        orderbook_snapshot_columns(include_orders),
+        escape_string(market),
+        time_ms
+    );
+    Ok(client.query(&sql).fetch_optional().await?)
+}
+
+/// Returns up to `limit` snapshots of `market` taken in `[start_ms, end_ms]` (unix
+/// milliseconds), oldest first.
+pub async fn fetch_orderbook_snapshots(
+    client: &Client,
+    market: &str,
+    start_ms: i64,
+    end_ms: i64,
+    include_orders: bool,
+    limit: u64,
+) -> Result<Vec<OrderbookSnapshotRow>, DatabaseError> {
+    let sql = format!(
+        "SELECT {} FROM orderbook_snapshots
+        WHERE market = '{}'
+        AND timestamp >= fromUnixTimestamp64Milli(toInt64({}))
+        AND timestamp <= fromUnixTimestamp64Milli(toInt64({}))
+        ORDER BY timestamp LIMIT {}",
+        orderbook_snapshot_columns(include_orders),
+        escape_string(market),
+        start_ms,
+        end_ms,
+        limit
+    );
+    Ok(client.query(&sql).fetch_all().await?)
+}
+
 #[derive(Row, Deserialize)]
 struct CreditsRow {
     credits: u64,
 }
 
-pub async fn check_and_update_credits(
-    client: &Client,
-    user_id: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn check_and_update_credits(client: &Client, user_id: &str) -> Result<(), DatabaseError> {
     // Check current credits
     let credits_query = format!(
         "SELECT credits FROM user_credits WHERE user_id = '{}'",
         user_id
     );
-    let row: CreditsRow = client.query(&credits_query).fetch_one().await?;
+    let row: Option<CreditsRow> = client.query(&credits_query).fetch_optional().await?;
 
-    if row.credits == 0 {
-        return Err("Insufficient credits".into());
+    // Unknown users are treated the same as users without credits
+    let credits = row.map(|row| row.credits).unwrap_or(0);
+    if credits == 0 {
+        return Err(DatabaseError::InsufficientCredits);
     }
 
     // Deduct 1 credit and update
@@ -170,14 +683,232 @@ pub async fn check_and_update_credits(
     Ok(())
 }
 
+/// A transaction whose fills or context were ingested below `finalized`.
+#[derive(Debug, Clone, Row, Deserialize)]
+pub struct UnfinalizedSignatureRow {
+    pub signature: String,
+    pub slot: u64,
+}
+
+/// Returns transactions ingested below `finalized` in slots up to `max_slot`, oldest first.
+pub async fn fetch_unfinalized_signatures(
+    client: &Client,
+    max_slot: u64,
+    limit: u64,
+) -> Result<Vec<UnfinalizedSignatureRow>, DatabaseError> {
+    let query = format!(
+        "SELECT signature, min(slot) AS slot
+        FROM (
+            SELECT signature, slot FROM trade_fill_events
+            WHERE commitment != 'finalized' AND slot <= {max_slot}
+            UNION ALL
+            SELECT signature, slot FROM transaction_context
+            WHERE commitment != 'finalized' AND slot <= {max_slot}
+        )
+        GROUP BY signature
+        ORDER BY slot
+        LIMIT {limit}",
+        max_slot = max_slot,
+        limit = limit
+    );
+    Ok(client
+        .query(&query)
+        .fetch_all::<UnfinalizedSignatureRow>()
+        .await?)
+}
+
+fn signature_list(signatures: &[String]) -> String {
+    signatures
+        .iter()
+        .map(|signature| format!("'{}'", escape_string(signature)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Tables holding rows per signature, which follow the finality of their transaction.
+const SIGNATURE_TABLES: [&str; 3] = ["trade_fill_events", "transaction_context", "failed_orders"];
+
+/// Marks the fills, transaction contexts and failed orders of `signatures` as finalized.
+pub async fn promote_signatures(
+    client: &Client,
+    signatures: &[String],
+) -> Result<(), DatabaseError> {
+    if signatures.is_empty() {
+        return Ok(());
+    }
+    for table in SIGNATURE_TABLES {
+        let query = format!(
+            "ALTER TABLE {} UPDATE commitment = 'finalized' WHERE signature IN ({})",
+            table,
+            signature_list(signatures)
+        );
+        // Wait for the mutation so the next pass does not pick the same rows up again
+        client
+            .clone()
+            .with_option("mutations_sync", "1")
+            .query(&query)
+            .execute()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Removes the fills, transaction contexts and failed orders of `signatures`, for transactions
+/// that did not make it into the finalized chain.
+pub async fn delete_signatures(
+    client: &Client,
+    signatures: &[String],
+) -> Result<(), DatabaseError> {
+    if signatures.is_empty() {
+        return Ok(());
+    }
+    for table in SIGNATURE_TABLES {
+        let query = format!(
+            "ALTER TABLE {} DELETE WHERE signature IN ({})",
+            table,
+            signature_list(signatures)
+        );
+        client
+            .clone()
+            .with_option("mutations_sync", "1")
+            .query(&query)
+            .execute()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Escapes a value for use inside a single-quoted ClickHouse string literal. `?` is doubled
+/// because the client treats a bare `?` as a bind placeholder.
+fn escape_string(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\'', "\\'")
+        .replace('?', "??")
+}
+
+/// A signature that could not be ingested, as stored in `failed_signatures`.
+#[derive(Debug, Clone, Row, Deserialize)]
+pub struct FailedSignatureRow {
+    pub signature: String,
+    pub slot: u64,
+    /// `fetch`, `parse` or `insert`.
+    pub stage: String,
+    pub error: String,
+    pub attempts: u32,
+    pub resolved: bool,
+    /// Unix timestamp of the last failure (or of the successful replay).
+    pub updated_at: i64,
+}
+
+#[derive(Row, Deserialize)]
+struct AttemptsRow {
+    attempts: u32,
+}
+
+/// Records a failed signature in the dead-letter store, incrementing its attempt count.
+pub async fn record_failed_signature(
+    client: &Client,
+    signature: &str,
+    slot: u64,
+    stage: &str,
+    error: &str,
+) -> Result<(), DatabaseError> {
+    let attempts_query = format!(
+        "SELECT attempts FROM failed_signatures FINAL WHERE signature = '{}'",
+        escape_string(signature)
+    );
+    let previous: Option<AttemptsRow> = client.query(&attempts_query).fetch_optional().await?;
+    let attempts = previous.map_or(0, |row| row.attempts) + 1;
+
+    // failed_signatures is a ReplacingMergeTree, so the newest row per signature wins
+    let query = format!(
+        "INSERT INTO failed_signatures (signature, slot, stage, error, attempts, resolved, updated_at)
+        VALUES ('{}', {}, '{}', '{}', {}, false, now64(3))",
+        escape_string(signature),
+        slot,
+        escape_string(stage),
+        escape_string(error),
+        attempts
+    );
+    client.query(&query).execute().await?;
+    Ok(())
+}
+
+/// Marks a dead-lettered signature as successfully replayed.
+pub async fn mark_signature_resolved(
+    client: &Client,
+    signature: &str,
+) -> Result<(), DatabaseError> {
+    let query = format!(
+        "INSERT INTO failed_signatures (signature, slot, stage, error, attempts, resolved, updated_at)
+        SELECT signature, slot, stage, error, attempts, true, now64(3)
+        FROM failed_signatures FINAL
+        WHERE signature = '{}'",
+        escape_string(signature)
+    );
+    client.query(&query).execute().await?;
+    Ok(())
+}
+
+/// Lists dead-lettered signatures, oldest failure first.
+///
+/// Resolved entries are skipped unless `include_resolved` is set, and entries that have already
+/// been attempted `max_attempts` times are skipped when a limit is given.
+pub async fn fetch_failed_signatures(
+    client: &Client,
+    include_resolved: bool,
+    max_attempts: Option<u32>,
+    limit: u64,
+) -> Result<Vec<FailedSignatureRow>, DatabaseError> {
+    let mut conditions = vec![];
+    if !include_resolved {
+        conditions.push("NOT resolved".to_string());
+    }
+    if let Some(max_attempts) = max_attempts {
+        conditions.push(format!("attempts < {}", max_attempts));
+    }
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let query = format!(
+        "SELECT signature, slot, stage, error, attempts, resolved, toInt64(toUnixTimestamp(updated_at)) AS updated_at
+        FROM failed_signatures FINAL
+        {}
+        ORDER BY updated_at
+        LIMIT {}",
+        where_clause, limit
+    );
+    Ok(client
+        .query(&query)
+        .fetch_all::<FailedSignatureRow>()
+        .await?)
+}
+
+/// Looks up a single dead-lettered signature.
+pub async fn fetch_failed_signature(
+    client: &Client,
+    signature: &str,
+) -> Result<Option<FailedSignatureRow>, DatabaseError> {
+    let query = format!(
+        "SELECT signature, slot, stage, error, attempts, resolved, toInt64(toUnixTimestamp(updated_at)) AS updated_at
+        FROM failed_signatures FINAL
+        WHERE signature = '{}'",
+        escape_string(signature)
+    );
+    Ok(client.query(&query).fetch_optional().await?)
+}
+
 // AI Generated Test cases
 // Mocks involve repetitive boilerplate and syntax
 #[cfg(test)]
 mod tests {
     use super::*;
     use mockall::predicate::*;
-    use phoenix::program::MarketSizeParams;
-    use solana_sdk::{pubkey::Pubkey, signature::Signature};
+    use solana_sdk::signature::Signature;
 
     #[tokio::test]
     async fn test_insert_fill_event_success() {
@@ -189,6 +920,7 @@ mod tests {
             timestamp: 1,
             signature: Signature::new_unique(),
             signer: Pubkey::new_unique(),
+            trader: Pubkey::new_unique(),
             event_index: 1,
             details: MarketEventDetails::Fill(Fill {
                 order_sequence_number: 1,
@@ -243,10 +975,12 @@ mod tests {
         impl MockClient {
             async fn query(&self, _query: &str) -> Result<Vec<OHLCRow>> {
                 Ok(vec![OHLCRow {
+                    time: 0,
                     open: 100,
                     high: 200,
                     low: 50,
                     close: 150,
+                    volume: 10,
                 }])
             }
         }
@@ -294,4 +1028,34 @@ mod tests {
         let result = mock_client.update("mock_update_credits_query").await;
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_escape_string() {
+        assert_eq!(escape_string(r"it's a \ path?"), r"it\'s a \\ path??");
+    }
+
+    #[test]
+    fn test_trade_filter_to_sql() {
+        assert_eq!(
+            TradeFilter::Pair {
+                base_mint: "base",
+                quote_mint: "quote"
+            }
+            .to_sql(),
+            "base_mint = 'base' AND quote_mint = 'quote'"
+        );
+        assert_eq!(TradeFilter::Market("it's").to_sql(), "market = 'it\\'s'");
+    }
+
+    #[test]
+    fn test_nullable() {
+        assert_eq!(nullable(Some(5_000u64)), "5000");
+        assert_eq!(nullable(None::<u64>), "NULL");
+    }
+
+    #[test]
+    fn test_array() {
+        assert_eq!(array([10u32, 50, 100]), "[10, 50, 100]");
+        assert_eq!(array(Vec::<u64>::new()), "[]");
+    }
 }
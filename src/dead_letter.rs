@@ -0,0 +1,140 @@
+// dead_letter.rs
+use clickhouse::Client;
+use solana_sdk::signature::Signature;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::database::{fetch_failed_signatures, mark_signature_resolved, record_failed_signature};
+use crate::metrics::metrics;
+use crate::pipeline::Pipeline;
+use crate::rpc::RpcPool;
+use crate::solana::{parse_fills, replay_pipeline, FetchError};
+
+/// The ingestion step at which a signature failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureStage {
+    Fetch,
+    Parse,
+    Insert,
+}
+
+impl FailureStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureStage::Fetch => "fetch",
+            FailureStage::Parse => "parse",
+            FailureStage::Insert => "insert",
+        }
+    }
+}
+
+/// Stores a failed signature in the dead-letter store. Failing to do so is logged, not
+/// propagated, so that ingestion keeps going.
+pub async fn record_failure(
+    clickhouse_client: &Client,
+    signature: &Signature,
+    slot: u64,
+    err: &FetchError,
+) {
+    if let Err(db_err) = record_failed_signature(
+        clickhouse_client,
+        &signature.to_string(),
+        slot,
+        err.stage().as_str(),
+        &err.to_string(),
+    )
+    .await
+    {
+        eprintln!(
+            "Failed to record {} in the dead-letter store: {:?}",
+            signature, db_err
+        );
+    }
+}
+
+/// Reprocesses a signature through `pipeline` (see [`replay_pipeline`]). On success it is
+/// marked resolved; on failure its attempt count and error are updated.
+pub async fn replay_signature(
+    rpc_pool: &RpcPool,
+    clickhouse_client: &Client,
+    pipeline: &mut Pipeline,
+    signature: &str,
+    slot: u64,
+) -> Result<(), FetchError> {
+    let signature = Signature::from_str(signature)
+        .map_err(|_| FetchError::InvalidSignature(signature.to_string()))?;
+
+    match parse_fills(&signature, rpc_pool, pipeline).await {
+        Ok(()) => {
+            mark_signature_resolved(clickhouse_client, &signature.to_string()).await?;
+            metrics()
+                .dead_letter_replays_total
+                .with_label_values(&["resolved"])
+                .inc();
+            Ok(())
+        }
+        Err(err) => {
+            record_failure(clickhouse_client, &signature, slot, &err).await;
+            metrics()
+                .dead_letter_replays_total
+                .with_label_values(&["failed"])
+                .inc();
+            Err(err)
+        }
+    }
+}
+
+/// Periodically replays unresolved signatures that have not yet used up their attempts.
+pub async fn run_retry_job(rpc_pool: &RpcPool, config: &Config, clickhouse_client: &Client) {
+    let settings = &config.dead_letter;
+    let mut interval = tokio::time::interval(Duration::from_secs(settings.retry_interval_secs));
+    loop {
+        interval.tick().await;
+
+        let entries = match fetch_failed_signatures(
+            clickhouse_client,
+            false,
+            Some(settings.max_attempts),
+            settings.batch_size,
+        )
+        .await
+        {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("Failed to read the dead-letter store: {:?}", err);
+                continue;
+            }
+        };
+
+        if entries.is_empty() {
+            continue;
+        }
+        // Markets are loaded once per batch rather than once per signature
+        let mut pipeline = match replay_pipeline(rpc_pool, clickhouse_client).await {
+            Ok(pipeline) => pipeline,
+            Err(err) => {
+                eprintln!("Failed to load markets for dead-letter replays: {}", err);
+                continue;
+            }
+        };
+        for entry in entries {
+            if let Err(err) = replay_signature(
+                rpc_pool,
+                clickhouse_client,
+                &mut pipeline,
+                &entry.signature,
+                entry.slot,
+            )
+            .await
+            {
+                eprintln!(
+                    "Replay of {} failed (attempt {}): {}",
+                    entry.signature,
+                    entry.attempts + 1,
+                    err
+                );
+            }
+        }
+    }
+}
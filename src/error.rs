@@ -0,0 +1,176 @@
+// error.rs
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::database::DatabaseError;
+
+/// Errors returned by the HTTP handlers.
+///
+/// Every variant maps to a stable, machine-readable `code` so clients can branch on the
+/// error without parsing the message. Internal failures are logged server-side and only
+/// a generic message is sent back to the client.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("start_time must be less than end_time")]
+    InvalidTimeRange { start_time: i64, end_time: i64 },
+
+    #[error("Invalid interval. Supported values: 1m, 1h, 1d")]
+    InvalidInterval(String),
+
+    #[error("Invalid market address")]
+    InvalidMarket(String),
+
+    #[error("Rate limit exceeded. Please try again later.")]
+    RateLimited { retry_after_secs: u64 },
+
+    #[error("Insufficient credits")]
+    InsufficientCredits,
+
+    #[error("No data found for the requested range")]
+    NoData,
+
+    #[error("Internal server error")]
+    Internal(#[source] anyhow::Error),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable, machine-readable error code, e.g. `INVALID_INTERVAL`.
+    #[schema(example = "INVALID_INTERVAL")]
+    pub code: &'static str,
+    /// Human-readable description of the error.
+    pub message: String,
+    /// Optional structured context for the error.
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    /// Stable error code exposed to clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidTimeRange { .. } => "INVALID_TIME_RANGE",
+            ApiError::InvalidInterval(_) => "INVALID_INTERVAL",
+            ApiError::InvalidMarket(_) => "INVALID_MARKET",
+            ApiError::RateLimited { .. } => "RATE_LIMITED",
+            ApiError::InsufficientCredits => "INSUFFICIENT_CREDITS",
+            ApiError::NoData => "NO_DATA",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidTimeRange { .. }
+            | ApiError::InvalidInterval(_)
+            | ApiError::InvalidMarket(_) => StatusCode::BAD_REQUEST,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::InsufficientCredits => StatusCode::PAYMENT_REQUIRED,
+            ApiError::NoData => StatusCode::NOT_FOUND,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn details(&self) -> Option<Value> {
+        match self {
+            ApiError::InvalidTimeRange {
+                start_time,
+                end_time,
+            } => Some(json!({ "start_time": start_time, "end_time": end_time })),
+            ApiError::InvalidInterval(interval) => Some(json!({
+                "interval": interval,
+                "supported": ["1m", "1h", "1d"],
+            })),
+            ApiError::InvalidMarket(market) => Some(json!({ "market": market })),
+            ApiError::RateLimited { retry_after_secs } => {
+                Some(json!({ "retry_after_secs": retry_after_secs }))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            details: self.details(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        // Never leak internal error text to clients; keep it in the server logs instead.
+        if let ApiError::Internal(err) = &self {
+            eprintln!("Internal error while handling request: {:?}", err);
+        }
+        (self.status(), Json(self.body())).into_response()
+    }
+}
+
+impl From<DatabaseError> for ApiError {
+    fn from(err: DatabaseError) -> Self {
+        match err {
+            DatabaseError::NoData => ApiError::NoData,
+            DatabaseError::InsufficientCredits => ApiError::InsufficientCredits,
+            DatabaseError::InvalidInterval(interval) => ApiError::InvalidInterval(interval),
+            DatabaseError::Clickhouse(e) => ApiError::Internal(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        assert_eq!(
+            ApiError::InvalidInterval("5m".to_string()).code(),
+            "INVALID_INTERVAL"
+        );
+        assert_eq!(
+            ApiError::RateLimited {
+                retry_after_secs: 60
+            }
+            .code(),
+            "RATE_LIMITED"
+        );
+        assert_eq!(ApiError::InsufficientCredits.code(), "INSUFFICIENT_CREDITS");
+        assert_eq!(ApiError::NoData.code(), "NO_DATA");
+        assert_eq!(
+            ApiError::InvalidMarket("SOL/USDC".to_string()).code(),
+            "INVALID_MARKET"
+        );
+    }
+
+    #[test]
+    fn test_internal_error_message_is_not_leaked() {
+        let err = ApiError::Internal(anyhow::anyhow!("clickhouse: connection refused"));
+        let body = err.body();
+
+        assert_eq!(body.code, "INTERNAL_ERROR");
+        assert_eq!(body.message, "Internal server error");
+        assert!(body.details.is_none());
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_database_errors_map_to_api_errors() {
+        assert!(matches!(
+            ApiError::from(DatabaseError::InsufficientCredits),
+            ApiError::InsufficientCredits
+        ));
+        assert!(matches!(
+            ApiError::from(DatabaseError::NoData),
+            ApiError::NoData
+        ));
+    }
+}
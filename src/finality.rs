@@ -0,0 +1,279 @@
+// finality.rs
+use clickhouse::Client;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{TransactionConfirmationStatus, TransactionStatus};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::database::{
+    delete_signatures, fetch_unfinalized_signatures, promote_signatures, record_failed_signature,
+    UnfinalizedSignatureRow,
+};
+use crate::dead_letter::FailureStage;
+use crate::metrics::metrics;
+use crate::rpc::RpcPool;
+use crate::solana::FetchError;
+
+/// `getSignatureStatuses` accepts at most this many signatures per call.
+const MAX_SIGNATURE_STATUSES: usize = 256;
+
+/// What to do with a transaction ingested below `finalized`, once its slot is at or below the
+/// finalized slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finality {
+    /// Finalized in the slot it was ingested from: promote its rows.
+    Finalized,
+    /// Not finalized yet: check again on the next pass.
+    Pending,
+    /// Finalized in a different slot than the one ingested, so the stored rows came from a
+    /// fork: remove them and re-ingest the transaction.
+    Moved(u64),
+    /// No status at all: depends on whether the slot made it into the finalized chain.
+    Unknown,
+    /// The slot was skipped: remove the rows.
+    RolledBack,
+}
+
+pub fn classify(ingested_slot: u64, status: Option<&TransactionStatus>) -> Finality {
+    match status {
+        None => Finality::Unknown,
+        Some(status) => match status.confirmation_status {
+            Some(TransactionConfirmationStatus::Finalized) if status.slot == ingested_slot => {
+                Finality::Finalized
+            }
+            Some(TransactionConfirmationStatus::Finalized) => Finality::Moved(status.slot),
+            _ => Finality::Pending,
+        },
+    }
+}
+
+/// Settles a transaction without a status from whether its slot is in the finalized chain. A
+/// missing status for a transaction in a finalized block usually means the node has no history
+/// for it, and the block being finalized finalizes the transaction; only a skipped slot proves a
+/// rollback.
+pub fn classify_missing(in_chain: bool) -> Finality {
+    if in_chain {
+        Finality::Finalized
+    } else {
+        Finality::RolledBack
+    }
+}
+
+/// What a finality pass does with the transactions it checked.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Reconciliation {
+    /// Promoted to `finalized`.
+    pub finalized: Vec<String>,
+    /// Deleted: their slot was skipped.
+    pub rolled_back: Vec<String>,
+    /// Deleted and re-ingested from the slot they were finalized in.
+    pub moved: Vec<(String, u64)>,
+}
+
+impl Reconciliation {
+    /// Routes each of `rows`, given its signature status. Rows without a status are settled by
+    /// whether their slot is in the finalized chain, as recorded in `slot_finalized`; those
+    /// whose slot has not been looked up are left for the next pass.
+    pub fn add(
+        &mut self,
+        rows: &[UnfinalizedSignatureRow],
+        statuses: &[Option<TransactionStatus>],
+        slot_finalized: &HashMap<u64, bool>,
+    ) {
+        for (row, status) in rows.iter().zip(statuses) {
+            let finality = match classify(row.slot, status.as_ref()) {
+                Finality::Unknown => slot_finalized
+                    .get(&row.slot)
+                    .map_or(Finality::Unknown, |in_chain| classify_missing(*in_chain)),
+                finality => finality,
+            };
+            match finality {
+                Finality::Finalized => self.finalized.push(row.signature.clone()),
+                Finality::Pending | Finality::Unknown => {}
+                Finality::Moved(slot) => self.moved.push((row.signature.clone(), slot)),
+                Finality::RolledBack => self.rolled_back.push(row.signature.clone()),
+            }
+        }
+    }
+}
+
+/// Periodically promotes fills ingested below `finalized`, and removes those whose slot was
+/// skipped. Nothing to do when ingestion already reads at `finalized`.
+pub async fn run_finality_job(rpc_pool: &RpcPool, config: &Config, clickhouse_client: &Client) {
+    if rpc_pool.commitment().is_finalized() {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        config.ingestion.finality_check_interval_secs,
+    ));
+    loop {
+        interval.tick().await;
+        if let Err(err) = reconcile(rpc_pool, config, clickhouse_client).await {
+            eprintln!("Finality check failed: {}", err);
+        }
+    }
+}
+
+async fn reconcile(
+    rpc_pool: &RpcPool,
+    config: &Config,
+    clickhouse_client: &Client,
+) -> Result<(), FetchError> {
+    let finalized_slot = rpc_pool
+        .call(|rpc| async move {
+            rpc.get_slot_with_commitment(CommitmentConfig::finalized())
+                .await
+        })
+        .await?;
+    let pending = fetch_unfinalized_signatures(
+        clickhouse_client,
+        finalized_slot,
+        config.ingestion.finality_batch_size,
+    )
+    .await?;
+
+    let mut reconciliation = Reconciliation::default();
+    // Whether each slot made it into the finalized chain, fetched lazily
+    let mut slot_finalized: HashMap<u64, bool> = HashMap::new();
+
+    for chunk in pending.chunks(MAX_SIGNATURE_STATUSES) {
+        let signatures = chunk
+            .iter()
+            .map(|row| {
+                Signature::from_str(&row.signature)
+                    .map_err(|_| FetchError::InvalidSignature(row.signature.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let signatures = &signatures;
+        let statuses = rpc_pool
+            .call(|rpc| async move { rpc.get_signature_statuses_with_history(signatures).await })
+            .await?
+            .value;
+
+        for (row, status) in chunk.iter().zip(statuses.iter()) {
+            let slot = row.slot;
+            if status.is_some() || slot_finalized.contains_key(&slot) {
+                continue;
+            }
+            let blocks = rpc_pool
+                .call(|rpc| async move {
+                    rpc.get_blocks_with_commitment(slot, Some(slot), CommitmentConfig::finalized())
+                        .await
+                })
+                .await?;
+            slot_finalized.insert(slot, blocks.contains(&slot));
+        }
+        reconciliation.add(chunk, &statuses, &slot_finalized);
+    }
+    let Reconciliation {
+        finalized,
+        rolled_back,
+        moved,
+    } = reconciliation;
+
+    promote_signatures(clickhouse_client, &finalized).await?;
+    delete_signatures(clickhouse_client, &rolled_back).await?;
+    let moved_signatures = moved
+        .iter()
+        .map(|(signature, _)| signature.clone())
+        .collect::<Vec<_>>();
+    delete_signatures(clickhouse_client, &moved_signatures).await?;
+    for (signature, slot) in moved.iter() {
+        // Hand the transaction to the dead-letter retry job to be ingested from its final slot
+        record_failed_signature(
+            clickhouse_client,
+            signature,
+            *slot,
+            FailureStage::Fetch.as_str(),
+            &format!("re-included in slot {} after a fork", slot),
+        )
+        .await?;
+    }
+
+    for signature in rolled_back.iter() {
+        eprintln!("Rolled back fills from {}: its slot was skipped", signature);
+    }
+    metrics()
+        .finality_reconciled_total
+        .with_label_values(&["finalized"])
+        .inc_by(finalized.len() as u64);
+    metrics()
+        .finality_reconciled_total
+        .with_label_values(&["rolled_back"])
+        .inc_by(rolled_back.len() as u64);
+    metrics()
+        .finality_reconciled_total
+        .with_label_values(&["moved"])
+        .inc_by(moved.len() as u64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(slot: u64, confirmation_status: TransactionConfirmationStatus) -> TransactionStatus {
+        TransactionStatus {
+            slot,
+            confirmations: None,
+            status: Ok(()),
+            err: None,
+            confirmation_status: Some(confirmation_status),
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let finalized = status(100, TransactionConfirmationStatus::Finalized);
+        assert_eq!(classify(100, Some(&finalized)), Finality::Finalized);
+        assert_eq!(classify(99, Some(&finalized)), Finality::Moved(100));
+
+        let confirmed = status(100, TransactionConfirmationStatus::Confirmed);
+        assert_eq!(classify(100, Some(&confirmed)), Finality::Pending);
+
+        assert_eq!(classify(100, None), Finality::Unknown);
+    }
+
+    fn row(signature: &str, slot: u64) -> UnfinalizedSignatureRow {
+        UnfinalizedSignatureRow {
+            signature: signature.to_string(),
+            slot,
+        }
+    }
+
+    #[test]
+    fn test_missing_status_in_finalized_block_is_promoted() {
+        let rows = [
+            row("finalized", 100),
+            row("missing_in_chain", 101),
+            row("missing_in_skipped_slot", 102),
+            row("missing_not_looked_up", 103),
+            row("moved", 104),
+            row("confirmed", 105),
+        ];
+        let statuses = [
+            Some(status(100, TransactionConfirmationStatus::Finalized)),
+            None,
+            None,
+            None,
+            Some(status(110, TransactionConfirmationStatus::Finalized)),
+            Some(status(105, TransactionConfirmationStatus::Confirmed)),
+        ];
+        let slot_finalized = HashMap::from([(101, true), (102, false)]);
+
+        let mut reconciliation = Reconciliation::default();
+        reconciliation.add(&rows, &statuses, &slot_finalized);
+        // Otherwise rows without a status would stay at the head of every batch, oldest first
+        assert_eq!(
+            reconciliation,
+            Reconciliation {
+                finalized: vec!["finalized".to_string(), "missing_in_chain".to_string()],
+                rolled_back: vec!["missing_in_skipped_slot".to_string()],
+                moved: vec![("moved".to_string(), 110)],
+            }
+        );
+    }
+}
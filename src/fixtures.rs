@@ -0,0 +1,392 @@
+// fixtures.rs
+use async_trait::async_trait;
+use clickhouse::Client;
+use ellipsis_client::EllipsisClient;
+use phoenix::program::MarketHeader;
+use phoenix_sdk::sdk_client::SDKClient;
+use phoenix_sdk_core::sdk_client_core::{MarketMetadata, SDKClientCore};
+use serde_json::Value;
+use solana_account_decoder::{UiAccount, UiAccountEncoding, UiDataSliceConfig};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signature};
+use std::collections::{BTreeMap, VecDeque};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::pipeline::Pipeline;
+use crate::rpc::RpcPool;
+use crate::sink::{sinks_from_config, BroadcastSink, BROADCAST_CAPACITY};
+use crate::solana::{fetch_transaction, load_sdk_client, FetchError};
+use crate::source::{IngestSource, RawTransaction, SourceItem};
+
+/// Market headers needed to decode the fixtures in a directory, keyed by market address.
+pub const MARKETS_FILE: &str = "markets.json";
+
+/// Replays recorded transactions from disk, in file order.
+///
+/// A `.json` file holds one `getTransaction` result, either bare or wrapped in its JSON-RPC
+/// response; a `.jsonl` file holds one per line. Directories are expanded to the fixture files
+/// they contain, sorted by name.
+pub struct FileSource {
+    files: VecDeque<PathBuf>,
+    pending: VecDeque<RawTransaction>,
+}
+
+impl FileSource {
+    pub fn open(paths: &[PathBuf]) -> Result<Self, FetchError> {
+        let mut files = VecDeque::new();
+        for path in paths {
+            if path.is_dir() {
+                let mut entries = std::fs::read_dir(path)
+                    .map_err(|err| fixture_error(path, err))?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| fixture_error(path, err))?;
+                entries.sort();
+                files.extend(entries.into_iter().filter(|path| is_fixture(path)));
+            } else {
+                files.push_back(path.clone());
+            }
+        }
+        Ok(FileSource {
+            files,
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl IngestSource for FileSource {
+    /// Recorded transactions are historical.
+    fn commitment(&self) -> CommitmentConfig {
+        CommitmentConfig::finalized()
+    }
+
+    async fn next(&mut self) -> Result<Option<SourceItem>, FetchError> {
+        loop {
+            if let Some(tx) = self.pending.pop_front() {
+                return Ok(Some(SourceItem::Transaction(Box::new(tx))));
+            }
+            let Some(path) = self.files.pop_front() else {
+                return Ok(None);
+            };
+            self.pending = read_transactions(&path)?.into();
+        }
+    }
+}
+
+fn is_fixture(path: &Path) -> bool {
+    let is_markets = path.file_name().map_or(false, |name| name == MARKETS_FILE);
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    !is_markets && matches!(extension, Some("json") | Some("jsonl"))
+}
+
+fn fixture_error(path: &Path, err: impl std::fmt::Display) -> FetchError {
+    FetchError::FixtureError(format!("{}: {}", path.display(), err))
+}
+
+fn read_transactions(path: &Path) -> Result<Vec<RawTransaction>, FetchError> {
+    let contents = std::fs::read_to_string(path).map_err(|err| fixture_error(path, err))?;
+    let documents = if path
+        .extension()
+        .map_or(false, |extension| extension == "jsonl")
+    {
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect()
+    } else {
+        vec![contents.as_str()]
+    };
+    documents
+        .into_iter()
+        .map(|document| parse_transaction_json(document).map_err(|err| fixture_error(path, err)))
+        .collect()
+}
+
+/// Parses a `getTransaction` result, accepting the full JSON-RPC response too.
+pub fn parse_transaction_json(document: &str) -> Result<RawTransaction, serde_json::Error> {
+    let mut value: Value = serde_json::from_str(document)?;
+    if let Some(result) = value.get_mut("result") {
+        value = result.take();
+    }
+    serde_json::from_value(value)
+}
+
+/// Reads the market headers recorded next to the fixtures in `dir`, if there are any.
+pub fn load_markets(dir: &Path) -> Result<Option<BTreeMap<Pubkey, MarketMetadata>>, FetchError> {
+    let path = dir.join(MARKETS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&path).map_err(|err| fixture_error(&path, err))?;
+    let accounts: BTreeMap<String, UiAccount> =
+        serde_json::from_str(&contents).map_err(|err| fixture_error(&path, err))?;
+
+    let mut markets = BTreeMap::new();
+    for (market, account) in accounts {
+        let market_key =
+            Pubkey::from_str(&market).map_err(|_| FetchError::InvalidPubkey(market.clone()))?;
+        let account = account
+            .decode::<Account>()
+            .ok_or_else(|| fixture_error(&path, format!("cannot decode market {}", market)))?;
+        if account.data.len() < size_of::<MarketHeader>() {
+            return Err(fixture_error(&path, format!("truncated market {}", market)));
+        }
+        let header = bytemuck::pod_read_unaligned::<MarketHeader>(
+            &account.data[..size_of::<MarketHeader>()],
+        );
+        let metadata = MarketMetadata::from_header(&header)
+            .map_err(|err| fixture_error(&path, format!("market {}: {}", market, err)))?;
+        markets.insert(market_key, metadata);
+    }
+    Ok(Some(markets))
+}
+
+/// An SDK client that decodes events for `markets` without ever touching the network.
+pub async fn offline_sdk_client(
+    markets: BTreeMap<Pubkey, MarketMetadata>,
+) -> Result<SDKClient, FetchError> {
+    // Never used: every market the fixtures refer to is already cached
+    let rpc = RpcClient::new("http://127.0.0.1:8899".to_string());
+    let client = EllipsisClient::from_rpc(rpc, &Keypair::new())?;
+    let mut sdk_client = SDKClient::new_from_ellipsis_client(client).await?;
+    sdk_client.markets = markets;
+    Ok(sdk_client)
+}
+
+/// Runs recorded transactions through the pipeline into the configured sinks. When every
+/// fixture directory has its market headers recorded, no RPC request is made.
+pub async fn replay_fixtures(
+    paths: &[PathBuf],
+    rpc_pool: &Arc<RpcPool>,
+    config: &Config,
+    clickhouse_client: &Client,
+) -> Result<(), FetchError> {
+    let mut markets = BTreeMap::new();
+    let mut offline = true;
+    for path in paths {
+        let dir = if path.is_dir() {
+            path.as_path()
+        } else {
+            path.parent().unwrap_or_else(|| Path::new("."))
+        };
+        match load_markets(dir)? {
+            Some(recorded) => markets.extend(recorded),
+            None => offline = false,
+        }
+    }
+    let sdk_client = if offline {
+        offline_sdk_client(markets).await?
+    } else {
+        load_sdk_client(rpc_pool).await?
+    };
+
+    let sinks = sinks_from_config(
+        config,
+        clickhouse_client,
+        &BroadcastSink::new(BROADCAST_CAPACITY),
+    )
+    .await?;
+    let mut source = FileSource::open(paths)?;
+    Pipeline::new(sdk_client, sinks)
+        .run(&mut source, rpc_pool, config)
+        .await
+}
+
+/// Fetches `signatures` into `dir` as fixtures, one file per transaction, along with the headers
+/// of the markets they touch.
+pub async fn record_fixtures(
+    rpc_pool: &RpcPool,
+    signatures: &[Signature],
+    dir: &Path,
+) -> Result<(), FetchError> {
+    std::fs::create_dir_all(dir).map_err(|err| fixture_error(dir, err))?;
+    let markets_path = dir.join(MARKETS_FILE);
+    let mut accounts: BTreeMap<String, UiAccount> = if markets_path.exists() {
+        let contents = std::fs::read_to_string(&markets_path)
+            .map_err(|err| fixture_error(&markets_path, err))?;
+        serde_json::from_str(&contents).map_err(|err| fixture_error(&markets_path, err))?
+    } else {
+        BTreeMap::new()
+    };
+
+    let core = SDKClientCore {
+        markets: BTreeMap::new(),
+        trader: Pubkey::default(),
+    };
+    for signature in signatures {
+        let tx = fetch_transaction(signature, rpc_pool).await?;
+        let path = dir.join(format!("{}.json", signature));
+        let json = serde_json::to_string_pretty(&tx).map_err(|err| fixture_error(&path, err))?;
+        std::fs::write(&path, json).map_err(|err| fixture_error(&path, err))?;
+
+        let parsed = ellipsis_transaction_utils::parse_transaction(tx);
+        for event in core
+            .parse_events_from_transaction(&parsed)
+            .unwrap_or_default()
+        {
+            let market = event.header.market;
+            if accounts.contains_key(&market.to_string()) {
+                continue;
+            }
+            let account = rpc_pool
+                .call(|rpc| async move { rpc.get_account(&market).await })
+                .await?;
+            // Only the header is needed to decode events
+            let header = UiAccount::encode(
+                &market,
+                &account,
+                UiAccountEncoding::Base64,
+                None,
+                Some(UiDataSliceConfig {
+                    offset: 0,
+                    length: size_of::<MarketHeader>(),
+                }),
+            );
+            accounts.insert(market.to_string(), header);
+        }
+    }
+
+    let json =
+        serde_json::to_string_pretty(&accounts).map_err(|err| fixture_error(&markets_path, err))?;
+    std::fs::write(&markets_path, json).map_err(|err| fixture_error(&markets_path, err))?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sink::ClickHouseSink;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// A Phoenix fill on mainnet, recorded with its market header in `fixtures/mainnet`.
+    pub(crate) const SIGNATURE: &str =
+        "3oYstdr1CJzjkeXmGsuB1k7sxrVkRy7UEGTpDi4ak2BSufqVQiZ5bsXhRcKBK46CBAjywwLKyWFbCZKx7NySzv4p";
+
+    pub(crate) fn recorded_fixtures() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/mainnet")
+    }
+
+    pub(crate) fn recorded_transaction() -> String {
+        std::fs::read_to_string(recorded_fixtures().join(format!("{}.json", SIGNATURE))).unwrap()
+    }
+
+    fn fixture_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fixtures-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Accepts every ClickHouse query on a local port, returning a client for it and the
+    /// statements it received.
+    pub(crate) async fn mock_clickhouse() -> (Client, Arc<Mutex<Vec<String>>>) {
+        let queries = Arc::new(Mutex::new(vec![]));
+        let received = queries.clone();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = axum::Router::new().fallback(
+            move |axum::extract::Query(params): axum::extract::Query<HashMap<String, String>>,
+                  body: String| {
+                let received = received.clone();
+                async move {
+                    // The statement is sent in the body, or in the `query` parameter
+                    let query = params.get("query").cloned().unwrap_or_default();
+                    received.lock().unwrap().push(query + &body);
+                }
+            },
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let client = Client::default()
+            .with_url(url)
+            .with_compression(clickhouse::Compression::None);
+        (client, queries)
+    }
+
+    /// The values of a single-row `INSERT ... VALUES (...)` statement, unquoted.
+    fn inserted_values(query: &str) -> Vec<String> {
+        let start = query.find("VALUES (").unwrap() + "VALUES (".len();
+        let end = query.rfind(')').unwrap();
+        query[start..end]
+            .split(',')
+            .map(|value| value.trim().trim_matches('\'').to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_transaction_json_accepts_rpc_responses() {
+        let bare = recorded_transaction();
+        let response = format!(r#"{{"jsonrpc":"2.0","id":1,"result":{}}}"#, bare);
+
+        assert_eq!(parse_transaction_json(&bare).unwrap().slot, 308_423_799);
+        assert_eq!(parse_transaction_json(&response).unwrap().slot, 308_423_799);
+    }
+
+    #[tokio::test]
+    async fn test_file_source_reads_json_and_jsonl_in_order() {
+        let dir = fixture_dir();
+        let at_slot = |slot: u64| {
+            let mut tx: Value = serde_json::from_str(&recorded_transaction()).unwrap();
+            tx["slot"] = slot.into();
+            tx.to_string()
+        };
+        std::fs::write(dir.join("a.json"), at_slot(1)).unwrap();
+        std::fs::write(dir.join("b.jsonl"), [at_slot(2), at_slot(3)].join("\n")).unwrap();
+        std::fs::write(dir.join(MARKETS_FILE), "{}").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let mut source = FileSource::open(&[dir.clone()]).unwrap();
+        let mut slots = vec![];
+        while let Some(SourceItem::Transaction(tx)) = source.next().await.unwrap() {
+            slots.push(tx.slot);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(slots, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_fill_replays_into_clickhouse() {
+        let dir = recorded_fixtures();
+        let markets = load_markets(&dir).unwrap().unwrap();
+        let sdk_client = offline_sdk_client(markets).await.unwrap();
+        let (client, queries) = mock_clickhouse().await;
+        let mut config = Config::default();
+        config.rpc.urls = vec!["http://127.0.0.1:8899".to_string()];
+        let rpc_pool = Arc::new(RpcPool::from_config(&config.rpc));
+
+        let mut source = FileSource::open(&[dir.clone()]).unwrap();
+        Pipeline::new(sdk_client, vec![Box::new(ClickHouseSink::new(client))])
+            .run(&mut source, &rpc_pool, &config)
+            .await
+            .unwrap();
+
+        // The row the indexer wrote for this fill on mainnet, followed by its commitment
+        let exported = std::fs::read_to_string(dir.join("trade_fill_events.csv")).unwrap();
+        let mut expected = exported
+            .lines()
+            .find(|line| line.contains(SIGNATURE))
+            .unwrap()
+            .split(',')
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        expected.push("finalized".to_string());
+
+        let queries = queries.lock().unwrap();
+        let fills = queries
+            .iter()
+            .filter(|query| query.contains("INSERT INTO trade_fill_events"))
+            .collect::<Vec<_>>();
+        assert_eq!(fills.len(), 1);
+        assert_eq!(inserted_values(fills[0]), expected);
+        assert!(queries.iter().any(|query| {
+            query.contains("INSERT INTO transaction_context") && query.contains(SIGNATURE)
+        }));
+    }
+}
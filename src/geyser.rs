@@ -0,0 +1,399 @@
+// geyser.rs
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+use std::collections::HashMap;
+use std::time::Duration;
+use tonic::transport::ClientTlsConfig;
+use tonic::Status;
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::convert_from::create_tx_with_meta;
+use yellowstone_grpc_proto::prelude::{
+    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest,
+    SubscribeRequestFilterTransactions, SubscribeUpdate, SubscribeUpdateTransaction,
+};
+
+use crate::config::Config;
+use crate::metrics::metrics;
+use crate::rpc::{redact_url, RetryPolicy};
+use crate::solana::FetchError;
+use crate::source::{IngestSource, RawTransaction, SourceItem};
+
+type UpdateStream = BoxStream<'static, Result<SubscribeUpdate, Status>>;
+
+/// Transaction updates for the Phoenix program from a Yellowstone (Geyser) gRPC endpoint.
+///
+/// Updates carry the full transaction and status meta, so events are decoded without a
+/// `getTransaction` round trip. The source reconnects with backoff and yields a
+/// [`SourceItem::Gap`] after every (re)connection.
+pub struct GeyserSource {
+    /// `None` for a source built from a fixed stream, which ends with that stream.
+    endpoint: Option<String>,
+    x_token: Option<String>,
+    program_id: String,
+    commitment: CommitmentConfig,
+    reconnect: RetryPolicy,
+    attempt: u32,
+    stream: Option<UpdateStream>,
+}
+
+impl GeyserSource {
+    pub fn new(config: &Config) -> Result<Self, FetchError> {
+        let endpoint =
+            config.geyser.endpoint.clone().ok_or_else(|| {
+                FetchError::StreamError("no Geyser endpoint configured".to_string())
+            })?;
+        Ok(GeyserSource {
+            endpoint: Some(endpoint),
+            x_token: config.geyser.x_token.clone(),
+            program_id: config.ingestion.program_id.clone(),
+            commitment: config.rpc.commitment_config(),
+            reconnect: RetryPolicy {
+                max_retries: u32::MAX,
+                base: Duration::from_millis(config.rpc.backoff_base_ms),
+                max: Duration::from_millis(config.rpc.backoff_max_ms),
+            },
+            attempt: 0,
+            stream: None,
+        })
+    }
+
+    /// A source that reads updates from `stream` instead of a gRPC connection.
+    #[cfg(test)]
+    pub fn from_stream(stream: UpdateStream, commitment: CommitmentConfig) -> Self {
+        GeyserSource {
+            endpoint: None,
+            x_token: None,
+            program_id: phoenix::id().to_string(),
+            commitment,
+            reconnect: RetryPolicy {
+                max_retries: 0,
+                base: Duration::ZERO,
+                max: Duration::ZERO,
+            },
+            attempt: 0,
+            stream: Some(stream),
+        }
+    }
+
+    async fn wait_to_reconnect(&mut self) {
+        if self.endpoint.is_none() {
+            return;
+        }
+        // Nothing is being ingested until the stream is back and the gap is filled
+        metrics().ingestion_lag_slots.set(-1);
+        let delay = self.reconnect.backoff(self.attempt);
+        eprintln!("Reconnecting to the Geyser stream in {:?}", delay);
+        tokio::time::sleep(delay).await;
+        self.attempt = self.attempt.saturating_add(1);
+    }
+
+    fn subscribe_request(&self) -> SubscribeRequest {
+        let commitment = if self.commitment.is_finalized() {
+            CommitmentLevel::Finalized
+        } else if self.commitment.is_confirmed() {
+            CommitmentLevel::Confirmed
+        } else {
+            CommitmentLevel::Processed
+        };
+        SubscribeRequest {
+            transactions: HashMap::from([(
+                "phoenix".to_string(),
+                SubscribeRequestFilterTransactions {
+                    vote: Some(false),
+                    // Failed transactions are ingested for their transaction context
+                    failed: None,
+                    account_include: vec![self.program_id.clone()],
+                    ..Default::default()
+                },
+            )]),
+            commitment: Some(commitment as i32),
+            ..Default::default()
+        }
+    }
+
+    async fn connect(&self, endpoint: &str) -> Result<UpdateStream, FetchError> {
+        let tls_config = endpoint.starts_with("https://").then(ClientTlsConfig::new);
+        let mut client =
+            GeyserGrpcClient::connect(endpoint.to_string(), self.x_token.clone(), tls_config)
+                .map_err(|err| FetchError::StreamError(err.to_string()))?;
+        let stream = client
+            .subscribe_once2(self.subscribe_request())
+            .await
+            .map_err(|err| FetchError::StreamError(err.to_string()))?;
+        Ok(stream.boxed())
+    }
+}
+
+/// Converts a transaction update to the `getTransaction` representation. Other updates (pings,
+/// slots) yield `None`.
+pub fn transaction_from_update(
+    update: SubscribeUpdate,
+) -> Result<Option<RawTransaction>, FetchError> {
+    let Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+        transaction: Some(info),
+        slot,
+    })) = update.update_oneof
+    else {
+        return Ok(None);
+    };
+    let tx = create_tx_with_meta(info)
+        .map_err(|err| FetchError::StreamError(format!("invalid transaction update: {}", err)))?;
+    let transaction = tx
+        .encode(UiTransactionEncoding::Json, Some(0), false)
+        .map_err(|err| FetchError::StreamError(format!("cannot encode transaction: {}", err)))?;
+    Ok(Some(EncodedConfirmedTransactionWithStatusMeta {
+        slot,
+        transaction,
+        // Not part of transaction updates; Phoenix events carry their own timestamp
+        block_time: None,
+    }))
+}
+
+#[async_trait]
+impl IngestSource for GeyserSource {
+    fn commitment(&self) -> CommitmentConfig {
+        self.commitment
+    }
+
+    async fn next(&mut self) -> Result<Option<SourceItem>, FetchError> {
+        loop {
+            let Some(stream) = self.stream.as_mut() else {
+                let Some(endpoint) = self.endpoint.clone() else {
+                    return Ok(None);
+                };
+                match self.connect(&endpoint).await {
+                    Ok(stream) => {
+                        self.stream = Some(stream);
+                        return Ok(Some(SourceItem::Gap));
+                    }
+                    Err(err) => {
+                        eprintln!("Failed to subscribe to {}: {}", redact_url(&endpoint), err);
+                        self.wait_to_reconnect().await;
+                        continue;
+                    }
+                }
+            };
+
+            let update = stream.next().await;
+            match update {
+                Some(Ok(update)) => match transaction_from_update(update) {
+                    Ok(Some(tx)) => {
+                        // Back off from scratch after a connection that made progress
+                        self.attempt = 0;
+                        // Updates arrive as transactions land, so the stream is at the tip
+                        metrics().ingestion_lag_slots.set(0);
+                        return Ok(Some(SourceItem::Transaction(Box::new(tx))));
+                    }
+                    Ok(None) => {}
+                    Err(err) => eprintln!("Skipping Geyser update: {}", err),
+                },
+                Some(Err(status)) => {
+                    eprintln!("Geyser stream failed: {}", status);
+                    self.stream = None;
+                    self.wait_to_reconnect().await;
+                }
+                None => {
+                    eprintln!("Geyser stream closed");
+                    self.stream = None;
+                    self.wait_to_reconnect().await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::message::Message;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::transaction::{Transaction, VersionedTransaction};
+    use solana_transaction_status::TransactionStatusMeta;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use tonic::{Request, Response, Streaming};
+    use yellowstone_grpc_proto::convert_to::{create_transaction, create_transaction_meta};
+    use yellowstone_grpc_proto::prelude::geyser_server::{Geyser, GeyserServer};
+    use yellowstone_grpc_proto::prelude::{
+        GetBlockHeightRequest, GetBlockHeightResponse, GetLatestBlockhashRequest,
+        GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse, GetVersionRequest,
+        GetVersionResponse, IsBlockhashValidRequest, IsBlockhashValidResponse, PingRequest,
+        PongResponse, SubscribeUpdatePing, SubscribeUpdateTransactionInfo,
+    };
+
+    fn transaction_update(slot: u64) -> (SubscribeUpdate, String) {
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(phoenix::id(), &[], vec![]);
+        let message = Message::new(&[instruction], Some(&payer.pubkey()));
+        let tx =
+            VersionedTransaction::from(Transaction::new(&[&payer], message, Default::default()));
+        let signature = tx.signatures[0];
+
+        let update = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                transaction: Some(SubscribeUpdateTransactionInfo {
+                    signature: signature.as_ref().to_vec(),
+                    is_vote: false,
+                    transaction: Some(create_transaction(&tx)),
+                    meta: Some(create_transaction_meta(&TransactionStatusMeta::default())),
+                    index: 0,
+                }),
+                slot,
+            })),
+            ..Default::default()
+        };
+        (update, signature.to_string())
+    }
+
+    /// Streams two transaction updates on every subscription, then closes it. The updates of
+    /// the n-th subscription are at slots `10n + 1` and `10n + 2`.
+    struct MockGeyser {
+        subscriptions: Arc<AtomicU64>,
+    }
+
+    #[tonic::async_trait]
+    impl Geyser for MockGeyser {
+        type SubscribeStream = UpdateStream;
+
+        async fn subscribe(
+            &self,
+            _request: Request<Streaming<SubscribeRequest>>,
+        ) -> Result<Response<UpdateStream>, Status> {
+            let subscription = self.subscriptions.fetch_add(1, Ordering::SeqCst) + 1;
+            let updates = [1, 2].map(|offset| Ok(transaction_update(subscription * 10 + offset).0));
+            Ok(Response::new(futures::stream::iter(updates).boxed()))
+        }
+
+        async fn ping(&self, _: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+            Err(Status::unimplemented("ping"))
+        }
+
+        async fn get_latest_blockhash(
+            &self,
+            _: Request<GetLatestBlockhashRequest>,
+        ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+            Err(Status::unimplemented("get_latest_blockhash"))
+        }
+
+        async fn get_block_height(
+            &self,
+            _: Request<GetBlockHeightRequest>,
+        ) -> Result<Response<GetBlockHeightResponse>, Status> {
+            Err(Status::unimplemented("get_block_height"))
+        }
+
+        async fn get_slot(
+            &self,
+            _: Request<GetSlotRequest>,
+        ) -> Result<Response<GetSlotResponse>, Status> {
+            Err(Status::unimplemented("get_slot"))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _: Request<IsBlockhashValidRequest>,
+        ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+            Err(Status::unimplemented("is_blockhash_valid"))
+        }
+
+        async fn get_version(
+            &self,
+            _: Request<GetVersionRequest>,
+        ) -> Result<Response<GetVersionResponse>, Status> {
+            Err(Status::unimplemented("get_version"))
+        }
+    }
+
+    /// Serves `geyser` on a local port, returning its URL.
+    async fn serve(geyser: MockGeyser) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        })
+        .boxed();
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(GeyserServer::new(geyser))
+                .serve_with_incoming(incoming)
+                .await
+                .unwrap()
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_with_a_gap_when_the_server_closes_the_stream() {
+        let subscriptions = Arc::new(AtomicU64::new(0));
+        let url = serve(MockGeyser {
+            subscriptions: subscriptions.clone(),
+        })
+        .await;
+        let mut config = Config::default();
+        config.geyser.endpoint = Some(url);
+        config.rpc.backoff_base_ms = 1;
+        config.rpc.backoff_max_ms = 10;
+        let mut source = GeyserSource::new(&config).unwrap();
+
+        let mut items = vec![];
+        for _ in 0..6 {
+            let item = tokio::time::timeout(Duration::from_secs(10), source.next())
+                .await
+                .expect("the source stalled")
+                .unwrap()
+                .unwrap();
+            items.push(match item {
+                SourceItem::Gap => None,
+                SourceItem::Transaction(tx) => Some(tx.slot),
+                SourceItem::Failed { signature, .. } => panic!("{} failed", signature),
+            });
+        }
+
+        assert_eq!(
+            items,
+            vec![None, Some(11), Some(12), None, Some(21), Some(22)]
+        );
+        assert_eq!(subscriptions.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_yields_transactions_and_skips_other_updates() {
+        let (update, signature) = transaction_update(42);
+        let ping = SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Ping(SubscribeUpdatePing {})),
+            ..Default::default()
+        };
+        let mut source = GeyserSource::from_stream(
+            futures::stream::iter(vec![Ok(ping), Ok(update)]).boxed(),
+            CommitmentConfig::confirmed(),
+        );
+
+        let Some(SourceItem::Transaction(tx)) = source.next().await.unwrap() else {
+            panic!("expected a transaction");
+        };
+        assert_eq!(tx.slot, 42);
+        let parsed = ellipsis_transaction_utils::parse_transaction(*tx);
+        assert_eq!(parsed.signature, signature);
+
+        assert!(source.next().await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_subscribe_request_filters_on_the_program() {
+        let source = GeyserSource::from_stream(
+            futures::stream::empty().boxed(),
+            CommitmentConfig::processed(),
+        );
+        let request = source.subscribe_request();
+        let filter = &request.transactions["phoenix"];
+        assert_eq!(filter.account_include, vec![phoenix::id().to_string()]);
+        assert_eq!(filter.vote, Some(false));
+        assert_eq!(filter.failed, None);
+        assert_eq!(request.commitment, Some(CommitmentLevel::Processed as i32));
+    }
+}
@@ -0,0 +1,98 @@
+// health.rs
+use crate::api::AppState;
+use crate::metrics::metrics;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub clickhouse: bool,
+    /// Slots behind the cluster tip, or `null` if ingestion has not reported yet.
+    pub ingestion_lag_slots: Option<i64>,
+}
+
+/// Liveness probe: succeeds as long as the process is serving requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses((status = 200, description = "The process is up", body = String))
+)]
+pub(crate) async fn healthz_handler() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe: ClickHouse must be reachable and ingestion lag below the threshold.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = ReadinessResponse),
+        (status = 503, description = "A dependency is unavailable", body = ReadinessResponse),
+    )
+)]
+pub(crate) async fn readyz_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let clickhouse = match state.clickhouse_client.query("SELECT 1").execute().await {
+        Ok(_) => true,
+        Err(err) => {
+            eprintln!("Readiness check: ClickHouse unreachable: {:?}", err);
+            false
+        }
+    };
+    let lag = metrics().ingestion_lag_slots.get();
+    let ingestion_lag_slots = (lag >= 0).then_some(lag);
+    let max_lag_slots = state.config.ingestion.max_lag_slots;
+    let ingestion_ok = matches!(ingestion_lag_slots, Some(lag) if lag <= max_lag_slots);
+
+    let response = ReadinessResponse {
+        ready: clickhouse && ingestion_ok,
+        clickhouse,
+        ingestion_lag_slots,
+    };
+    let status = if response.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(response))
+}
+
+/// Prometheus metrics in the text exposition format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "Prometheus metrics", body = String))
+)]
+pub(crate) async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        metrics().render(),
+    )
+}
+
+/// Records request latency per matched route.
+pub async fn track_request_latency(request: Request, next: Next) -> Response {
+    // Use the route template rather than the raw path to keep label cardinality bounded
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+
+    let timer = std::time::Instant::now();
+    let response = next.run(request).await;
+
+    metrics()
+        .http_request_duration_seconds
+        .with_label_values(&[&route, &method, response.status().as_str()])
+        .observe(timer.elapsed().as_secs_f64());
+    response
+}
@@ -0,0 +1,85 @@
+// live_orderbook.rs
+use phoenix_sdk::orderbook_watcher::OrderbookWatcher;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::rpc::{redact_url, RetryPolicy, RpcPool};
+use crate::solana::{load_sdk_client, FetchError};
+
+/// Keeps the orderbooks of the watched markets up to date from `accountSubscribe`, resubscribing
+/// with backoff whenever the connection drops. `orderbook_watcher` is set once every book has
+/// been read, and is what the API streams from.
+pub async fn run_orderbook_watcher(
+    rpc_pool: &RpcPool,
+    config: &Config,
+    orderbook_watcher: &OnceLock<OrderbookWatcher>,
+) {
+    if !config.live_orderbook.enabled {
+        return;
+    }
+    let Some(ws_url) = config.rpc.pubsub_url() else {
+        eprintln!("Live orderbooks disabled: no PubSub WebSocket URL configured");
+        return;
+    };
+    let reconnect = RetryPolicy {
+        max_retries: u32::MAX,
+        base: Duration::from_millis(config.rpc.backoff_base_ms),
+        max: Duration::from_millis(config.rpc.backoff_max_ms),
+    };
+
+    let mut attempt = 0;
+    let watcher = loop {
+        match load_orderbook_watcher(rpc_pool, config, &ws_url).await {
+            Ok(watcher) => break orderbook_watcher.get_or_init(|| watcher),
+            Err(err) => {
+                eprintln!("Failed to load the live orderbooks: {}", err);
+                tokio::time::sleep(reconnect.backoff(attempt)).await;
+                attempt = attempt.saturating_add(1);
+            }
+        }
+    };
+
+    attempt = 0;
+    loop {
+        match watcher.run().await {
+            Ok(()) => {
+                eprintln!(
+                    "Market account subscription to {} closed",
+                    redact_url(&ws_url)
+                );
+                attempt = 0;
+            }
+            Err(err) => {
+                eprintln!(
+                    "Market account subscription to {} failed: {}",
+                    redact_url(&ws_url),
+                    err
+                );
+                attempt = attempt.saturating_add(1);
+            }
+        }
+        tokio::time::sleep(reconnect.backoff(attempt)).await;
+    }
+}
+
+async fn load_orderbook_watcher(
+    rpc_pool: &RpcPool,
+    config: &Config,
+    ws_url: &str,
+) -> Result<OrderbookWatcher, FetchError> {
+    let sdk_client = load_sdk_client(rpc_pool).await?;
+    let markets = if config.live_orderbook.markets.is_empty() {
+        sdk_client.markets.keys().copied().collect::<Vec<_>>()
+    } else {
+        config
+            .live_orderbook
+            .markets
+            .iter()
+            .filter_map(|market| Pubkey::from_str(market).ok())
+            .collect()
+    };
+    Ok(sdk_client.get_orderbook_watcher(ws_url, &markets).await?)
+}
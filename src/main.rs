@@ -1,57 +1,169 @@
 // main.rs
 use crate::api::start_api_server;
-use crate::solana::fetch_and_parse_transactions;
+use crate::cli::{run_failed_signatures_command, run_fixtures_command, Cli, Command};
+use crate::config::Config;
+use crate::dead_letter::run_retry_job;
+use crate::finality::run_finality_job;
+use crate::live_orderbook::run_orderbook_watcher;
+use crate::pipeline::run_ingestion;
+use crate::rpc::RpcPool;
+use crate::sink::{BroadcastSink, BROADCAST_CAPACITY};
+use crate::snapshot::run_snapshot_job;
+use clap::Parser;
 use dotenv::dotenv;
-use solana_client::rpc_client::RpcClient;
-use std::env;
+use std::sync::{Arc, OnceLock};
 use tokio;
 
 mod api;
+mod cli;
+mod config;
 mod database;
+mod dead_letter;
+mod error;
+mod finality;
+mod fixtures;
+mod geyser;
+mod health;
+mod live_orderbook;
+mod metrics;
+mod openapi;
+mod pipeline;
+mod rpc;
+mod rpc_sender;
+mod sink;
+mod snapshot;
 mod solana;
+mod source;
+mod stream;
+mod udf;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load environment variables from the .env file
     dotenv().ok();
+    let cli = Cli::parse();
 
-    // Initialize Solana RPC Client
-    let helius_api_key = env::var("HELIUS_API_KEY")?;
-    let rpc_url = format!("https://rpc.helius.xyz/?api-key={}", helius_api_key);
-    let client = RpcClient::new(rpc_url.to_string());
+    // Load and validate the configuration before starting any subsystem
+    let config = Arc::new(Config::load()?);
+    let clickhouse_client = config.clickhouse.client();
 
-    // Address of the Phoenix DEX Program
-    let phoenix_program_id = "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY";
+    // Initialize the Solana RPC endpoints, in order of preference
+    let rpc_pool = Arc::new(RpcPool::from_config(&config.rpc));
 
-    // Call the fetch_and_parse_transactions function
-    // Run both tasks concurrently
+    match cli.command {
+        Some(Command::FailedSignatures(command)) => {
+            run_failed_signatures_command(command, &rpc_pool, &clickhouse_client).await?;
+            return Ok(());
+        }
+        Some(Command::Fixtures(command)) => {
+            run_fixtures_command(command, &rpc_pool, &config, &clickhouse_client).await?;
+            return Ok(());
+        }
+        Some(Command::Serve) | None => {}
+    }
+
+    // Run ingestion, the background jobs and the API server concurrently
+    let events = BroadcastSink::new(BROADCAST_CAPACITY);
+    let ingestion_events = events.clone();
+    let ingestion_config = config.clone();
+    let ingestion_clickhouse_client = clickhouse_client.clone();
+    let ingestion_rpc_pool = rpc_pool.clone();
     let fetch_and_parse_task = tokio::spawn(async move {
-        match fetch_and_parse_transactions(&client, phoenix_program_id).await {
+        // Stream new transactions from Geyser or PubSub as they land, or poll the latest batch
+        // once, and write their events to the configured sinks
+        match run_ingestion(
+            &ingestion_rpc_pool,
+            &ingestion_config,
+            &ingestion_clickhouse_client,
+            &ingestion_events,
+        )
+        .await
+        {
             Ok(_) => println!("Transactions fetched and processed successfully."),
             Err(err) => eprintln!("Error fetching or processing transactions: {:?}", err),
         }
     });
 
+    // Promote or roll back fills ingested below finalized
+    let finality_config = config.clone();
+    let finality_clickhouse_client = clickhouse_client.clone();
+    let finality_rpc_pool = rpc_pool.clone();
+    let finality_task = tokio::spawn(async move {
+        run_finality_job(
+            &finality_rpc_pool,
+            &finality_config,
+            &finality_clickhouse_client,
+        )
+        .await;
+    });
+
+    // Store orderbook snapshots of the tracked markets on a fixed cadence
+    let snapshot_config = config.clone();
+    let snapshot_clickhouse_client = clickhouse_client.clone();
+    let snapshot_rpc_pool = rpc_pool.clone();
+    let snapshot_task = tokio::spawn(async move {
+        run_snapshot_job(
+            &snapshot_rpc_pool,
+            &snapshot_config,
+            &snapshot_clickhouse_client,
+        )
+        .await;
+    });
+
+    // Keep the live orderbooks up to date from market account subscriptions
+    let orderbook_watcher = Arc::new(OnceLock::new());
+    let watcher_config = config.clone();
+    let watcher_rpc_pool = rpc_pool.clone();
+    let api_orderbook_watcher = orderbook_watcher.clone();
+    let watcher_task = tokio::spawn(async move {
+        run_orderbook_watcher(&watcher_rpc_pool, &watcher_config, &orderbook_watcher).await;
+    });
+
+    // Replay dead-lettered signatures in the background
+    let retry_config = config.clone();
+    let retry_clickhouse_client = clickhouse_client.clone();
+    let retry_task = tokio::spawn(async move {
+        run_retry_job(&rpc_pool, &retry_config, &retry_clickhouse_client).await;
+    });
+
     // Start the API server
-    let start_api_server_task = tokio::spawn(async {
-        if let Err(err) = start_api_server().await {
+    let start_api_server_task = tokio::spawn(async move {
+        if let Err(err) =
+            start_api_server(config, clickhouse_client, api_orderbook_watcher, events).await
+        {
             eprintln!("Error starting API server: {:?}", err);
         }
     });
 
     // Wait for either task to fail (ideally, they should run forever)
-    let (fetch_result, api_result) = tokio::join!(fetch_and_parse_task, start_api_server_task);
+    let (fetch_result, api_result, retry_result, finality_result, snapshot_result, watcher_result) = tokio::join!(
+        fetch_and_parse_task,
+        start_api_server_task,
+        retry_task,
+        finality_task,
+        snapshot_task,
+        watcher_task
+    );
 
     // Handle unexpected task exits
     if let Err(err) = fetch_result {
-        eprintln!(
-            "fetch_and_parse_transactions task exited unexpectedly: {:?}",
-            err
-        );
+        eprintln!("Ingestion task exited unexpectedly: {:?}", err);
     }
     if let Err(err) = api_result {
         eprintln!("start_api_server task exited unexpectedly: {:?}", err);
     }
+    if let Err(err) = retry_result {
+        eprintln!("Dead-letter retry task exited unexpectedly: {:?}", err);
+    }
+    if let Err(err) = finality_result {
+        eprintln!("Finality task exited unexpectedly: {:?}", err);
+    }
+    if let Err(err) = snapshot_result {
+        eprintln!("Orderbook snapshot task exited unexpectedly: {:?}", err);
+    }
+    if let Err(err) = watcher_result {
+        eprintln!("Live orderbook task exited unexpectedly: {:?}", err);
+    }
 
     Ok(())
 }
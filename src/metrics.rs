@@ -0,0 +1,268 @@
+// metrics.rs
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+
+/// Process-wide Prometheus metrics, shared by the API server and the ingestion task.
+pub struct Metrics {
+    pub registry: Registry,
+    /// Request latency per route, method and status code.
+    pub http_request_duration_seconds: HistogramVec,
+    /// Requests rejected by the per-user rate limiter.
+    pub rate_limit_rejections_total: IntCounter,
+    /// Credits deducted from users.
+    pub credits_consumed_total: IntCounter,
+    /// Slots between the cluster tip and the newest ingested transaction; -1 until the first
+    /// ingestion pass has reported.
+    pub ingestion_lag_slots: IntGauge,
+    /// Signatures handed to the parser.
+    pub signatures_processed_total: IntCounter,
+    /// Transactions whose events could not be parsed, by stage.
+    pub parse_failures_total: IntCounterVec,
+    /// Parts of transactions that were skipped or only partially decoded, by kind
+    /// (`market_not_loaded`, `market_metadata_unavailable`, `unknown_event`).
+    pub parse_warnings_total: IntCounterVec,
+    /// Latency of inserts into ClickHouse.
+    pub clickhouse_insert_duration_seconds: Histogram,
+    /// RPC request latency per endpoint and outcome (`ok`, `error`, `timeout`, `rate_limited`,
+    /// `rejected`).
+    pub rpc_request_duration_seconds: HistogramVec,
+    /// 1 if the endpoint is currently in rotation, 0 while it is cooling down.
+    pub rpc_endpoint_healthy: IntGaugeVec,
+    /// Retry rounds started after every RPC endpoint failed.
+    pub rpc_retries_total: IntCounter,
+    /// HTTP 429 responses per endpoint.
+    pub rpc_rate_limited_total: IntCounterVec,
+    /// Replays of dead-lettered signatures, by outcome (`resolved`, `failed`).
+    pub dead_letter_replays_total: IntCounterVec,
+    /// Transactions reconciled against the finalized chain, by outcome (`finalized`,
+    /// `rolled_back`, `moved`).
+    pub finality_reconciled_total: IntCounterVec,
+    /// Orderbook snapshots taken, by outcome (`ok`, `error`).
+    pub orderbook_snapshots_total: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency in seconds",
+            ),
+            &["route", "method", "status"],
+        )
+        .unwrap();
+        let rate_limit_rejections_total = IntCounter::new(
+            "rate_limit_rejections_total",
+            "Requests rejected by the rate limiter",
+        )
+        .unwrap();
+        let credits_consumed_total =
+            IntCounter::new("credits_consumed_total", "Credits deducted from users").unwrap();
+        let ingestion_lag_slots = IntGauge::new(
+            "ingestion_lag_slots",
+            "Slots between the cluster tip and the newest ingested transaction",
+        )
+        .unwrap();
+        ingestion_lag_slots.set(-1);
+        let signatures_processed_total = IntCounter::new(
+            "signatures_processed_total",
+            "Transaction signatures processed by the ingestion task",
+        )
+        .unwrap();
+        let parse_failures_total = IntCounterVec::new(
+            Opts::new(
+                "parse_failures_total",
+                "Transactions that failed to be parsed",
+            ),
+            &["stage"],
+        )
+        .unwrap();
+        let parse_warnings_total = IntCounterVec::new(
+            Opts::new(
+                "parse_warnings_total",
+                "Parts of transactions skipped or only partially decoded",
+            ),
+            &["kind"],
+        )
+        .unwrap();
+        let clickhouse_insert_duration_seconds = Histogram::with_opts(HistogramOpts::new(
+            "clickhouse_insert_duration_seconds",
+            "ClickHouse insert latency in seconds",
+        ))
+        .unwrap();
+        let rpc_request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "rpc_request_duration_seconds",
+                "Solana RPC request latency in seconds",
+            ),
+            &["endpoint", "outcome"],
+        )
+        .unwrap();
+        let rpc_endpoint_healthy = IntGaugeVec::new(
+            Opts::new(
+                "rpc_endpoint_healthy",
+                "Whether the RPC endpoint is currently in rotation",
+            ),
+            &["endpoint"],
+        )
+        .unwrap();
+        let rpc_retries_total = IntCounter::new(
+            "rpc_retries_total",
+            "RPC retry rounds after every endpoint failed",
+        )
+        .unwrap();
+        let rpc_rate_limited_total = IntCounterVec::new(
+            Opts::new(
+                "rpc_rate_limited_total",
+                "HTTP 429 responses from RPC endpoints",
+            ),
+            &["endpoint"],
+        )
+        .unwrap();
+        let dead_letter_replays_total = IntCounterVec::new(
+            Opts::new(
+                "dead_letter_replays_total",
+                "Replays of signatures from the dead-letter store",
+            ),
+            &["outcome"],
+        )
+        .unwrap();
+        let finality_reconciled_total = IntCounterVec::new(
+            Opts::new(
+                "finality_reconciled_total",
+                "Transactions ingested below finalized that were promoted or rolled back",
+            ),
+            &["outcome"],
+        )
+        .unwrap();
+        let orderbook_snapshots_total = IntCounterVec::new(
+            Opts::new(
+                "orderbook_snapshots_total",
+                "Orderbook snapshots stored in orderbook_snapshots, or failed",
+            ),
+            &["outcome"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(http_request_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rate_limit_rejections_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(credits_consumed_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(ingestion_lag_slots.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(signatures_processed_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(parse_failures_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(parse_warnings_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(clickhouse_insert_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rpc_request_duration_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rpc_endpoint_healthy.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rpc_retries_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(rpc_rate_limited_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(dead_letter_replays_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(finality_reconciled_total.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(orderbook_snapshots_total.clone()))
+            .unwrap();
+
+        Metrics {
+            registry,
+            http_request_duration_seconds,
+            rate_limit_rejections_total,
+            credits_consumed_total,
+            ingestion_lag_slots,
+            signatures_processed_total,
+            parse_failures_total,
+            parse_warnings_total,
+            clickhouse_insert_duration_seconds,
+            rpc_request_duration_seconds,
+            rpc_endpoint_healthy,
+            rpc_retries_total,
+            rpc_rate_limited_total,
+            dead_letter_replays_total,
+            finality_reconciled_total,
+            orderbook_snapshots_total,
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_all_metrics() {
+        let metrics = metrics();
+        metrics
+            .http_request_duration_seconds
+            .with_label_values(&["/ohlc", "GET", "200"])
+            .observe(0.01);
+        metrics
+            .parse_failures_total
+            .with_label_values(&["parse"])
+            .inc();
+        metrics
+            .parse_warnings_total
+            .with_label_values(&["unknown_event"])
+            .inc();
+
+        let output = metrics.render();
+        for name in [
+            "http_request_duration_seconds",
+            "rate_limit_rejections_total",
+            "credits_consumed_total",
+            "ingestion_lag_slots",
+            "signatures_processed_total",
+            "parse_failures_total",
+            "parse_warnings_total",
+            "clickhouse_insert_duration_seconds",
+            "rpc_retries_total",
+        ] {
+            assert!(output.contains(name), "missing metric {}", name);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// openapi.rs
+use utoipa::OpenApi;
+
+use crate::api::{
+    LevelChangeResponse, OHLCQuery, OHLCResponse, OrderbookDepth, OrderbookDiffResponse,
+    OrderbookLevel, OrderbookOrder, OrderbookSnapshotResponse,
+};
+use crate::error::ErrorBody;
+use crate::health::ReadinessResponse;
+use crate::udf::{
+    UdfConfig, UdfError, UdfExchange, UdfHistory, UdfSearchResult, UdfSymbolInfo, UdfSymbolType,
+};
+
+/// OpenAPI document for the HTTP API, served at `/openapi.json` and rendered at `/swagger-ui`.
+/// New handlers and their request/response types must be registered here.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Solana Phoenix Transaction API",
+        description = "Query trade data for the Phoenix program on Solana"
+    ),
+    paths(
+        crate::api::ohlc_handler,
+        crate::api::orderbook_snapshot_handler,
+        crate::api::orderbook_snapshots_handler,
+        crate::api::orderbook_stream_handler,
+        crate::api::event_stream_handler,
+        crate::udf::config_handler,
+        crate::udf::symbols_handler,
+        crate::udf::search_handler,
+        crate::udf::history_handler,
+        crate::udf::time_handler,
+        crate::health::healthz_handler,
+        crate::health::readyz_handler,
+        crate::health::metrics_handler,
+    ),
+    components(schemas(
+        OHLCResponse,
+        OrderbookSnapshotResponse,
+        OrderbookLevel,
+        OrderbookDepth,
+        OrderbookOrder,
+        OrderbookDiffResponse,
+        LevelChangeResponse,
+        ErrorBody,
+        UdfConfig,
+        UdfExchange,
+        UdfSymbolType,
+        UdfSymbolInfo,
+        UdfSearchResult,
+        UdfHistory,
+        UdfError,
+        ReadinessResponse,
+    ))
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::OrderbookSnapshotRow;
+    use axum::extract::Query;
+    use axum::http::Uri;
+    use serde_json::Value;
+    use std::collections::BTreeSet;
+
+    fn spec() -> Value {
+        serde_json::to_value(ApiDoc::openapi()).unwrap()
+    }
+
+    fn sample_value(schema: &Value) -> &'static str {
+        match schema["type"].as_str() {
+            Some("integer") => "1",
+            _ => "1m",
+        }
+    }
+
+    #[test]
+    fn test_ohlc_query_params_match_handler() {
+        let spec = spec();
+        let params = spec["paths"]["/ohlc"]["get"]["parameters"]
+            .as_array()
+            .expect("/ohlc should document its query parameters");
+
+        // Every documented parameter must be accepted by the handler's extractor, and the
+        // extractor must not require anything that is missing from the spec.
+        let query_string = params
+            .iter()
+            .map(|p| {
+                assert_eq!(p["in"], "query");
+                format!(
+                    "{}={}",
+                    p["name"].as_str().unwrap(),
+                    sample_value(&p["schema"])
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        let uri: Uri = format!("/ohlc?{}", query_string).parse().unwrap();
+        let Query(query) = Query::<OHLCQuery>::try_from_uri(&uri)
+            .expect("documented parameters should deserialize into OHLCQuery");
+        assert_eq!(query.interval, "1m");
+
+        let names = params
+            .iter()
+            .map(|p| p["name"].as_str().unwrap().to_string())
+            .collect::<BTreeSet<_>>();
+        let expected = [
+            "user_id",
+            "base_token_mint",
+            "quote_token_mint",
+            "start_time",
+            "end_time",
+            "interval",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<BTreeSet<_>>();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn test_ohlc_response_schema_matches_serialized_response() {
+        let spec = spec();
+        let properties = spec["components"]["schemas"]["OHLCResponse"]["properties"]
+            .as_object()
+            .expect("OHLCResponse should be registered as a schema");
+
+        let response = serde_json::to_value(OHLCResponse {
+            open: 1,
+            high: 2,
+            low: 0,
+            close: 1,
+        })
+        .unwrap();
+        let serialized_keys = response
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let schema_keys = properties.keys().cloned().collect::<BTreeSet<_>>();
+        assert_eq!(serialized_keys, schema_keys);
+    }
+
+    #[test]
+    fn test_ohlc_error_responses_are_documented() {
+        let spec = spec();
+        let responses = spec["paths"]["/ohlc"]["get"]["responses"]
+            .as_object()
+            .unwrap();
+        for status in ["200", "400", "402", "404", "429", "500"] {
+            assert!(
+                responses.contains_key(status),
+                "missing {} response",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn test_orderbook_snapshot_schema_matches_serialized_response() {
+        let spec = spec();
+        for path in ["/orderbook/snapshot", "/orderbook/snapshots"] {
+            assert!(
+                spec["paths"][path]["get"].is_object(),
+                "{} is missing",
+                path
+            );
+        }
+        let properties = spec["components"]["schemas"]["OrderbookSnapshotResponse"]["properties"]
+            .as_object()
+            .expect("OrderbookSnapshotResponse should be registered as a schema");
+
+        let response = OrderbookSnapshotResponse::from(OrderbookSnapshotRow {
+            market: "4DoNfFBfF7UokCC2FQzriy7yHK6DY6NVdYpuekQ5pRgg".to_string(),
+            timestamp_ms: 1_700_000_000_000,
+            slot: 1,
+            sequence_number: 2,
+            raw_base_units_per_base_lot: 0.001,
+            quote_units_per_raw_base_unit_per_tick: 0.001,
+            best_bid_in_ticks: Some(99_000),
+            best_ask_in_ticks: Some(101_000),
+            mid_price_in_ticks: Some(100_000.0),
+            spread_in_ticks: Some(2_000),
+            spread_bps: Some(200.0),
+            bid_prices_in_ticks: vec![99_000],
+            bid_sizes_in_base_lots: vec![5_000],
+            bid_order_counts: vec![2],
+            ask_prices_in_ticks: vec![101_000],
+            ask_sizes_in_base_lots: vec![1_000],
+            ask_order_counts: vec![1],
+            depth_bps: vec![100, 500],
+            bid_depth_in_base_lots: vec![0, 5_000],
+            ask_depth_in_base_lots: vec![0, 1_000],
+            orders: vec![],
+        });
+        assert_eq!(response.mid_price, Some(100.0));
+        assert_eq!(response.bids[0].size, 5.0);
+        assert_eq!(response.depth[1].ask_base_lots, 1_000);
+
+        let response = serde_json::to_value(response).unwrap();
+        let serialized_keys = response
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let schema_keys = properties.keys().cloned().collect::<BTreeSet<_>>();
+        assert_eq!(serialized_keys, schema_keys);
+    }
+}
@@ -0,0 +1,630 @@
+// pipeline.rs
+use clickhouse::Client;
+use ellipsis_transaction_utils::{parse_transaction, ParsedTransaction};
+use phoenix_sdk::error::PhoenixSdkError;
+use phoenix_sdk::sdk_client::{
+    MarketMetadata, ParseWarning, PhoenixEvent, SDKClient, TransactionContext,
+};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+use crate::dead_letter::record_failure;
+use crate::geyser::GeyserSource;
+use crate::metrics::metrics;
+use crate::rpc::{commitment_label, RetryPolicy, RpcPool};
+use crate::sink::{sinks_from_config, BroadcastSink, EventSink, SinkEvent, SinkTransaction};
+use crate::solana::{load_sdk_client, FetchError};
+use crate::source::{IngestSource, RawTransaction, RpcSource, SourceItem};
+use crate::stream::LogsSource;
+
+/// Decodes the transactions yielded by an [`IngestSource`] into Phoenix events and writes
+/// them to every [`EventSink`].
+pub struct Pipeline {
+    sdk_client: SDKClient,
+    sinks: Vec<Box<dyn EventSink>>,
+    /// Warnings the SDK raised while parsing, drained after every transaction.
+    warnings: mpsc::UnboundedReceiver<ParseWarning>,
+    /// Where transactions that fail to ingest are recorded; they are only logged without it.
+    dead_letter: Option<Client>,
+    /// Set when the SDK client's endpoint failed a request, so it is rebuilt.
+    rpc_failed: bool,
+}
+
+impl Pipeline {
+    pub fn new(mut sdk_client: SDKClient, sinks: Vec<Box<dyn EventSink>>) -> Self {
+        let (sender, warnings) = mpsc::unbounded_channel();
+        sdk_client.set_warning_channel(sender);
+        Pipeline {
+            sdk_client,
+            sinks,
+            warnings,
+            dead_letter: None,
+            rpc_failed: false,
+        }
+    }
+
+    pub fn with_dead_letter(mut self, clickhouse_client: Client) -> Self {
+        self.dead_letter = Some(clickhouse_client);
+        self
+    }
+
+    /// Ingests everything `source` yields until it is exhausted.
+    ///
+    /// When the source reports a gap, the transactions since the last one seen are backfilled
+    /// by polling RPC. Transactions that show up in both are ingested twice, which is harmless
+    /// because ingestion is idempotent.
+    pub async fn run(
+        &mut self,
+        source: &mut dyn IngestSource,
+        rpc_pool: &Arc<RpcPool>,
+        config: &Config,
+    ) -> Result<(), FetchError> {
+        let commitment = commitment_label(source.commitment());
+        let mut last_seen = None;
+        while let Some(item) = source.next().await? {
+            if let SourceItem::Gap = item {
+                self.backfill(rpc_pool, config, &mut last_seen).await;
+            } else {
+                self.handle(item, commitment, &mut last_seen).await;
+            }
+            self.reload_sdk_client(rpc_pool).await;
+        }
+        Ok(())
+    }
+
+    /// Polls RPC for everything after `last_seen`, retrying with backoff until it succeeds so
+    /// the gap is never skipped. Progress is kept across attempts.
+    async fn backfill(
+        &mut self,
+        rpc_pool: &Arc<RpcPool>,
+        config: &Config,
+        last_seen: &mut Option<Signature>,
+    ) {
+        let retry = RetryPolicy {
+            max_retries: u32::MAX,
+            base: Duration::from_millis(config.rpc.backoff_base_ms),
+            max: Duration::from_millis(config.rpc.backoff_max_ms),
+        };
+        let mut attempt = 0;
+        loop {
+            let mut backfill = RpcSource::new(rpc_pool.clone(), config, *last_seen);
+            let commitment = commitment_label(backfill.commitment());
+            let result = async {
+                while let Some(item) = backfill.next().await? {
+                    self.handle(item, commitment, last_seen).await;
+                    self.reload_sdk_client(rpc_pool).await;
+                }
+                Ok::<(), FetchError>(())
+            }
+            .await;
+            let Err(err) = result else {
+                return;
+            };
+
+            let delay = retry.backoff(attempt);
+            eprintln!("Backfill failed, retrying in {:?}: {}", delay, err);
+            tokio::time::sleep(delay).await;
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    /// The SDK client is bound to a single endpoint, so it is rebuilt against whichever one is
+    /// serving after an RPC error. The old client is kept until loading succeeds.
+    async fn reload_sdk_client(&mut self, rpc_pool: &RpcPool) {
+        if !self.rpc_failed {
+            return;
+        }
+        match load_sdk_client(rpc_pool).await {
+            Ok(mut sdk_client) => {
+                let (sender, warnings) = mpsc::unbounded_channel();
+                sdk_client.set_warning_channel(sender);
+                self.sdk_client = sdk_client;
+                self.warnings = warnings;
+                self.rpc_failed = false;
+            }
+            Err(err) => eprintln!("Failed to reload markets: {}", err),
+        }
+    }
+
+    async fn handle(
+        &mut self,
+        item: SourceItem,
+        commitment: &'static str,
+        last_seen: &mut Option<Signature>,
+    ) {
+        match item {
+            SourceItem::Transaction(raw) => {
+                let slot = raw.slot;
+                let context = transaction_context(&raw);
+                let tx = parse_transaction(*raw);
+                let Ok(signature) = Signature::from_str(&tx.signature) else {
+                    eprintln!("Invalid signature from ingest source: {}", tx.signature);
+                    return;
+                };
+                metrics().signatures_processed_total.inc();
+                if let Err(err) = self.process(&tx, context.as_ref(), commitment).await {
+                    self.record_failure(&signature, slot, &err).await;
+                }
+                *last_seen = Some(signature);
+            }
+            SourceItem::Failed {
+                signature,
+                slot,
+                error,
+            } => {
+                metrics().signatures_processed_total.inc();
+                self.record_failure(&signature, slot, &error).await;
+                *last_seen = Some(signature);
+            }
+            // Backfilled by `run`
+            SourceItem::Gap => {}
+        }
+    }
+
+    /// Decodes the events of one transaction, read at `commitment`, and writes them to every
+    /// sink, preceded by the transaction's `context` when it is known. Failed transactions emit
+    /// no events; only their context is written.
+    pub async fn process(
+        &mut self,
+        tx: &ParsedTransaction,
+        context: Option<&TransactionContext>,
+        commitment: &'static str,
+    ) -> Result<(), FetchError> {
+        let signature = &tx.signature;
+        let events = if tx.is_err {
+            vec![]
+        } else {
+            let events = self
+                .sdk_client
+                .parse_events_from_parsed_transaction(tx)
+                .await;
+            self.drain_warnings();
+            match events {
+                Ok(events) => events,
+                Err(err) => {
+                    metrics()
+                        .parse_failures_total
+                        .with_label_values(&["parse"])
+                        .inc();
+                    eprintln!("Failed to parse events of {}: {}", signature, err);
+                    return Err(FetchError::EventParseError(signature.to_string()));
+                }
+            }
+        };
+
+        // Keep going after a failure so one bad event does not hold back the rest; the first
+        // error is returned so the signature lands in the dead-letter store
+        let mut first_error = None;
+        if let Some(context) = context {
+            if let Err(err) = self
+                .write_transaction(tx, context, &events, commitment)
+                .await
+            {
+                first_error = Some(err);
+            }
+        }
+        for event in events {
+            let metadata = match self.market_metadata(&event.market).await {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    // Writing with default metadata would store nonsensical prices
+                    metrics()
+                        .parse_failures_total
+                        .with_label_values(&["metadata"])
+                        .inc();
+                    eprintln!(
+                        "Failed to fetch metadata for market {}, skipping event in {}: {:?}",
+                        event.market, signature, err
+                    );
+                    first_error
+                        .get_or_insert(FetchError::MarketMetadataError(event.market.to_string()));
+                    continue;
+                }
+            };
+
+            let event = SinkEvent {
+                event,
+                metadata,
+                commitment,
+            };
+            for sink in self.sinks.iter_mut() {
+                if let Err(err) = sink.write(&event).await {
+                    metrics()
+                        .parse_failures_total
+                        .with_label_values(&["insert"])
+                        .inc();
+                    eprintln!(
+                        "Failed to write event to the {} sink: {:?}",
+                        sink.name(),
+                        err
+                    );
+                    first_error.get_or_insert(FetchError::InsertionError(err.to_string()));
+                }
+            }
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the metadata of `market`. Markets created after the SDK client was loaded are
+    /// fetched once and cached.
+    async fn market_metadata(
+        &mut self,
+        market: &Pubkey,
+    ) -> Result<MarketMetadata, PhoenixSdkError> {
+        if let Ok(metadata) = self.sdk_client.get_market_metadata_from_cache(market) {
+            return Ok(*metadata);
+        }
+        if let Err(err) = self.sdk_client.add_market(market).await {
+            self.rpc_failed |= matches!(err, PhoenixSdkError::RpcError(_));
+            return Err(err);
+        }
+        self.sdk_client
+            .get_market_metadata_from_cache(market)
+            .copied()
+    }
+
+    /// Writes the context of `tx` and its Phoenix instructions to every sink, for the markets
+    /// the instructions and `events` refer to. Transactions that touch no market are not
+    /// written.
+    async fn write_transaction(
+        &mut self,
+        tx: &ParsedTransaction,
+        context: &TransactionContext,
+        events: &[PhoenixEvent],
+        commitment: &'static str,
+    ) -> Result<(), FetchError> {
+        let instructions = match self.sdk_client.parse_instructions_from_transaction(tx) {
+            Ok(instructions) => instructions,
+            Err(err) => {
+                // The markets of the events are still known
+                eprintln!(
+                    "Failed to decode the instructions of {}: {}",
+                    tx.signature, err
+                );
+                vec![]
+            }
+        };
+        let mut markets = vec![];
+        let instruction_markets = instructions.iter().filter_map(|ix| ix.market);
+        for market in instruction_markets.chain(events.iter().map(|event| event.market)) {
+            if !markets.contains(&market) {
+                markets.push(market);
+            }
+        }
+        if markets.is_empty() {
+            return Ok(());
+        }
+
+        let transaction = SinkTransaction {
+            context: context.clone(),
+            markets,
+            instructions,
+            commitment,
+        };
+        let mut first_error = None;
+        for sink in self.sinks.iter_mut() {
+            if let Err(err) = sink.write_transaction(&transaction).await {
+                metrics()
+                    .parse_failures_total
+                    .with_label_values(&["insert"])
+                    .inc();
+                eprintln!(
+                    "Failed to write transaction context to the {} sink: {:?}",
+                    sink.name(),
+                    err
+                );
+                first_error.get_or_insert(FetchError::InsertionError(err.to_string()));
+            }
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Logs and counts the warnings raised while parsing; the transaction's other events are
+    /// still ingested.
+    fn drain_warnings(&mut self) {
+        while let Ok(warning) = self.warnings.try_recv() {
+            metrics()
+                .parse_warnings_total
+                .with_label_values(&[warning.kind()])
+                .inc();
+            eprintln!("Parse warning: {}", warning);
+        }
+    }
+
+    async fn record_failure(&self, signature: &Signature, slot: u64, err: &FetchError) {
+        eprintln!("Failed to ingest {}: {}", signature, err);
+        // Keep the signature so the retry job or an operator can replay it
+        if let Some(clickhouse_client) = &self.dead_letter {
+            record_failure(clickhouse_client, signature, slot, err).await;
+        }
+    }
+}
+
+/// Reads the fees, compute units, status and signers of `raw`. Its events are still ingested
+/// when they cannot be read.
+pub fn transaction_context(raw: &RawTransaction) -> Option<TransactionContext> {
+    match TransactionContext::from_encoded_transaction(&raw.transaction, raw.slot, raw.block_time) {
+        Ok(context) => Some(context),
+        Err(err) => {
+            eprintln!("Failed to read the transaction context: {}", err);
+            None
+        }
+    }
+}
+
+/// Runs ingestion with the configured source and sinks: Geyser when `geyser.endpoint` is set,
+/// otherwise the PubSub log stream, or a single RPC poll with `ingestion.streaming` disabled.
+/// The `broadcast` sink publishes on `broadcast`. Setup is retried with backoff until it
+/// succeeds.
+pub async fn run_ingestion(
+    rpc_pool: &Arc<RpcPool>,
+    config: &Config,
+    clickhouse_client: &Client,
+    broadcast: &BroadcastSink,
+) -> Result<(), FetchError> {
+    let retry = RetryPolicy {
+        max_retries: u32::MAX,
+        base: Duration::from_millis(config.rpc.backoff_base_ms),
+        max: Duration::from_millis(config.rpc.backoff_max_ms),
+    };
+
+    let mut attempt = 0;
+    let (mut source, mut pipeline) = loop {
+        match load_ingestion(rpc_pool, config, clickhouse_client, broadcast).await {
+            Ok(loaded) => break loaded,
+            Err(err) => {
+                eprintln!("Failed to set up ingestion: {}", err);
+                tokio::time::sleep(retry.backoff(attempt)).await;
+                attempt = attempt.saturating_add(1);
+            }
+        }
+    };
+    pipeline.run(source.as_mut(), rpc_pool, config).await
+}
+
+async fn load_ingestion(
+    rpc_pool: &Arc<RpcPool>,
+    config: &Config,
+    clickhouse_client: &Client,
+    broadcast: &BroadcastSink,
+) -> Result<(Box<dyn IngestSource>, Pipeline), FetchError> {
+    let source: Box<dyn IngestSource> = if config.geyser.endpoint.is_some() {
+        Box::new(GeyserSource::new(config)?)
+    } else if config.ingestion.streaming {
+        Box::new(LogsSource::new(rpc_pool.clone(), config)?)
+    } else {
+        Box::new(RpcSource::new(rpc_pool.clone(), config, None))
+    };
+
+    let sinks = sinks_from_config(config, clickhouse_client, broadcast).await?;
+    // Market metadata is loaded once rather than per transaction
+    let sdk_client = load_sdk_client(rpc_pool).await?;
+    let pipeline = Pipeline::new(sdk_client, sinks).with_dead_letter(clickhouse_client.clone());
+    Ok((source, pipeline))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::tests::{
+        mock_clickhouse, recorded_fixtures, recorded_transaction, SIGNATURE,
+    };
+    use crate::fixtures::{load_markets, offline_sdk_client, parse_transaction_json};
+    use crate::sink::SinkError;
+    use async_trait::async_trait;
+    use ellipsis_client::EllipsisClient;
+    use serde_json::Value;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_sdk::signature::Keypair;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Keeps what it is given in memory, failing every write with `error` if set.
+    #[derive(Clone, Default)]
+    struct MemorySink {
+        events: Arc<Mutex<Vec<SinkEvent>>>,
+        transactions: Arc<Mutex<Vec<SinkTransaction>>>,
+        error: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl EventSink for MemorySink {
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+
+        async fn write(&mut self, event: &SinkEvent) -> Result<(), SinkError> {
+            self.events.lock().unwrap().push(event.clone());
+            match self.error {
+                Some(error) => Err(SinkError::Database(error.to_string())),
+                None => Ok(()),
+            }
+        }
+
+        async fn write_transaction(
+            &mut self,
+            transaction: &SinkTransaction,
+        ) -> Result<(), SinkError> {
+            self.transactions.lock().unwrap().push(transaction.clone());
+            Ok(())
+        }
+    }
+
+    async fn recorded_pipeline(sinks: Vec<Box<dyn EventSink>>) -> Pipeline {
+        let markets = load_markets(&recorded_fixtures()).unwrap().unwrap();
+        Pipeline::new(offline_sdk_client(markets).await.unwrap(), sinks)
+    }
+
+    fn recorded_market() -> Pubkey {
+        *load_markets(&recorded_fixtures())
+            .unwrap()
+            .unwrap()
+            .keys()
+            .next()
+            .unwrap()
+    }
+
+    /// Answers the first `getAccountInfo` with the recorded market header and fails every
+    /// later request.
+    async fn rpc_serving_market_once() -> String {
+        let markets: Value = serde_json::from_str(
+            &std::fs::read_to_string(recorded_fixtures().join(crate::fixtures::MARKETS_FILE))
+                .unwrap(),
+        )
+        .unwrap();
+        let account = markets
+            .as_object()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::post(move |axum::Json(request): axum::Json<Value>| {
+                let account = account.clone();
+                let requests = requests.clone();
+                async move {
+                    if requests.fetch_add(1, Ordering::SeqCst) > 0 {
+                        return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+                    }
+                    Ok(axum::Json(serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "result": { "context": { "slot": 1 }, "value": account },
+                    })))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_failed_transaction_writes_only_its_context() {
+        // The recorded fill, reverted by a trader instruction (CancelAllOrders) on its market
+        let mut json: Value = serde_json::from_str(&recorded_transaction()).unwrap();
+        let err = serde_json::json!({ "InstructionError": [0, { "Custom": 1 }] });
+        json["meta"]["err"] = err.clone();
+        json["meta"]["status"] = serde_json::json!({ "Err": err });
+        json["transaction"]["message"]["instructions"][0]["accounts"] =
+            serde_json::json!([2, 2, 1, 0]);
+        json["transaction"]["message"]["instructions"][0]["data"] = "7".into();
+        let raw = parse_transaction_json(&json.to_string()).unwrap();
+        let context = transaction_context(&raw);
+        let tx = parse_transaction(raw);
+
+        let sink = MemorySink::default();
+        let mut pipeline = recorded_pipeline(vec![Box::new(sink.clone())]).await;
+        pipeline
+            .process(&tx, context.as_ref(), "confirmed")
+            .await
+            .unwrap();
+
+        assert!(sink.events.lock().unwrap().is_empty());
+        let transactions = sink.transactions.lock().unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert!(transactions[0].context.failure.is_some());
+        assert_eq!(transactions[0].markets, vec![recorded_market()]);
+    }
+
+    #[tokio::test]
+    async fn test_sink_error_is_returned_and_dead_lettered() {
+        let first = MemorySink {
+            error: Some("first"),
+            ..MemorySink::default()
+        };
+        let second = MemorySink {
+            error: Some("second"),
+            ..MemorySink::default()
+        };
+        let healthy = MemorySink::default();
+        let (client, queries) = mock_clickhouse().await;
+        let mut pipeline = recorded_pipeline(vec![
+            Box::new(first),
+            Box::new(second),
+            Box::new(healthy.clone()),
+        ])
+        .await
+        .with_dead_letter(client);
+
+        let raw = parse_transaction_json(&recorded_transaction()).unwrap();
+        let context = transaction_context(&raw);
+        let tx = parse_transaction(raw.clone());
+        let err = pipeline
+            .process(&tx, context.as_ref(), "confirmed")
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, FetchError::InsertionError(error) if error.contains("first")));
+        // The other sinks still get the events
+        assert!(!healthy.events.lock().unwrap().is_empty());
+
+        let mut last_seen = None;
+        pipeline
+            .handle(
+                SourceItem::Transaction(Box::new(raw)),
+                "confirmed",
+                &mut last_seen,
+            )
+            .await;
+        assert_eq!(last_seen.unwrap().to_string(), SIGNATURE);
+        assert!(queries.lock().unwrap().iter().any(|query| {
+            query.contains("INSERT INTO failed_signatures") && query.contains(SIGNATURE)
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_metadata_failure_skips_only_its_events() {
+        let loaded = recorded_market();
+        let unloaded = Pubkey::new_unique();
+        let raw = parse_transaction_json(&recorded_transaction()).unwrap();
+        let context = transaction_context(&raw);
+        let mut tx = parse_transaction(raw);
+        // Log the recorded events a second time, for a market the client has not loaded
+        let mut log = tx.inner_instructions[0][0].clone();
+        let offset = log
+            .instruction
+            .data
+            .windows(32)
+            .position(|window| window == loaded.as_ref())
+            .unwrap();
+        log.instruction.data[offset..offset + 32].copy_from_slice(unloaded.as_ref());
+        tx.inner_instructions[0].push(log);
+
+        // The SDK fetches the unloaded market while parsing, then the endpoint fails the
+        // pipeline's own lookup
+        let url = rpc_serving_market_once().await;
+        let client = EllipsisClient::from_rpc(RpcClient::new(url), &Keypair::new()).unwrap();
+        let mut sdk_client = SDKClient::new_from_ellipsis_client(client).await.unwrap();
+        sdk_client.markets = load_markets(&recorded_fixtures()).unwrap().unwrap();
+        let sink = MemorySink::default();
+        let mut pipeline = Pipeline::new(sdk_client, vec![Box::new(sink.clone())]);
+
+        let err = pipeline
+            .process(&tx, context.as_ref(), "confirmed")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, FetchError::MarketMetadataError(market) if *market == unloaded.to_string())
+        );
+        let events = sink.events.lock().unwrap();
+        assert!(!events.is_empty());
+        assert!(events.iter().all(|event| event.event.market == loaded));
+        // The endpoint failed, so the client is rebuilt before the next transaction
+        assert!(pipeline.rpc_failed);
+    }
+}
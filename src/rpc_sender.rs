@@ -0,0 +1,227 @@
+// rpc_sender.rs
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use solana_client::client_error::reqwest::{
+    self,
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    StatusCode,
+};
+use solana_client::client_error::Result as ClientResult;
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::metrics::metrics;
+use crate::rpc::redact_url;
+
+/// How long every worker holds off after a 429 that carries no `Retry-After` header.
+const DEFAULT_RATE_LIMIT_PAUSE: Duration = Duration::from_secs(1);
+
+/// Client-side requests-per-second budget, shared by every endpoint and ingestion worker.
+///
+/// A token bucket that refills at `requests_per_second` and holds at most one second's worth of
+/// tokens. A rate-limited response pauses the whole budget so that every worker backs off, not
+/// just the one that got throttled.
+pub struct RequestBudget {
+    requests_per_second: f64,
+    state: Mutex<BudgetState>,
+}
+
+struct BudgetState {
+    tokens: f64,
+    refilled_at: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl RequestBudget {
+    pub fn new(requests_per_second: u32) -> Self {
+        let requests_per_second = requests_per_second.max(1) as f64;
+        RequestBudget {
+            requests_per_second,
+            state: Mutex::new(BudgetState {
+                tokens: requests_per_second,
+                refilled_at: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Waits until a request may be sent and takes one token.
+    pub async fn acquire(&self) {
+        loop {
+            match self.try_acquire(Instant::now()) {
+                Ok(()) => return,
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    /// Takes a token, or returns how long to wait before trying again.
+    fn try_acquire(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(until) = state.paused_until {
+            if now < until {
+                return Err(until - now);
+            }
+            state.paused_until = None;
+        }
+
+        let elapsed = now.saturating_duration_since(state.refilled_at);
+        state.tokens = (state.tokens + elapsed.as_secs_f64() * self.requests_per_second)
+            .min(self.requests_per_second);
+        state.refilled_at = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - state.tokens) / self.requests_per_second,
+            ))
+        }
+    }
+
+    /// Stops handing out tokens for `duration`.
+    pub fn pause_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut state = self.state.lock().unwrap();
+        state.paused_until = Some(
+            state
+                .paused_until
+                .map_or(until, |current| current.max(until)),
+        );
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds. HTTP dates are not used by RPC providers.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+#[derive(Deserialize)]
+struct RpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+/// JSON-RPC over HTTP transport that draws every request from a shared [`RequestBudget`].
+///
+/// Unlike the stock `HttpSender`, it does not sleep on HTTP 429 itself: it pauses the shared
+/// budget for the `Retry-After` period and returns the error, leaving the retry decision to
+/// [`crate::rpc::RpcPool`].
+pub struct BudgetedHttpSender {
+    client: reqwest::Client,
+    url: String,
+    name: String,
+    budget: Arc<RequestBudget>,
+    request_id: AtomicU64,
+}
+
+impl BudgetedHttpSender {
+    pub fn new(url: String, budget: Arc<RequestBudget>) -> Self {
+        BudgetedHttpSender {
+            client: reqwest::Client::new(),
+            name: redact_url(&url),
+            url,
+            budget,
+            request_id: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl RpcSender for BudgetedHttpSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        self.budget.acquire().await;
+
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let response = self
+            .client
+            .post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.build_request_json(request_id, params).to_string())
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after)
+                .unwrap_or(DEFAULT_RATE_LIMIT_PAUSE);
+            eprintln!(
+                "RPC endpoint {} is rate limiting requests, pausing for {:?}",
+                self.name, retry_after
+            );
+            metrics()
+                .rpc_rate_limited_total
+                .with_label_values(&[&self.name])
+                .inc();
+            self.budget.pause_for(retry_after);
+        }
+
+        let mut json = response.error_for_status()?.json::<Value>().await?;
+        if json["error"].is_object() {
+            return match serde_json::from_value::<RpcErrorObject>(json["error"].clone()) {
+                Ok(RpcErrorObject { code, message }) => Err(RpcError::RpcResponseError {
+                    code,
+                    message,
+                    data: RpcResponseErrorData::Empty,
+                }
+                .into()),
+                Err(err) => Err(RpcError::RpcRequestError(format!(
+                    "Failed to deserialize RPC error response: {} [{}]",
+                    serde_json::to_string(&json["error"]).unwrap_or_default(),
+                    err
+                ))
+                .into()),
+            };
+        }
+        Ok(json["result"].take())
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_limits_burst_and_refills() {
+        let budget = RequestBudget::new(2);
+        let now = Instant::now();
+
+        assert!(budget.try_acquire(now).is_ok());
+        assert!(budget.try_acquire(now).is_ok());
+        let wait = budget.try_acquire(now).unwrap_err();
+        assert!(wait <= Duration::from_millis(500));
+
+        assert!(budget.try_acquire(now + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn test_pause_blocks_every_caller() {
+        let budget = RequestBudget::new(100);
+        budget.pause_for(Duration::from_secs(2));
+        let wait = budget.try_acquire(Instant::now()).unwrap_err();
+        assert!(wait > Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_retry_after(" 0 "), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+}
@@ -0,0 +1,302 @@
+// sink.rs
+use async_trait::async_trait;
+use clickhouse::Client;
+use solana_sdk::pubkey::Pubkey;
+use std::path::Path;
+use thiserror::Error;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+
+use crate::config::Config;
+use crate::database::{insert_failed_orders, insert_fill_event, insert_transaction_context};
+
+pub use phoenix_sdk_core::{
+    instruction_decoder::DecodedInstruction,
+    market_event::{MarketEventDetails, PhoenixEvent},
+    sdk_client_core::MarketMetadata,
+    transaction_context::TransactionContext,
+};
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("Failed to write event to the database: {0}")]
+    Database(String),
+
+    #[error("Failed to write event: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Unknown sink: {0}")]
+    UnknownSink(String),
+}
+
+/// A decoded Phoenix event together with what is needed to store it.
+#[derive(Debug, Clone)]
+pub struct SinkEvent {
+    pub event: PhoenixEvent,
+    /// Metadata of the event's market, used to convert lots and ticks.
+    pub metadata: MarketMetadata,
+    /// Commitment level the transaction was read at.
+    pub commitment: &'static str,
+}
+
+/// The context of a transaction that touched Phoenix markets, failed or not.
+#[derive(Debug, Clone)]
+pub struct SinkTransaction {
+    pub context: TransactionContext,
+    /// The markets the transaction's Phoenix instructions and events refer to.
+    pub markets: Vec<Pubkey>,
+    /// The transaction's Phoenix instructions, with the orders they placed.
+    pub instructions: Vec<DecodedInstruction>,
+    /// Commitment level the transaction was read at.
+    pub commitment: &'static str,
+}
+
+/// Consumes the events decoded by the ingestion pipeline.
+#[async_trait]
+pub trait EventSink: Send {
+    /// Short name used in logs.
+    fn name(&self) -> &'static str;
+
+    async fn write(&mut self, event: &SinkEvent) -> Result<(), SinkError>;
+
+    /// Called once per transaction, before its events are written. Ignored by default.
+    async fn write_transaction(&mut self, _transaction: &SinkTransaction) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// Stores fills in `trade_fill_events`, transaction contexts in `transaction_context` and the
+/// orders of failed transactions in `failed_orders`; other events are ignored.
+pub struct ClickHouseSink {
+    client: Client,
+}
+
+impl ClickHouseSink {
+    pub fn new(client: Client) -> Self {
+        ClickHouseSink { client }
+    }
+}
+
+#[async_trait]
+impl EventSink for ClickHouseSink {
+    fn name(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    async fn write(&mut self, event: &SinkEvent) -> Result<(), SinkError> {
+        if !matches!(event.event.details, MarketEventDetails::Fill(..)) {
+            return Ok(());
+        }
+        insert_fill_event(
+            &self.client,
+            event.event.clone(),
+            event.metadata,
+            event.commitment,
+        )
+        .await
+        .map_err(|err| SinkError::Database(err.to_string()))
+    }
+
+    async fn write_transaction(&mut self, transaction: &SinkTransaction) -> Result<(), SinkError> {
+        insert_transaction_context(
+            &self.client,
+            &transaction.context,
+            &transaction.markets,
+            transaction.commitment,
+        )
+        .await
+        .map_err(|err| SinkError::Database(err.to_string()))?;
+        let Some(failure) = &transaction.context.failure else {
+            return Ok(());
+        };
+        insert_failed_orders(
+            &self.client,
+            &transaction.context,
+            failure,
+            &transaction.instructions,
+            transaction.commitment,
+        )
+        .await
+        .map_err(|err| SinkError::Database(err.to_string()))
+    }
+}
+
+/// Prints every event to stdout as JSON, one per line.
+pub struct StdoutSink;
+
+#[async_trait]
+impl EventSink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    async fn write(&mut self, event: &SinkEvent) -> Result<(), SinkError> {
+        println!("{}", format_event(event));
+        Ok(())
+    }
+}
+
+/// Appends every event to a file as JSON, one per line.
+pub struct FileSink {
+    file: File,
+}
+
+impl FileSink {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, SinkError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(FileSink { file })
+    }
+}
+
+#[async_trait]
+impl EventSink for FileSink {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn write(&mut self, event: &SinkEvent) -> Result<(), SinkError> {
+        let line = format!("{}\n", format_event(event));
+        self.file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Number of events a broadcast subscriber may fall behind before it misses some.
+pub const BROADCAST_CAPACITY: usize = 1024;
+
+/// Publishes every event on a broadcast channel, for in-process consumers such as the API.
+/// Events are dropped while nobody is subscribed, and slow subscribers miss events rather than
+/// holding back ingestion. Clones share the channel.
+#[derive(Clone)]
+pub struct BroadcastSink {
+    sender: broadcast::Sender<SinkEvent>,
+}
+
+impl BroadcastSink {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        BroadcastSink { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SinkEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[async_trait]
+impl EventSink for BroadcastSink {
+    fn name(&self) -> &'static str {
+        "broadcast"
+    }
+
+    async fn write(&mut self, event: &SinkEvent) -> Result<(), SinkError> {
+        // Only fails when there are no subscribers
+        let _ = self.sender.send(event.clone());
+        Ok(())
+    }
+}
+
+pub fn format_event(event: &SinkEvent) -> String {
+    serde_json::json!({ "commitment": event.commitment, "event": event.event }).to_string()
+}
+
+/// Builds the sinks listed in `ingestion.sinks`. `broadcast` publishes on the given sink's
+/// channel, so its subscribers see the events.
+pub async fn sinks_from_config(
+    config: &Config,
+    clickhouse_client: &Client,
+    broadcast: &BroadcastSink,
+) -> Result<Vec<Box<dyn EventSink>>, SinkError> {
+    let mut sinks: Vec<Box<dyn EventSink>> = vec![];
+    for name in config.ingestion.sinks.iter() {
+        match name.as_str() {
+            "clickhouse" => sinks.push(Box::new(ClickHouseSink::new(clickhouse_client.clone()))),
+            "stdout" => sinks.push(Box::new(StdoutSink)),
+            "broadcast" => sinks.push(Box::new(broadcast.clone())),
+            // Validation guarantees the path is set
+            "file" => {
+                let path = config.ingestion.sink_file.as_deref().unwrap_or_default();
+                sinks.push(Box::new(FileSink::open(path).await?));
+            }
+            other => return Err(SinkError::UnknownSink(other.to_string())),
+        }
+    }
+    Ok(sinks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phoenix_sdk_core::market_event::Fill;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
+
+    fn fill_event() -> SinkEvent {
+        SinkEvent {
+            event: PhoenixEvent {
+                market: Pubkey::new_unique(),
+                sequence_number: 7,
+                slot: 100,
+                timestamp: 1_700_000_000,
+                signature: Signature::new_unique(),
+                signer: Pubkey::new_unique(),
+                trader: Pubkey::new_unique(),
+                event_index: 1,
+                details: MarketEventDetails::Fill(Fill {
+                    order_sequence_number: 42,
+                    maker: Pubkey::new_unique(),
+                    taker: Pubkey::new_unique(),
+                    price_in_ticks: 1000,
+                    base_lots_filled: 5,
+                    base_lots_remaining: 0,
+                    side_filled: phoenix::state::enums::Side::Bid,
+                    is_full_fill: true,
+                }),
+            },
+            metadata: MarketMetadata::default(),
+            commitment: "confirmed",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_sink_appends_one_line_per_event() {
+        let path = std::env::temp_dir().join(format!("events-{}.log", uuid::Uuid::new_v4()));
+        let event = fill_event();
+        {
+            let mut sink = FileSink::open(&path).await.unwrap();
+            sink.write(&event).await.unwrap();
+            sink.write(&event).await.unwrap();
+            sink.file.flush().await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let line: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(line["commitment"], "confirmed");
+        assert_eq!(
+            line["event"]["signature"],
+            event.event.signature.to_string()
+        );
+        assert_eq!(line["event"]["details"]["fill"]["base_lots_filled"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_sink_delivers_to_subscribers() {
+        let mut sink = BroadcastSink::new(16);
+        // Writing without subscribers is not an error
+        sink.write(&fill_event()).await.unwrap();
+
+        let mut receiver = sink.clone().subscribe();
+        let event = fill_event();
+        sink.write(&event).await.unwrap();
+        let received = receiver.recv().await.unwrap();
+        assert_eq!(received.event.signature, event.event.signature);
+    }
+}
@@ -0,0 +1,273 @@
+// snapshot.rs
+use clickhouse::Client;
+use phoenix::quantities::WrapperU64;
+use phoenix::state::markets::FIFOOrderId;
+use phoenix_sdk::error::PhoenixSdkError;
+use phoenix_sdk::orderbook_replay::L3Orderbook;
+use phoenix_sdk::sdk_client::SDKClient;
+use phoenix_sdk_core::sdk_client_core::PhoenixOrder;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::database::insert_orderbook_snapshot;
+use crate::metrics::metrics;
+use crate::rpc::RpcPool;
+use crate::solana::load_sdk_client;
+
+/// The orders resting at one price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Level {
+    pub price_in_ticks: u64,
+    pub base_lots: u64,
+    pub orders: u32,
+}
+
+/// What is stored of an orderbook besides its resting orders. Prices are in ticks and sizes in
+/// base lots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookSummary {
+    /// The best levels of each side, best first.
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+    /// `None`, like the spread, unless both sides have orders.
+    pub mid_price_in_ticks: Option<f64>,
+    pub spread_in_ticks: Option<u64>,
+    pub spread_bps: Option<f64>,
+    /// Base lots resting within each of `depth_bps` of the mid, on each side. Empty without a
+    /// mid.
+    pub bid_depth: Vec<u64>,
+    pub ask_depth: Vec<u64>,
+}
+
+/// Aggregates the best `levels` price levels of one side of the book, given its orders best
+/// first.
+fn aggregate_levels(orders: &[(FIFOOrderId, PhoenixOrder)], levels: usize) -> Vec<Level> {
+    let mut aggregated: Vec<Level> = vec![];
+    for (order_id, order) in orders {
+        let price_in_ticks = order_id.price_in_ticks.as_u64();
+        match aggregated.last_mut() {
+            Some(level) if level.price_in_ticks == price_in_ticks => {
+                level.base_lots += order.num_base_lots;
+                level.orders += 1;
+            }
+            _ if aggregated.len() == levels => break,
+            _ => aggregated.push(Level {
+                price_in_ticks,
+                base_lots: order.num_base_lots,
+                orders: 1,
+            }),
+        }
+    }
+    aggregated
+}
+
+/// Sums the base lots of `orders`, best first, until one is priced outside `within`.
+fn depth(orders: &[(FIFOOrderId, PhoenixOrder)], within: impl Fn(f64) -> bool) -> u64 {
+    orders
+        .iter()
+        .take_while(|(order_id, _)| within(order_id.price_in_ticks.as_u64() as f64))
+        .map(|(_, order)| order.num_base_lots)
+        .sum()
+}
+
+pub fn summarize(orderbook: &L3Orderbook, levels: usize, depth_bps: &[u32]) -> BookSummary {
+    let bid_orders = orderbook.get_bids();
+    let ask_orders = orderbook.get_asks();
+    let bids = aggregate_levels(&bid_orders, levels);
+    let asks = aggregate_levels(&ask_orders, levels);
+
+    let mut summary = BookSummary {
+        bids,
+        asks,
+        mid_price_in_ticks: None,
+        spread_in_ticks: None,
+        spread_bps: None,
+        bid_depth: vec![],
+        ask_depth: vec![],
+    };
+    let (Some(best_bid), Some(best_ask)) = (summary.bids.first(), summary.asks.first()) else {
+        return summary;
+    };
+    let mid = (best_bid.price_in_ticks as f64 + best_ask.price_in_ticks as f64) / 2.0;
+    let spread = best_ask
+        .price_in_ticks
+        .saturating_sub(best_bid.price_in_ticks);
+    summary.mid_price_in_ticks = Some(mid);
+    summary.spread_in_ticks = Some(spread);
+    summary.spread_bps = Some(spread as f64 / mid * 10_000.0);
+    for &bps in depth_bps {
+        let distance = mid * bps as f64 / 10_000.0;
+        summary
+            .bid_depth
+            .push(depth(&bid_orders, |price| price >= mid - distance));
+        summary
+            .ask_depth
+            .push(depth(&ask_orders, |price| price <= mid + distance));
+    }
+    summary
+}
+
+/// Stores the orderbook of every tracked market in `orderbook_snapshots` every
+/// `snapshots.interval_secs`, so the book can be looked up at any past time.
+pub async fn run_snapshot_job(rpc_pool: &RpcPool, config: &Config, clickhouse_client: &Client) {
+    if !config.snapshots.enabled {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(config.snapshots.interval_secs));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut sdk_client: Option<SDKClient> = None;
+    loop {
+        interval.tick().await;
+        // The SDK client is bound to a single endpoint, so it is rebuilt against whichever one
+        // is serving after an RPC error
+        let client = match sdk_client.take() {
+            Some(client) => client,
+            None => match load_sdk_client(rpc_pool).await {
+                Ok(client) => client,
+                Err(err) => {
+                    eprintln!("Failed to load markets for orderbook snapshots: {}", err);
+                    continue;
+                }
+            },
+        };
+        let markets = if config.snapshots.markets.is_empty() {
+            client.markets.keys().copied().collect::<Vec<_>>()
+        } else {
+            config
+                .snapshots
+                .markets
+                .iter()
+                .filter_map(|market| Pubkey::from_str(market).ok())
+                .collect()
+        };
+
+        let mut rpc_failed = false;
+        for market in markets.iter() {
+            let outcome = match snapshot_market(&client, config, clickhouse_client, market).await {
+                Ok(()) => "ok",
+                Err(err) => {
+                    eprintln!("Failed to snapshot the orderbook of {}: {}", market, err);
+                    rpc_failed |= matches!(
+                        err.downcast_ref::<PhoenixSdkError>(),
+                        Some(PhoenixSdkError::RpcError(_))
+                    );
+                    "error"
+                }
+            };
+            metrics()
+                .orderbook_snapshots_total
+                .with_label_values(&[outcome])
+                .inc();
+        }
+        if !rpc_failed {
+            sdk_client = Some(client);
+        }
+    }
+}
+
+async fn snapshot_market(
+    sdk_client: &SDKClient,
+    config: &Config,
+    clickhouse_client: &Client,
+    market: &Pubkey,
+) -> anyhow::Result<()> {
+    let snapshot = sdk_client.get_market_orderbook_snapshot(market).await?;
+    let timestamp_ms = chrono::Utc::now().timestamp_millis();
+    let summary = summarize(
+        &snapshot.orderbook,
+        config.snapshots.levels,
+        &config.snapshots.depth_bps,
+    );
+    insert_orderbook_snapshot(
+        clickhouse_client,
+        &snapshot,
+        timestamp_ms,
+        &summary,
+        &config.snapshots.depth_bps,
+        config.snapshots.include_l3,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phoenix::state::enums::Side;
+    use std::collections::BTreeMap;
+
+    fn book(bids: &[(u64, u64)], asks: &[(u64, u64)]) -> L3Orderbook {
+        let mut orderbook = L3Orderbook {
+            raw_base_units_per_base_lot: 0.001,
+            quote_units_per_raw_base_unit_per_tick: 0.01,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        };
+        for (side, orders) in [(Side::Bid, bids), (Side::Ask, asks)] {
+            for (sequence_number, &(price_in_ticks, num_base_lots)) in orders.iter().enumerate() {
+                let sequence_number = sequence_number as u64;
+                let order_id = FIFOOrderId::new_from_untyped(
+                    price_in_ticks,
+                    match side {
+                        Side::Bid => !sequence_number,
+                        Side::Ask => sequence_number,
+                    },
+                );
+                let order = PhoenixOrder {
+                    num_base_lots,
+                    maker_id: Pubkey::new_unique(),
+                };
+                match side {
+                    Side::Bid => orderbook.bids.insert(order_id, order),
+                    Side::Ask => orderbook.asks.insert(order_id, order),
+                };
+            }
+        }
+        orderbook
+    }
+
+    #[test]
+    fn test_summarize_aggregates_levels_and_depth() {
+        let orderbook = book(
+            &[(9_990, 5), (9_990, 3), (9_950, 10), (9_000, 100)],
+            &[(10_010, 2), (10_020, 4), (10_020, 1), (11_000, 50)],
+        );
+        let summary = summarize(&orderbook, 2, &[10, 50]);
+
+        assert_eq!(
+            summary.bids,
+            vec![
+                Level {
+                    price_in_ticks: 9_990,
+                    base_lots: 8,
+                    orders: 2
+                },
+                Level {
+                    price_in_ticks: 9_950,
+                    base_lots: 10,
+                    orders: 1
+                },
+            ]
+        );
+        assert_eq!(summary.asks.len(), 2);
+        assert_eq!(summary.asks[1].base_lots, 5);
+        assert_eq!(summary.mid_price_in_ticks, Some(10_000.0));
+        assert_eq!(summary.spread_in_ticks, Some(20));
+        assert_eq!(summary.spread_bps, Some(20.0));
+        // 10 bps of 10,000 ticks reaches 9,990 and 10,010; 50 bps reaches 9,950 and 10,050,
+        // beyond the stored levels but not the orders at 9,000 and 11,000
+        assert_eq!(summary.bid_depth, vec![8, 18]);
+        assert_eq!(summary.ask_depth, vec![2, 7]);
+    }
+
+    #[test]
+    fn test_summarize_one_sided_book() {
+        let summary = summarize(&book(&[(9_990, 5)], &[]), 20, &[10]);
+        assert_eq!(summary.bids.len(), 1);
+        assert!(summary.asks.is_empty());
+        assert_eq!(summary.mid_price_in_ticks, None);
+        assert_eq!(summary.spread_bps, None);
+        assert!(summary.bid_depth.is_empty());
+    }
+}
@@ -3,15 +3,23 @@ use anyhow::Result;
 use ellipsis_client::{EllipsisClient, EllipsisClientError};
 use solana_sdk::signature::Keypair;
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
-use std::env;
 use std::fmt::Debug;
 use std::str::FromStr;
 use thiserror::Error;
 
-use crate::database::insert_fill_event;
-use solana_client::nonblocking::rpc_client::RpcClient as NonblockingRpcClient;
-use solana_client::rpc_client::RpcClient as BlockingRpcClient;
-
+use crate::database::DatabaseError;
+use crate::dead_letter::FailureStage;
+use crate::metrics::metrics;
+use crate::pipeline::{transaction_context, Pipeline};
+use crate::rpc::{commitment_label, history_commitment, RpcPool, RpcPoolError};
+use crate::sink::{ClickHouseSink, SinkError};
+use crate::source::RawTransaction;
+use clickhouse::Client;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_transaction_status::UiTransactionEncoding;
+
+use phoenix_sdk::error::PhoenixSdkError;
 use phoenix_sdk::sdk_client::SDKClient;
 
 pub use phoenix_sdk_core::{
@@ -46,87 +54,201 @@ pub enum FetchError {
     #[error("Failed to create EllipsisClient")]
     EllipsisClientError(#[from] EllipsisClientError),
 
-    #[error("Failed to create SDKClient")]
-    SDKClientError(#[from] anyhow::Error),
+    #[error("Phoenix SDK error: {0}")]
+    SDKClientError(#[from] PhoenixSdkError),
 
     // Errors related to database insertion
-    #[error("Error inserting fill event into database")]
-    InsertionError,
+    #[error("Error inserting fill event into database: {0}")]
+    InsertionError(String),
 
     // Errors during RPC client initialization
     #[error("Error initializing RPC client")]
     RPCInitializationError,
+
+    // Errors returned once every RPC endpoint has been tried
+    #[error("RPC request failed: {0}")]
+    RpcError(#[from] RpcPoolError),
+
+    // Errors decoding Phoenix events from a fetched transaction
+    #[error("Failed to parse Phoenix events from transaction {0}")]
+    EventParseError(String),
+
+    // Errors fetching the metadata needed to store an event
+    #[error("Failed to fetch metadata for market {0}")]
+    MarketMetadataError(String),
+
+    // Errors reading or writing the dead-letter store
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] DatabaseError),
+
+    // Errors on a streaming connection (PubSub WebSocket or Geyser)
+    #[error("Stream error: {0}")]
+    StreamError(String),
+
+    // Errors reading recorded transactions or market headers
+    #[error("Invalid fixture: {0}")]
+    FixtureError(String),
+
+    // Errors setting up an event sink
+    #[error("Failed to set up event sink: {0}")]
+    SinkError(#[from] SinkError),
 }
 // AI Generated Error Handling -- End
 
-
-pub async fn fetch_and_parse_transactions(
-    client: &BlockingRpcClient,
-    address: &str,
-) -> Result<(), FetchError> {
-    let pubkey =
-        Pubkey::from_str(address).map_err(|_| FetchError::InvalidPubkey(address.to_string()))?;
-
-    // Fetch signatures for the given address
-    let signatures = client.get_signatures_for_address(&pubkey).map_err(|e| {
-        FetchError::FetchSignaturesError(format!("Error fetching signatures: {:?}", e))
-    })?;
-    for signature_infox in signatures {
-        // for signature_info in signature_infox {
-        let signature_str = signature_infox.signature;
-
-        // Convert signature string to a Signature object
-        let signature = Signature::from_str(&signature_str)
-            .map_err(|_| FetchError::InvalidSignature(signature_str.clone()))?;
-
-        if let Err(err) = parse_fills(&signature).await {
-            // Handle the specific error or propagate it
-            match err {
-                FetchError::InvalidSignature(_) => eprintln!("Invalid signature: {:?}", err),
-                FetchError::InsertionError => eprintln!("Database insertion failed: {:?}", err),
-                _ => eprintln!("An error occurred: {:?}", err),
-            }
+impl FetchError {
+    /// The ingestion stage a failed signature is recorded under in the dead-letter store.
+    pub fn stage(&self) -> FailureStage {
+        match self {
+            FetchError::FetchSignaturesError(_)
+            | FetchError::RpcError(_)
+            | FetchError::StreamError(_) => FailureStage::Fetch,
+            FetchError::InsertionError(_) | FetchError::DatabaseError(_) => FailureStage::Insert,
+            _ => FailureStage::Parse,
         }
     }
-    Ok(()) // Return Ok(()) if everything succeeds
 }
 
-pub async fn parse_fills(signature: &Signature) -> Result<(), FetchError> {
-    let phoenix_keypair: Keypair = Keypair::new();
-
-    let helius_api_key =
-        env::var("HELIUS_API_KEY").map_err(|_| FetchError::RPCInitializationError)?;
+/// Fetches the program's signatures, oldest first, and updates the ingestion lag.
+///
+/// With `until`, pages back through history until that signature is reached, so a gap (e.g.
+/// while a stream was down) is filled completely; without it, only the latest `batch_size`
+/// signatures are returned.
+pub async fn fetch_signatures(
+    rpc_pool: &RpcPool,
+    program_id: &str,
+    batch_size: usize,
+    until: Option<Signature>,
+) -> Result<Vec<(Signature, u64)>, FetchError> {
+    let pubkey = Pubkey::from_str(program_id)
+        .map_err(|_| FetchError::InvalidPubkey(program_id.to_string()))?;
+    let commitment = history_commitment(rpc_pool.commitment());
+
+    // Fetch signatures for the given address, newest first
+    let mut signatures = vec![];
+    let mut before = None;
+    loop {
+        let page = rpc_pool
+            .call(|client| async move {
+                client
+                    .get_signatures_for_address_with_config(
+                        &pubkey,
+                        GetConfirmedSignaturesForAddress2Config {
+                            before,
+                            until,
+                            limit: Some(batch_size),
+                            commitment: Some(commitment),
+                        },
+                    )
+                    .await
+            })
+            .await
+            .map_err(|e| {
+                FetchError::FetchSignaturesError(format!("Error fetching signatures: {:?}", e))
+            })?;
+        let full_page = page.len() == batch_size;
+        signatures.extend(page);
+        if until.is_none() || !full_page {
+            break;
+        }
+        before = match signatures.last() {
+            Some(oldest) => Some(
+                Signature::from_str(&oldest.signature)
+                    .map_err(|_| FetchError::InvalidSignature(oldest.signature.clone()))?,
+            ),
+            None => break,
+        };
+    }
 
-    let url_prefix = String::from("https://rpc.helius.xyz/?api-key=");
-    let rpc_url: String = format!("{}{}", url_prefix, helius_api_key);
+    // Signatures are returned newest first, so the first one tells us how far behind the tip we are
+    let tip = rpc_pool
+        .call(|client| async move { client.get_slot().await })
+        .await;
+    if let (Some(newest), Ok(tip)) = (signatures.first(), tip) {
+        metrics()
+            .ingestion_lag_slots
+            .set(tip.saturating_sub(newest.slot) as i64);
+    }
 
-    let client = NonblockingRpcClient::new(rpc_url.to_string());
+    signatures
+        .into_iter()
+        .rev()
+        .map(|info| {
+            // Convert signature string to a Signature object
+            let signature = Signature::from_str(&info.signature)
+                .map_err(|_| FetchError::InvalidSignature(info.signature.clone()))?;
+            Ok((signature, info.slot))
+        })
+        .collect()
+}
 
-    let client = EllipsisClient::from_rpc(client, &phoenix_keypair)
-        .map_err(|_| FetchError::ClientInitializationError)?;
-    let sdk_client: SDKClient = SDKClient::new_from_ellipsis_client_with_all_markets(client)
+/// Fetches a transaction by signature, at the pool's commitment (or `confirmed`, for
+/// `processed`).
+pub async fn fetch_transaction(
+    signature: &Signature,
+    rpc_pool: &RpcPool,
+) -> Result<RawTransaction, FetchError> {
+    // Fetch the transaction through the pool so RPC failures are retried instead of being
+    // mistaken for a transaction without events
+    let commitment = history_commitment(rpc_pool.commitment());
+    Ok(rpc_pool
+        .call(|rpc| async move {
+            rpc.get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: Some(commitment),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await
+        })
         .await
-        .map_err(|_| FetchError::SDKClientInitializationError)?;
+        .map_err(|err| {
+            metrics()
+                .parse_failures_total
+                .with_label_values(&["fetch"])
+                .inc();
+            err
+        })?)
+}
 
-    let events = sdk_client
-        .parse_events_from_transaction(signature)
-        .await
-        .unwrap_or_default();
-
-    for event in events {
-        // Filter only Fill events
-        if let MarketEventDetails::Fill(..) = event.details {
-            // Fetch market metadata for the event's market
-            let market_metadata = sdk_client
-                .get_market_metadata(&event.market)
-                .await
-                .unwrap_or_default();
-
-            // Insert the event
-            if let Err(err) = insert_fill_event(event, market_metadata).await {
-                eprintln!("Failed to insert event: {:?}", err);
-            }
-        }
-    }
-    Ok(())
+/// Fetches a transaction and runs it through `pipeline`, outside the ingestion source. Used to
+/// replay dead-lettered signatures.
+pub async fn parse_fills(
+    signature: &Signature,
+    rpc_pool: &RpcPool,
+    pipeline: &mut Pipeline,
+) -> Result<(), FetchError> {
+    let tx = fetch_transaction(signature, rpc_pool).await?;
+    let context = transaction_context(&tx);
+    let tx = ellipsis_transaction_utils::parse_transaction(tx);
+    let commitment = commitment_label(history_commitment(rpc_pool.commitment()));
+    pipeline.process(&tx, context.as_ref(), commitment).await
+}
+
+/// A pipeline that writes replayed transactions to ClickHouse. Loading every market is
+/// expensive, so it is built once and reused for a whole batch of replays.
+pub async fn replay_pipeline(
+    rpc_pool: &RpcPool,
+    clickhouse_client: &Client,
+) -> Result<Pipeline, FetchError> {
+    let sdk_client = load_sdk_client(rpc_pool).await?;
+    Ok(Pipeline::new(
+        sdk_client,
+        vec![Box::new(ClickHouseSink::new(clickhouse_client.clone()))],
+    ))
+}
+
+/// Builds an SDK client with every Phoenix market loaded.
+pub async fn load_sdk_client(rpc_pool: &RpcPool) -> Result<SDKClient, FetchError> {
+    // The SDK client owns its RPC client, so build one against whichever endpoint is serving
+    Ok(rpc_pool
+        .call(|rpc| async move {
+            let phoenix_keypair: Keypair = Keypair::new();
+            let client =
+                EllipsisClient::from_rpc(rpc_pool.detached_client(&rpc), &phoenix_keypair)
+                    .map_err(PhoenixSdkError::rpc)?;
+            SDKClient::new_from_ellipsis_client_with_all_markets(client).await
+        })
+        .await?)
 }
@@ -0,0 +1,104 @@
+// source.rs
+use async_trait::async_trait;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::rpc::{history_commitment, RpcPool};
+use crate::solana::{fetch_signatures, fetch_transaction, FetchError};
+
+/// A transaction as returned by `getTransaction`, which every source converts to.
+pub type RawTransaction = EncodedConfirmedTransactionWithStatusMeta;
+
+pub enum SourceItem {
+    Transaction(Box<RawTransaction>),
+    /// A transaction the source knows about but could not fetch.
+    Failed {
+        signature: Signature,
+        slot: u64,
+        error: FetchError,
+    },
+    /// The source (re)connected; transactions since the last one it yielded may have been
+    /// missed and should be backfilled.
+    Gap,
+}
+
+impl SourceItem {
+    pub fn from_fetch(
+        signature: Signature,
+        slot: u64,
+        result: Result<RawTransaction, FetchError>,
+    ) -> Self {
+        match result {
+            Ok(tx) => SourceItem::Transaction(Box::new(tx)),
+            Err(error) => SourceItem::Failed {
+                signature,
+                slot,
+                error,
+            },
+        }
+    }
+}
+
+/// A feed of raw Phoenix transactions, consumed by the ingestion pipeline.
+#[async_trait]
+pub trait IngestSource: Send {
+    /// Commitment level the transactions are delivered at.
+    fn commitment(&self) -> CommitmentConfig;
+
+    /// Waits for the next item; `None` once the source is exhausted.
+    async fn next(&mut self) -> Result<Option<SourceItem>, FetchError>;
+}
+
+/// Polls `getSignaturesForAddress` once and yields the transactions, oldest first.
+///
+/// With `until`, pages back through history to that signature; otherwise only the latest
+/// `ingestion.signature_batch_size` transactions are yielded.
+pub struct RpcSource {
+    rpc_pool: Arc<RpcPool>,
+    program_id: String,
+    batch_size: usize,
+    until: Option<Signature>,
+    /// Signatures left to fetch; `None` until the first poll.
+    pending: Option<VecDeque<(Signature, u64)>>,
+}
+
+impl RpcSource {
+    pub fn new(rpc_pool: Arc<RpcPool>, config: &Config, until: Option<Signature>) -> Self {
+        RpcSource {
+            rpc_pool,
+            program_id: config.ingestion.program_id.clone(),
+            batch_size: config.ingestion.signature_batch_size,
+            until,
+            pending: None,
+        }
+    }
+}
+
+#[async_trait]
+impl IngestSource for RpcSource {
+    fn commitment(&self) -> CommitmentConfig {
+        history_commitment(self.rpc_pool.commitment())
+    }
+
+    async fn next(&mut self) -> Result<Option<SourceItem>, FetchError> {
+        if self.pending.is_none() {
+            let signatures = fetch_signatures(
+                &self.rpc_pool,
+                &self.program_id,
+                self.batch_size,
+                self.until,
+            )
+            .await?;
+            self.pending = Some(signatures.into());
+        }
+        let Some((signature, slot)) = self.pending.as_mut().and_then(|p| p.pop_front()) else {
+            return Ok(None);
+        };
+        let result = fetch_transaction(&signature, &self.rpc_pool).await;
+        Ok(Some(SourceItem::from_fetch(signature, slot, result)))
+    }
+}
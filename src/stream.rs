@@ -0,0 +1,264 @@
+// stream.rs
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::Config;
+use crate::metrics::metrics;
+use crate::rpc::{history_commitment, redact_url, RetryPolicy, RpcPool};
+use crate::solana::{fetch_transaction, FetchError};
+use crate::source::{IngestSource, SourceItem};
+
+/// Notifications buffered between the WebSocket and the pipeline.
+const NOTIFICATION_BUFFER: usize = 1024;
+
+/// Signatures and slots of the transactions notified over one connection.
+type NotificationStream = BoxStream<'static, (Signature, u64)>;
+
+/// Streams Phoenix transactions as they land via `logsSubscribe`, fetching each one by
+/// signature.
+///
+/// The subscription reconnects with backoff and yields a [`SourceItem::Gap`] after every
+/// (re)connection, so nothing sent while it was down is lost.
+pub struct LogsSource {
+    rpc_pool: Arc<RpcPool>,
+    ws_url: String,
+    program_id: String,
+    reconnect: RetryPolicy,
+    attempt: u32,
+    /// Connections handed out in order instead of subscribing, for a source built with
+    /// [`LogsSource::from_streams`]; it ends once they are used up.
+    connections: Option<VecDeque<Result<NotificationStream, FetchError>>>,
+    /// Signatures and slots forwarded by the subscription task, while connected.
+    notifications: Option<NotificationStream>,
+}
+
+impl LogsSource {
+    pub fn new(rpc_pool: Arc<RpcPool>, config: &Config) -> Result<Self, FetchError> {
+        let ws_url = config.rpc.pubsub_url().ok_or_else(|| {
+            FetchError::StreamError("no PubSub WebSocket URL configured".to_string())
+        })?;
+        Ok(LogsSource {
+            rpc_pool,
+            ws_url,
+            program_id: config.ingestion.program_id.clone(),
+            reconnect: RetryPolicy {
+                max_retries: u32::MAX,
+                base: Duration::from_millis(config.rpc.backoff_base_ms),
+                max: Duration::from_millis(config.rpc.backoff_max_ms),
+            },
+            attempt: 0,
+            connections: None,
+            notifications: None,
+        })
+    }
+
+    /// A source that takes its notifications from `connections` instead of a WebSocket, one
+    /// per (re)connection. An `Err` is a connection that fails.
+    #[cfg(test)]
+    pub fn from_streams(
+        rpc_pool: Arc<RpcPool>,
+        connections: Vec<Result<NotificationStream, FetchError>>,
+    ) -> Self {
+        LogsSource {
+            rpc_pool,
+            ws_url: String::new(),
+            program_id: phoenix::id().to_string(),
+            reconnect: RetryPolicy {
+                max_retries: 0,
+                base: Duration::ZERO,
+                max: Duration::ZERO,
+            },
+            attempt: 0,
+            connections: Some(connections.into()),
+            notifications: None,
+        }
+    }
+
+    /// Subscribes on a task of its own, which owns the PubSub client and forwards notifications
+    /// until the connection drops or the source is dropped.
+    async fn connect(&self) -> Result<NotificationStream, FetchError> {
+        let pubsub_client = PubsubClient::new(&self.ws_url)
+            .await
+            .map_err(|err| FetchError::StreamError(err.to_string()))?;
+        let filter = RpcTransactionLogsFilter::Mentions(vec![self.program_id.clone()]);
+        let logs_config = RpcTransactionLogsConfig {
+            // getTransaction cannot see anything below confirmed
+            commitment: Some(history_commitment(self.rpc_pool.commitment())),
+        };
+        let (sender, receiver) = mpsc::channel(NOTIFICATION_BUFFER);
+        let (subscribed, subscription) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (mut notifications, unsubscribe) =
+                match pubsub_client.logs_subscribe(filter, logs_config).await {
+                    Ok(subscription) => {
+                        let _ = subscribed.send(Ok(()));
+                        subscription
+                    }
+                    Err(err) => {
+                        let _ = subscribed.send(Err(err.to_string()));
+                        return;
+                    }
+                };
+            while let Some(notification) = notifications.next().await {
+                let Ok(signature) = Signature::from_str(&notification.value.signature) else {
+                    eprintln!(
+                        "Invalid signature in log notification: {}",
+                        notification.value.signature
+                    );
+                    continue;
+                };
+                if sender
+                    .send((signature, notification.context.slot))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            unsubscribe().await;
+        });
+
+        subscription
+            .await
+            .map_err(|_| FetchError::StreamError("subscription task exited".to_string()))?
+            .map_err(FetchError::StreamError)?;
+        Ok(
+            futures::stream::unfold(receiver, |mut receiver| async move {
+                let notification = receiver.recv().await?;
+                Some((notification, receiver))
+            })
+            .boxed(),
+        )
+    }
+
+    async fn wait_to_reconnect(&mut self) {
+        // Nothing is being ingested until the stream is back and the gap is filled
+        metrics().ingestion_lag_slots.set(-1);
+        let delay = self.reconnect.backoff(self.attempt);
+        eprintln!("Reconnecting to the log stream in {:?}", delay);
+        tokio::time::sleep(delay).await;
+        self.attempt = self.attempt.saturating_add(1);
+    }
+}
+
+#[async_trait]
+impl IngestSource for LogsSource {
+    fn commitment(&self) -> CommitmentConfig {
+        history_commitment(self.rpc_pool.commitment())
+    }
+
+    async fn next(&mut self) -> Result<Option<SourceItem>, FetchError> {
+        loop {
+            let Some(notifications) = self.notifications.as_mut() else {
+                let connection = match self.connections.as_mut() {
+                    Some(connections) => match connections.pop_front() {
+                        Some(connection) => connection,
+                        None => return Ok(None),
+                    },
+                    None => self.connect().await,
+                };
+                match connection {
+                    Ok(notifications) => {
+                        self.notifications = Some(notifications);
+                        return Ok(Some(SourceItem::Gap));
+                    }
+                    Err(err) => {
+                        eprintln!(
+                            "Log subscription to {} failed: {}",
+                            redact_url(&self.ws_url),
+                            err
+                        );
+                        self.wait_to_reconnect().await;
+                        continue;
+                    }
+                }
+            };
+
+            let notification = notifications.next().await;
+            match notification {
+                Some((signature, slot)) => {
+                    // Back off from scratch after a connection that made progress
+                    self.attempt = 0;
+                    // Notifications arrive as transactions land, so the stream is at the tip
+                    metrics().ingestion_lag_slots.set(0);
+                    let result = fetch_transaction(&signature, &self.rpc_pool).await;
+                    return Ok(Some(SourceItem::from_fetch(signature, slot, result)));
+                }
+                None => {
+                    eprintln!("Log subscription to {} closed", redact_url(&self.ws_url));
+                    self.notifications = None;
+                    self.wait_to_reconnect().await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RpcConfig;
+
+    fn notifications(slots: &[u64]) -> Result<NotificationStream, FetchError> {
+        let notifications = slots
+            .iter()
+            .map(|slot| (Signature::new_unique(), *slot))
+            .collect::<Vec<_>>();
+        Ok(futures::stream::iter(notifications).boxed())
+    }
+
+    fn item_slot(item: SourceItem) -> Option<u64> {
+        match item {
+            SourceItem::Gap => None,
+            SourceItem::Transaction(tx) => Some(tx.slot),
+            // Nothing serves getTransaction here, so every notification fails to fetch
+            SourceItem::Failed { slot, .. } => Some(slot),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_with_a_gap_and_resets_backoff_after_progress() {
+        let rpc_pool = Arc::new(RpcPool::from_config(&RpcConfig {
+            urls: vec!["http://127.0.0.1:1".to_string()],
+            max_retries: 0,
+            ..Default::default()
+        }));
+        let mut source = LogsSource::from_streams(
+            rpc_pool,
+            vec![
+                Err(FetchError::StreamError("connection refused".to_string())),
+                notifications(&[10]),
+                notifications(&[]),
+                notifications(&[20, 21]),
+            ],
+        );
+
+        // The first connection fails, the second one opens with a gap
+        assert_eq!(item_slot(source.next().await.unwrap().unwrap()), None);
+        assert_eq!(source.attempt, 1);
+        assert_eq!(item_slot(source.next().await.unwrap().unwrap()), Some(10));
+        assert_eq!(source.attempt, 0);
+
+        // Two reconnections in a row without progress back off further
+        assert_eq!(item_slot(source.next().await.unwrap().unwrap()), None);
+        assert_eq!(source.attempt, 1);
+        assert_eq!(item_slot(source.next().await.unwrap().unwrap()), None);
+        assert_eq!(source.attempt, 2);
+        assert_eq!(item_slot(source.next().await.unwrap().unwrap()), Some(20));
+        assert_eq!(source.attempt, 0);
+        assert_eq!(item_slot(source.next().await.unwrap().unwrap()), Some(21));
+
+        assert!(source.next().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,627 @@
+// udf.rs
+//! TradingView UDF (Universal Data Feed) endpoints.
+//!
+//! Phoenix markets are exposed as symbols: the ticker is the market address and the name is
+//! `BASE/QUOTE`. Candles are served from the same engine as `/ohlc`, for that market only, and
+//! converted from ticks and base lots to float prices and raw base units using its
+//! `MarketMetadata`.
+use crate::api::AppState;
+use crate::database::{
+    fetch_candles, fetch_last_trade_time_before, fetch_markets, OHLCRow, TradeFilter,
+};
+use crate::error::ApiError;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use phoenix_sdk_core::sdk_client_core::MarketMetadata;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use utoipa::{IntoParams, ToSchema};
+
+const EXCHANGE: &str = "Phoenix";
+
+/// Bars returned at most by `/udf/history` when the library asks for a `countback`.
+const MAX_COUNTBACK: usize = 5000;
+
+const SUPPORTED_RESOLUTIONS: [&str; 8] = ["1", "5", "15", "30", "60", "240", "1D", "1W"];
+
+/// Well-known mints, used to give symbols a readable name. Other mints are named by address.
+const KNOWN_MINTS: [(&str, &str); 6] = [
+    ("So11111111111111111111111111111111111111112", "SOL"),
+    ("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "USDC"),
+    ("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB", "USDT"),
+    ("mSoLzYCxHdYgdzU16g5QSh3i5K3z3KZK7ytfqcJm7So", "mSOL"),
+    ("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263", "BONK"),
+    ("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn", "JitoSOL"),
+];
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/config", get(config_handler))
+        .route("/symbols", get(symbols_handler))
+        .route("/search", get(search_handler))
+        .route("/history", get(history_handler))
+        .route("/time", get(time_handler))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UdfExchange {
+    pub value: String,
+    pub name: String,
+    pub desc: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UdfSymbolType {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UdfConfig {
+    pub supported_resolutions: Vec<String>,
+    pub supports_search: bool,
+    pub supports_group_request: bool,
+    pub supports_marks: bool,
+    pub supports_timescale_marks: bool,
+    pub supports_time: bool,
+    pub exchanges: Vec<UdfExchange>,
+    pub symbols_types: Vec<UdfSymbolType>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UdfSymbolQuery {
+    /// Market address, `BASE/QUOTE` name, or either prefixed with `Phoenix:`.
+    pub symbol: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UdfSymbolInfo {
+    pub name: String,
+    pub ticker: String,
+    pub description: String,
+    #[serde(rename = "type")]
+    pub symbol_type: String,
+    pub session: String,
+    pub exchange: String,
+    pub listed_exchange: String,
+    pub timezone: String,
+    pub minmov: u64,
+    pub pricescale: u64,
+    pub has_intraday: bool,
+    pub has_daily: bool,
+    pub has_weekly_and_monthly: bool,
+    pub supported_resolutions: Vec<String>,
+    pub volume_precision: u32,
+    pub data_status: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UdfSearchQuery {
+    /// Text to match against the symbol name and market address.
+    #[serde(default)]
+    pub query: String,
+    /// Symbol type filter; only `crypto` is served.
+    #[serde(rename = "type", default)]
+    pub symbol_type: String,
+    /// Exchange filter; only `Phoenix` is served.
+    #[serde(default)]
+    pub exchange: String,
+    /// Maximum number of results.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UdfSearchResult {
+    pub symbol: String,
+    pub full_name: String,
+    pub description: String,
+    pub exchange: String,
+    pub ticker: String,
+    #[serde(rename = "type")]
+    pub symbol_type: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UdfHistoryQuery {
+    /// Market address or `BASE/QUOTE` name.
+    pub symbol: String,
+    /// One of the supported resolutions, e.g. `1`, `60` or `1D`.
+    pub resolution: String,
+    /// Start of the range (unix seconds, inclusive).
+    pub from: i64,
+    /// End of the range (unix seconds, inclusive).
+    pub to: i64,
+    /// Number of bars requested, at most 5000; takes precedence over `from` when set.
+    pub countback: Option<usize>,
+}
+
+/// UDF history response. `s` is `ok`, `no_data` or `error`.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct UdfHistory {
+    pub s: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub t: Vec<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub o: Vec<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub h: Vec<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub l: Vec<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub c: Vec<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub v: Vec<f64>,
+    #[serde(rename = "nextTime", skip_serializing_if = "Option::is_none")]
+    pub next_time: Option<i64>,
+}
+
+/// Error payload in the shape the charting library expects.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UdfError {
+    pub s: String,
+    pub errmsg: String,
+}
+
+impl UdfError {
+    fn new(errmsg: impl Into<String>) -> Self {
+        UdfError {
+            s: "error".to_string(),
+            errmsg: errmsg.into(),
+        }
+    }
+
+    fn internal(err: impl std::fmt::Debug) -> Self {
+        eprintln!("UDF request failed: {:?}", err);
+        UdfError::new("Internal server error")
+    }
+}
+
+impl From<ApiError> for UdfError {
+    fn from(err: ApiError) -> Self {
+        UdfError::new(err.to_string())
+    }
+}
+
+impl IntoResponse for UdfError {
+    fn into_response(self) -> Response {
+        // The charting library inspects `s` rather than the HTTP status
+        (StatusCode::OK, Json(self)).into_response()
+    }
+}
+
+/// Start of the range holding the last `countback` bars of `interval_secs` up to `to`, or `from`
+/// if that is earlier.
+fn countback_start(
+    from: i64,
+    to: i64,
+    countback: usize,
+    interval_secs: u64,
+) -> Result<i64, ApiError> {
+    let span = i64::try_from(interval_secs)
+        .ok()
+        .zip(i64::try_from(countback).ok())
+        .and_then(|(interval_secs, countback)| interval_secs.checked_mul(countback))
+        .ok_or(ApiError::InvalidTimeRange {
+            start_time: from,
+            end_time: to,
+        })?;
+    Ok(from.min(to.saturating_sub(span)))
+}
+
+/// Converts a UDF resolution (`1`, `60`, `1D`, `W`, ...) to a bucket length in seconds.
+pub fn resolution_to_seconds(resolution: &str) -> Option<u64> {
+    let resolution = resolution.trim();
+    let (count, unit) = match resolution.char_indices().find(|(_, c)| c.is_alphabetic()) {
+        Some((i, _)) => resolution.split_at(i),
+        None => (resolution, ""),
+    };
+    let count = if count.is_empty() {
+        1
+    } else {
+        count.parse::<u64>().ok()?
+    };
+    let unit_secs = match unit {
+        "" => 60,
+        "S" => 1,
+        "D" => 86400,
+        "W" => 7 * 86400,
+        _ => return None,
+    };
+    (count > 0).then(|| count * unit_secs)
+}
+
+fn mint_symbol(mint: &Pubkey) -> String {
+    let mint = mint.to_string();
+    KNOWN_MINTS
+        .iter()
+        .find(|(address, _)| *address == mint)
+        .map(|(_, symbol)| symbol.to_string())
+        .unwrap_or(mint)
+}
+
+fn symbol_name(metadata: &MarketMetadata) -> String {
+    format!(
+        "{}/{}",
+        mint_symbol(&metadata.base_mint),
+        mint_symbol(&metadata.quote_mint)
+    )
+}
+
+/// Smallest power of ten that makes the market's tick size an integer, capped at 10^12.
+pub fn pricescale(metadata: &MarketMetadata) -> u64 {
+    let tick = metadata.quote_units_per_raw_base_unit_per_tick();
+    let mut scale = 1u64;
+    while scale < 1_000_000_000_000 {
+        let scaled = tick * scale as f64;
+        if (scaled - scaled.round()).abs() < 1e-9 {
+            break;
+        }
+        scale *= 10;
+    }
+    scale
+}
+
+/// Resolves a market address, or a `BASE/QUOTE` name that only one market trades under.
+fn find_market<'a>(
+    markets: &'a [(Pubkey, MarketMetadata)],
+    symbol: &str,
+) -> Result<&'a (Pubkey, MarketMetadata), UdfError> {
+    let name = symbol
+        .strip_prefix(&format!("{}:", EXCHANGE))
+        .unwrap_or(symbol);
+    if let Some(market) = markets
+        .iter()
+        .find(|(market, _)| market.to_string() == name)
+    {
+        return Ok(market);
+    }
+    let mut matches = markets
+        .iter()
+        .filter(|(_, metadata)| symbol_name(metadata).eq_ignore_ascii_case(name));
+    match (matches.next(), matches.next()) {
+        (Some(market), None) => Ok(market),
+        (Some(_), Some(_)) => Err(UdfError::new(format!(
+            "Ambiguous symbol: several markets trade {}, use the market address",
+            name
+        ))),
+        (None, _) => Err(UdfError::new(format!("Unknown symbol: {}", symbol))),
+    }
+}
+
+fn symbol_info(market: &Pubkey, metadata: &MarketMetadata) -> UdfSymbolInfo {
+    let name = symbol_name(metadata);
+    UdfSymbolInfo {
+        description: format!("{} ({})", name, market),
+        name,
+        ticker: market.to_string(),
+        symbol_type: "crypto".to_string(),
+        session: "24x7".to_string(),
+        exchange: EXCHANGE.to_string(),
+        listed_exchange: EXCHANGE.to_string(),
+        timezone: "Etc/UTC".to_string(),
+        minmov: 1,
+        pricescale: pricescale(metadata),
+        has_intraday: true,
+        has_daily: true,
+        has_weekly_and_monthly: true,
+        supported_resolutions: SUPPORTED_RESOLUTIONS
+            .iter()
+            .map(|r| r.to_string())
+            .collect(),
+        volume_precision: metadata.base_decimals,
+        data_status: "streaming".to_string(),
+    }
+}
+
+/// Converts candles in ticks and base lots to the float arrays the charting library expects.
+pub fn candles_to_history(candles: &[OHLCRow], metadata: &MarketMetadata) -> UdfHistory {
+    if candles.is_empty() {
+        return UdfHistory {
+            s: "no_data".to_string(),
+            ..Default::default()
+        };
+    }
+    let raw_base_units_per_base_lot = metadata.raw_base_units_per_base_lot();
+    let mut history = UdfHistory {
+        s: "ok".to_string(),
+        ..Default::default()
+    };
+    for candle in candles {
+        history.t.push(candle.time);
+        history.o.push(metadata.ticks_to_float_price(candle.open));
+        history.h.push(metadata.ticks_to_float_price(candle.high));
+        history.l.push(metadata.ticks_to_float_price(candle.low));
+        history.c.push(metadata.ticks_to_float_price(candle.close));
+        history
+            .v
+            .push(candle.volume as f64 * raw_base_units_per_base_lot);
+    }
+    history
+}
+
+/// Datafeed configuration.
+#[utoipa::path(
+    get,
+    path = "/udf/config",
+    responses((status = 200, description = "Datafeed configuration", body = UdfConfig))
+)]
+pub(crate) async fn config_handler() -> Json<UdfConfig> {
+    Json(UdfConfig {
+        supported_resolutions: SUPPORTED_RESOLUTIONS
+            .iter()
+            .map(|r| r.to_string())
+            .collect(),
+        supports_search: true,
+        supports_group_request: false,
+        supports_marks: false,
+        supports_timescale_marks: false,
+        supports_time: true,
+        exchanges: vec![UdfExchange {
+            value: EXCHANGE.to_string(),
+            name: EXCHANGE.to_string(),
+            desc: "Phoenix on-chain order book".to_string(),
+        }],
+        symbols_types: vec![UdfSymbolType {
+            name: "crypto".to_string(),
+            value: "crypto".to_string(),
+        }],
+    })
+}
+
+/// Resolve a single symbol.
+#[utoipa::path(
+    get,
+    path = "/udf/symbols",
+    params(UdfSymbolQuery),
+    responses(
+        (status = 200, description = "Symbol information, or a UDF error if unknown", body = UdfSymbolInfo),
+    )
+)]
+pub(crate) async fn symbols_handler(
+    Query(params): Query<UdfSymbolQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<UdfSymbolInfo>, UdfError> {
+    let markets = fetch_markets(&state.clickhouse_client)
+        .await
+        .map_err(UdfError::internal)?;
+    let (market, metadata) = find_market(&markets, &params.symbol)?;
+    Ok(Json(symbol_info(market, metadata)))
+}
+
+/// Search symbols by name or market address.
+#[utoipa::path(
+    get,
+    path = "/udf/search",
+    params(UdfSearchQuery),
+    responses((status = 200, description = "Matching symbols", body = [UdfSearchResult]))
+)]
+pub(crate) async fn search_handler(
+    Query(params): Query<UdfSearchQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<UdfSearchResult>>, UdfError> {
+    if !params.exchange.is_empty() && params.exchange != EXCHANGE {
+        return Ok(Json(vec![]));
+    }
+    if !params.symbol_type.is_empty() && params.symbol_type != "crypto" {
+        return Ok(Json(vec![]));
+    }
+
+    let markets = fetch_markets(&state.clickhouse_client)
+        .await
+        .map_err(UdfError::internal)?;
+    let needle = params.query.to_uppercase();
+    let results = markets
+        .iter()
+        .filter(|(market, metadata)| {
+            symbol_name(metadata).to_uppercase().contains(&needle)
+                || market.to_string().to_uppercase().contains(&needle)
+        })
+        .take(params.limit.unwrap_or(30))
+        .map(|(market, metadata)| {
+            let name = symbol_name(metadata);
+            UdfSearchResult {
+                symbol: name.clone(),
+                full_name: format!("{}:{}", EXCHANGE, name),
+                description: format!("{} ({})", name, market),
+                exchange: EXCHANGE.to_string(),
+                ticker: market.to_string(),
+                symbol_type: "crypto".to_string(),
+            }
+        })
+        .collect();
+    Ok(Json(results))
+}
+
+/// Bars for a symbol, served from the same candle engine as `/ohlc`.
+#[utoipa::path(
+    get,
+    path = "/udf/history",
+    params(UdfHistoryQuery),
+    responses((status = 200, description = "Bars in UDF format", body = UdfHistory))
+)]
+pub(crate) async fn history_handler(
+    Query(params): Query<UdfHistoryQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<UdfHistory>, UdfError> {
+    if params.from > params.to {
+        return Err(UdfError::new("from must not be greater than to"));
+    }
+    let interval_secs = resolution_to_seconds(&params.resolution)
+        .ok_or_else(|| UdfError::new(format!("Unsupported resolution: {}", params.resolution)))?;
+
+    let markets = fetch_markets(&state.clickhouse_client)
+        .await
+        .map_err(UdfError::internal)?;
+    let (market, metadata) = find_market(&markets, &params.symbol)?;
+    // Other markets on the pair have their own tick and lot sizes
+    let market = market.to_string();
+    let filter = TradeFilter::Market(&market);
+
+    // With countback the library wants the last N bars up to `to`, regardless of `from`
+    let countback = params
+        .countback
+        .map(|countback| countback.min(MAX_COUNTBACK));
+    let from = match countback {
+        Some(countback) => countback_start(params.from, params.to, countback, interval_secs)?,
+        None => params.from,
+    };
+    let mut candles = fetch_candles(
+        &state.clickhouse_client,
+        filter,
+        from,
+        params.to,
+        interval_secs,
+    )
+    .await
+    .map_err(UdfError::internal)?;
+    if let Some(countback) = countback {
+        let excess = candles.len().saturating_sub(countback);
+        candles.drain(..excess);
+    }
+
+    let mut history = candles_to_history(&candles, metadata);
+    if candles.is_empty() {
+        history.next_time = fetch_last_trade_time_before(&state.clickhouse_client, filter, from)
+            .await
+            .map_err(UdfError::internal)?;
+    }
+    Ok(Json(history))
+}
+
+/// Current server time in unix seconds.
+#[utoipa::path(
+    get,
+    path = "/udf/time",
+    responses((status = 200, description = "Server time in unix seconds", body = String))
+)]
+pub(crate) async fn time_handler() -> String {
+    chrono::Utc::now().timestamp().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use phoenix::program::MarketSizeParams;
+    use std::str::FromStr;
+
+    fn sol_usdc() -> MarketMetadata {
+        MarketMetadata {
+            base_mint: Pubkey::from_str(KNOWN_MINTS[0].0).unwrap(),
+            quote_mint: Pubkey::from_str(KNOWN_MINTS[1].0).unwrap(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_atoms_per_raw_base_unit: 1e9 as u64,
+            quote_atoms_per_quote_unit: 1e6 as u64,
+            quote_atoms_per_quote_lot: 1,
+            base_atoms_per_base_lot: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1000,
+            num_base_lots_per_base_unit: 1000,
+            raw_base_units_per_base_unit: 1,
+            market_size_params: MarketSizeParams::default(),
+        }
+    }
+
+    #[test]
+    fn test_resolution_to_seconds() {
+        assert_eq!(resolution_to_seconds("1"), Some(60));
+        assert_eq!(resolution_to_seconds("60"), Some(3600));
+        assert_eq!(resolution_to_seconds("1D"), Some(86400));
+        assert_eq!(resolution_to_seconds("D"), Some(86400));
+        assert_eq!(resolution_to_seconds("1W"), Some(604800));
+        assert_eq!(resolution_to_seconds("0"), None);
+        assert_eq!(resolution_to_seconds("1M"), None);
+        assert_eq!(resolution_to_seconds("abc"), None);
+        for resolution in SUPPORTED_RESOLUTIONS {
+            assert!(resolution_to_seconds(resolution).is_some());
+        }
+    }
+
+    #[test]
+    fn test_countback_start() {
+        assert_eq!(countback_start(1_000, 7_200, 10, 60).unwrap(), 6_600);
+        // `from` wins when it is earlier
+        assert_eq!(countback_start(0, 7_200, 10, 60).unwrap(), 0);
+        // The handler caps `countback=usize::MAX` before computing the range
+        let capped = usize::MAX.min(MAX_COUNTBACK);
+        assert_eq!(
+            countback_start(1_700_000_000, 1_700_000_000, capped, 604_800).unwrap(),
+            1_700_000_000 - 5000 * 604_800
+        );
+        assert_eq!(
+            countback_start(i64::MIN + 10, i64::MIN + 20, capped, 60).unwrap(),
+            i64::MIN
+        );
+        assert!(matches!(
+            countback_start(0, 100, usize::MAX, 60),
+            Err(ApiError::InvalidTimeRange { .. })
+        ));
+        assert!(countback_start(0, 100, 1, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_symbol_lookup() {
+        let market = Pubkey::new_unique();
+        let markets = vec![(market, sol_usdc())];
+
+        assert_eq!(symbol_name(&markets[0].1), "SOL/USDC");
+        assert!(find_market(&markets, "SOL/USDC").is_ok());
+        assert!(find_market(&markets, "Phoenix:sol/usdc").is_ok());
+        assert!(find_market(&markets, &market.to_string()).is_ok());
+        assert!(find_market(&markets, "BONK/USDC").is_err());
+    }
+
+    #[test]
+    fn test_ambiguous_symbols_resolve_only_by_address() {
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut coarse_ticks = sol_usdc();
+        coarse_ticks.tick_size_in_quote_atoms_per_base_unit = 10_000;
+        let markets = vec![(first, sol_usdc()), (second, coarse_ticks)];
+
+        let err = find_market(&markets, "SOL/USDC").unwrap_err();
+        assert!(err.errmsg.starts_with("Ambiguous symbol"));
+        assert_eq!(
+            find_market(&markets, &second.to_string()).unwrap().0,
+            second
+        );
+
+        // Unknown mints are named by their full address, so they cannot collide
+        let mut unknown = sol_usdc();
+        unknown.base_mint = Pubkey::new_unique();
+        assert_eq!(symbol_name(&unknown), format!("{}/USDC", unknown.base_mint));
+    }
+
+    #[test]
+    fn test_candles_to_history() {
+        let metadata = sol_usdc();
+        let candles = vec![OHLCRow {
+            time: 60,
+            open: 20_000,
+            high: 21_000,
+            low: 19_500,
+            close: 20_500,
+            volume: 1500,
+        }];
+        let history = candles_to_history(&candles, &metadata);
+
+        assert_eq!(history.s, "ok");
+        assert_eq!(history.t, vec![60]);
+        assert_eq!(history.o, vec![20.0]);
+        assert_eq!(history.h, vec![21.0]);
+        assert_eq!(history.l, vec![19.5]);
+        assert_eq!(history.c, vec![20.5]);
+        assert_eq!(history.v, vec![1.5]);
+        assert_eq!(pricescale(&metadata), 1000);
+
+        let empty = candles_to_history(&[], &metadata);
+        assert_eq!(empty.s, "no_data");
+        assert!(empty.t.is_empty());
+    }
+}